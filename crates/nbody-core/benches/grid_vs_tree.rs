@@ -0,0 +1,60 @@
+//! Step time of the grid cutoff force method against Barnes-Hut on a
+//! uniform box.
+//!
+//! Run with `cargo bench -p nbody-core --bench grid_vs_tree`. Bodies fill the
+//! unit box uniformly from a fixed seed, and the cutoff gives about
+//! `NEIGHBORS` bodies within range of each. Each time is the median of
+//! several steps.
+
+use std::time::{Duration, Instant};
+use nbody_core::{Body, ForceMethod, Scalar, Simulation};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+const SIZES: [usize; 2] = [10_000, 100_000];
+const NEIGHBORS: Scalar = 8.0;
+const TREE_RATIOS: [Scalar; 2] = [3.0, 0.5];
+const REPEATS: usize = 5;
+
+fn uniform_box(n: usize) -> Vec<Body> {
+    let mut rng = StdRng::seed_from_u64(42);
+    (0..n)
+        .map(|_| {
+            let x: Scalar = rng.gen_range(0.0..1.0);
+            let y: Scalar = rng.gen_range(0.0..1.0);
+            Body::new(1.0 / n as Scalar, x, y, 0.0, 0.0)
+        })
+        .collect()
+}
+
+/// Median time of a step
+fn step_time(mut simulation: Simulation) -> Duration {
+    let mut times: Vec<Duration> = (0..REPEATS)
+        .map(|_| {
+            let start = Instant::now();
+            simulation.step();
+            start.elapsed()
+        })
+        .collect();
+    times.sort();
+    times[REPEATS / 2]
+}
+
+fn main() {
+    println!("{:>8} {:>16} {:>12}", "bodies", "method", "step (ms)");
+    for n in SIZES {
+        let bodies = uniform_box(n);
+        let cutoff = (NEIGHBORS / (std::f64::consts::PI as Scalar * n as Scalar)).sqrt();
+
+        let mut grid = Simulation::new(bodies.clone(), 1e-6, 1.0, 1e-6, 0.5);
+        grid.set_force_method(ForceMethod::GridCutoff { cutoff });
+        let mut rows = vec![("grid cutoff".to_string(), step_time(grid))];
+        for tree_ratio in TREE_RATIOS {
+            let tree = Simulation::new(bodies.clone(), 1e-6, 1.0, 1e-6, tree_ratio);
+            rows.push((format!("tree --tr {}", tree_ratio), step_time(tree)));
+        }
+
+        for (method, time) in rows {
+            println!("{:>8} {:>16} {:>12.1}", n, method, time.as_secs_f64() * 1e3);
+        }
+    }
+}
@@ -0,0 +1,54 @@
+//! Quadtree build time and size over uniform random bodies.
+//!
+//! Run with `cargo bench -p nbody-core --bench tree_build`. The bodies come
+//! from a fixed seed, so node counts are identical from run to run and only
+//! the timings vary; each is the median of several builds.
+
+use std::time::{Duration, Instant};
+use nbody_core::{Body, QuadTree, Scalar, Simulation};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+const SIZES: [usize; 3] = [1_000, 10_000, 100_000];
+const REPEATS: usize = 9;
+
+fn uniform_bodies(n: usize) -> Vec<Body> {
+    let mut rng = StdRng::seed_from_u64(42);
+    (0..n)
+        .map(|_| {
+            let x: Scalar = rng.gen_range(-1.0..1.0);
+            let y: Scalar = rng.gen_range(-1.0..1.0);
+            Body::new(1.0 / n as Scalar, x, y, 0.0, 0.0)
+        })
+        .collect()
+}
+
+fn node_count(tree: &QuadTree) -> usize {
+    1 + tree.get_children().iter().flatten().map(|child| node_count(child)).sum::<usize>()
+}
+
+fn main() {
+    println!("{:>8} {:>12} {:>10} {:>12}", "bodies", "build (ms)", "nodes", "ns/body");
+    for n in SIZES {
+        let simulation = Simulation::new(uniform_bodies(n), 0.01, 1.0, 1e-4, 0.5);
+
+        let mut times: Vec<Duration> = (0..REPEATS)
+            .map(|_| {
+                let start = Instant::now();
+                let tree = simulation.get_tree();
+                let elapsed = start.elapsed();
+                std::hint::black_box(tree);
+                elapsed
+            })
+            .collect();
+        times.sort();
+        let median = times[REPEATS / 2];
+        let nodes = node_count(&simulation.get_tree());
+
+        println!("{:>8} {:>12.3} {:>10} {:>12.1}",
+            n,
+            median.as_secs_f64() * 1e3,
+            nodes,
+            median.as_secs_f64() * 1e9 / n as f64,
+        );
+    }
+}
@@ -0,0 +1,255 @@
+use glow::*;
+use crate::render::create_program;
+use crate::NBodyError;
+
+/// Brightness above which pixels contribute to the glow
+const BLOOM_THRESHOLD: f32 = 0.3;
+
+const QUAD_VERTEX_SHADER: &str = r#"
+layout (location = 0) in vec2 position;
+out vec2 vUv;
+
+void main() {
+    vUv = position * 0.5 + 0.5;
+    gl_Position = vec4(position, 0.0, 1.0);
+}
+"#;
+
+// Separable 9-tap gaussian blur; the first pass also extracts bright pixels
+const BLUR_FRAGMENT_SHADER: &str = r#"
+in vec2 vUv;
+uniform sampler2D source;
+uniform vec2 direction;
+uniform float threshold;
+out vec4 fragColor;
+
+vec3 sampleBright(vec2 uv) {
+    return max(texture(source, uv).rgb - vec3(threshold), vec3(0.0));
+}
+
+void main() {
+    float weights[5] = float[](0.227027, 0.1945946, 0.1216216, 0.054054, 0.016216);
+    vec3 color = sampleBright(vUv) * weights[0];
+    for (int i = 1; i < 5; i++) {
+        vec2 offset = direction * float(i);
+        color += sampleBright(vUv + offset) * weights[i];
+        color += sampleBright(vUv - offset) * weights[i];
+    }
+    fragColor = vec4(color, 1.0);
+}
+"#;
+
+const COMPOSITE_FRAGMENT_SHADER: &str = r#"
+in vec2 vUv;
+uniform sampler2D scene;
+uniform sampler2D glow;
+uniform float strength;
+out vec4 fragColor;
+
+void main() {
+    vec3 color = texture(scene, vUv).rgb + strength * texture(glow, vUv).rgb;
+    fragColor = vec4(color, 1.0);
+}
+"#;
+
+/// A framebuffer with a single color texture attachment
+struct RenderTarget {
+    framebuffer: Framebuffer,
+    texture: Texture,
+}
+
+impl RenderTarget {
+    unsafe fn new(gl: &Context, width: i32, height: i32) -> Result<Self, NBodyError> {
+        let texture = gl.create_texture()
+            .map_err(|e| NBodyError::Gl(format!("Failed to create texture: {}", e)))?;
+        gl.bind_texture(TEXTURE_2D, Some(texture));
+        gl.tex_image_2d(TEXTURE_2D, 0, RGBA8 as i32, width, height, 0, RGBA, UNSIGNED_BYTE, None);
+        gl.tex_parameter_i32(TEXTURE_2D, TEXTURE_MIN_FILTER, LINEAR as i32);
+        gl.tex_parameter_i32(TEXTURE_2D, TEXTURE_MAG_FILTER, LINEAR as i32);
+        gl.tex_parameter_i32(TEXTURE_2D, TEXTURE_WRAP_S, CLAMP_TO_EDGE as i32);
+        gl.tex_parameter_i32(TEXTURE_2D, TEXTURE_WRAP_T, CLAMP_TO_EDGE as i32);
+
+        let framebuffer = gl.create_framebuffer()
+            .map_err(|e| NBodyError::Gl(format!("Failed to create framebuffer: {}", e)))?;
+        gl.bind_framebuffer(FRAMEBUFFER, Some(framebuffer));
+        gl.framebuffer_texture_2d(FRAMEBUFFER, COLOR_ATTACHMENT0, TEXTURE_2D, Some(texture), 0);
+
+        let status = gl.check_framebuffer_status(FRAMEBUFFER);
+        gl.bind_framebuffer(FRAMEBUFFER, None);
+        if status != FRAMEBUFFER_COMPLETE {
+            gl.delete_framebuffer(framebuffer);
+            gl.delete_texture(texture);
+            return Err(NBodyError::Gl(format!("Framebuffer incomplete: 0x{:x}", status)));
+        }
+
+        Ok(RenderTarget { framebuffer, texture })
+    }
+
+    unsafe fn delete(&self, gl: &Context) {
+        gl.delete_framebuffer(self.framebuffer);
+        gl.delete_texture(self.texture);
+    }
+}
+
+/// Offscreen bloom pass: the scene is rendered into a texture, its bright
+/// parts are blurred at half resolution and added back on top.
+pub(crate) struct Bloom {
+    strength: f32,
+    size: (i32, i32),
+    scene: RenderTarget,
+    blur: [RenderTarget; 2],
+    blur_program: Program,
+    composite_program: Program,
+    quad_array: VertexArray,
+    quad_buffer: Buffer,
+}
+
+impl Bloom {
+    pub(crate) fn new(gl: &Context, width: i32, height: i32, strength: f32) -> Result<Self, NBodyError> {
+        unsafe {
+            let blur_program = create_program(
+                gl,
+                QUAD_VERTEX_SHADER,
+                BLUR_FRAGMENT_SHADER,
+            )?;
+            let composite_program = create_program(
+                gl,
+                QUAD_VERTEX_SHADER,
+                COMPOSITE_FRAGMENT_SHADER,
+            )?;
+
+            // Fullscreen quad as two triangles
+            let quad: [f32; 12] = [
+                -1.0, -1.0, 1.0, -1.0, 1.0, 1.0,
+                -1.0, -1.0, 1.0, 1.0, -1.0, 1.0,
+            ];
+            let quad_array = gl.create_vertex_array()
+                .map_err(|e| NBodyError::Gl(format!("Failed to create vertex array: {}", e)))?;
+            let quad_buffer = gl.create_buffer()
+                .map_err(|e| NBodyError::Gl(format!("Failed to create vertex buffer: {}", e)))?;
+            gl.bind_vertex_array(Some(quad_array));
+            gl.bind_buffer(ARRAY_BUFFER, Some(quad_buffer));
+            gl.buffer_data_u8_slice(
+                ARRAY_BUFFER,
+                std::slice::from_raw_parts(
+                    quad.as_ptr() as *const u8,
+                    quad.len() * std::mem::size_of::<f32>(),
+                ),
+                STATIC_DRAW,
+            );
+            gl.enable_vertex_attrib_array(0);
+            gl.vertex_attrib_pointer_f32(0, 2, FLOAT, false, 0, 0);
+
+            let (scene, blur) = Self::create_targets(gl, width, height)?;
+
+            Ok(Bloom {
+                strength,
+                size: (width, height),
+                scene,
+                blur,
+                blur_program,
+                composite_program,
+                quad_array,
+                quad_buffer,
+            })
+        }
+    }
+
+    unsafe fn create_targets(gl: &Context, width: i32, height: i32) -> Result<(RenderTarget, [RenderTarget; 2]), NBodyError> {
+        let (half_w, half_h) = ((width / 2).max(1), (height / 2).max(1));
+        Ok((
+            RenderTarget::new(gl, width.max(1), height.max(1))?,
+            [
+                RenderTarget::new(gl, half_w, half_h)?,
+                RenderTarget::new(gl, half_w, half_h)?,
+            ],
+        ))
+    }
+
+    pub(crate) fn set_strength(&mut self, strength: f32) {
+        self.strength = strength;
+    }
+
+    /// Reallocate the offscreen targets for a new window size
+    pub(crate) fn resize(&mut self, gl: &Context, width: i32, height: i32) -> Result<(), NBodyError> {
+        if self.size == (width, height) {
+            return Ok(());
+        }
+
+        unsafe {
+            let (scene, blur) = Self::create_targets(gl, width, height)?;
+            self.scene.delete(gl);
+            self.blur.iter().for_each(|target| target.delete(gl));
+            self.scene = scene;
+            self.blur = blur;
+        }
+        self.size = (width, height);
+        Ok(())
+    }
+
+    /// Redirect drawing into the offscreen scene texture
+    pub(crate) fn begin(&self, gl: &Context) {
+        unsafe {
+            gl.bind_framebuffer(FRAMEBUFFER, Some(self.scene.framebuffer));
+            gl.viewport(0, 0, self.size.0, self.size.1);
+        }
+    }
+
+    /// Blur the bright parts of the scene and composite onto the default framebuffer
+    pub(crate) fn finish(&self, gl: &Context) {
+        let (width, height) = self.size;
+        let (half_w, half_h) = ((width / 2).max(1), (height / 2).max(1));
+
+        unsafe {
+            gl.disable(BLEND);
+            gl.bind_vertex_array(Some(self.quad_array));
+            gl.active_texture(TEXTURE0);
+
+            // Horizontal pass with bright extraction, then vertical pass
+            gl.use_program(Some(self.blur_program));
+            gl.viewport(0, 0, half_w, half_h);
+            let source = gl.get_uniform_location(self.blur_program, "source");
+            let direction = gl.get_uniform_location(self.blur_program, "direction");
+            let threshold = gl.get_uniform_location(self.blur_program, "threshold");
+            gl.uniform_1_i32(source.as_ref(), 0);
+
+            gl.bind_framebuffer(FRAMEBUFFER, Some(self.blur[0].framebuffer));
+            gl.bind_texture(TEXTURE_2D, Some(self.scene.texture));
+            gl.uniform_2_f32(direction.as_ref(), 1.0 / width as f32, 0.0);
+            gl.uniform_1_f32(threshold.as_ref(), BLOOM_THRESHOLD);
+            gl.draw_arrays(TRIANGLES, 0, 6);
+
+            gl.bind_framebuffer(FRAMEBUFFER, Some(self.blur[1].framebuffer));
+            gl.bind_texture(TEXTURE_2D, Some(self.blur[0].texture));
+            gl.uniform_2_f32(direction.as_ref(), 0.0, 1.0 / half_h as f32);
+            gl.uniform_1_f32(threshold.as_ref(), 0.0);
+            gl.draw_arrays(TRIANGLES, 0, 6);
+
+            // Composite scene and glow onto the window
+            gl.bind_framebuffer(FRAMEBUFFER, None);
+            gl.viewport(0, 0, width, height);
+            gl.use_program(Some(self.composite_program));
+            gl.uniform_1_i32(gl.get_uniform_location(self.composite_program, "scene").as_ref(), 0);
+            gl.uniform_1_i32(gl.get_uniform_location(self.composite_program, "glow").as_ref(), 1);
+            gl.uniform_1_f32(gl.get_uniform_location(self.composite_program, "strength").as_ref(), self.strength);
+            gl.bind_texture(TEXTURE_2D, Some(self.scene.texture));
+            gl.active_texture(TEXTURE1);
+            gl.bind_texture(TEXTURE_2D, Some(self.blur[1].texture));
+            gl.draw_arrays(TRIANGLES, 0, 6);
+
+            gl.active_texture(TEXTURE0);
+            gl.enable(BLEND);
+        }
+    }
+
+    pub(crate) fn delete(&self, gl: &Context) {
+        unsafe {
+            self.scene.delete(gl);
+            self.blur.iter().for_each(|target| target.delete(gl));
+            gl.delete_program(self.blur_program);
+            gl.delete_program(self.composite_program);
+            gl.delete_vertex_array(self.quad_array);
+            gl.delete_buffer(self.quad_buffer);
+        }
+    }
+}
@@ -1,28 +1,68 @@
-#[derive(Clone, Debug)]
+#[cfg(not(any(feature = "std", test)))]
+use crate::math::Float;
+use crate::Scalar;
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct Body {
-    pub mass: f64,
-    pub position: [f64; 2],  // [x, y]
-    pub velocity: [f64; 2],  // [vx, vy]
-    pub acceleration: [f64; 2],  // [ax, ay]
+    pub mass: Scalar,
+    pub position: [Scalar; 2],  // [x, y]
+    pub velocity: [Scalar; 2],  // [vx, vy]
+    pub acceleration: [Scalar; 2],  // [ax, ay]
+    pub species: u8,  // Component tag, e.g. which galaxy a star came from
+    pub color: [f32; 3],  // [r, g, b] in 0..1, drawn with ColorMode::PerBody
 }
 
 impl Body {
-    pub fn new(mass: f64, x: f64, y: f64, vx: f64, vy: f64) -> Self {
+    pub fn new(mass: Scalar, x: Scalar, y: Scalar, vx: Scalar, vy: Scalar) -> Self {
         Body {
             mass,
             position: [x, y],
             velocity: [vx, vy],
             acceleration: [0.0, 0.0],
+            species: 0,
+            color: [1.0, 1.0, 1.0],
         }
     }
 
-    pub fn update_position(&mut self, dt: f64) {
+    /// Tag the body with a species id (0 by default)
+    pub fn with_species(mut self, species: u8) -> Self {
+        self.species = species;
+        self
+    }
+
+    /// Give the body its own color (white by default)
+    pub fn with_color(mut self, color: [f32; 3]) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// Magnitude of the velocity
+    ///
+    /// ```
+    /// # use nbody_core::Body;
+    /// assert_eq!(Body::new(2.0, 0.0, 0.0, 3.0, -4.0).speed(), 5.0);
+    /// ```
+    pub fn speed(&self) -> Scalar {
+        (self.velocity[0] * self.velocity[0] + self.velocity[1] * self.velocity[1]).sqrt()
+    }
+
+    /// Kinetic energy `m v² / 2`
+    ///
+    /// ```
+    /// # use nbody_core::Body;
+    /// assert_eq!(Body::new(2.0, 0.0, 0.0, 3.0, -4.0).kinetic_energy(), 25.0);
+    /// ```
+    pub fn kinetic_energy(&self) -> Scalar {
+        0.5 * self.mass * (self.velocity[0] * self.velocity[0] + self.velocity[1] * self.velocity[1])
+    }
+
+    pub fn update_position(&mut self, dt: Scalar) {
         // Update position based on velocity
         self.position[0] += self.velocity[0] * dt;
         self.position[1] += self.velocity[1] * dt;
     }
 
-    pub fn update_velocity(&mut self, dt: f64) {
+    pub fn update_velocity(&mut self, dt: Scalar) {
         // Update velocity based on acceleration
         self.velocity[0] += self.acceleration[0] * dt;
         self.velocity[1] += self.acceleration[1] * dt;
@@ -0,0 +1,57 @@
+//! Error type shared by the library and its front ends.
+
+use thiserror::Error;
+
+/// Errors from rendering and snapshot I/O. `Display` gives a one-line
+/// message suitable for logging.
+#[derive(Debug, Error)]
+pub enum NBodyError {
+    /// An I/O operation failed; `context` says which
+    #[error("{context}: {source}")]
+    Io {
+        context: String,
+        #[source]
+        source: std::io::Error,
+    },
+    /// A value in an input file is missing or malformed
+    #[error("{0}")]
+    Parse(String),
+    /// A body record has the wrong shape
+    #[error("Invalid body data: {0}")]
+    InvalidBody(String),
+    /// A shader failed to compile; holds the driver's info log
+    #[error("Failed to compile shader: {0}")]
+    GlShaderCompile(String),
+    /// A shader program failed to link; holds the driver's info log
+    #[error("Failed to link program: {0}")]
+    GlLink(String),
+    /// Any other OpenGL failure, such as creating an object
+    #[error("{0}")]
+    Gl(String),
+}
+
+impl NBodyError {
+    /// An `Io` error with the given context
+    pub fn io(context: &str, source: std::io::Error) -> Self {
+        NBodyError::Io { context: context.to_string(), source }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_messages_match_previous_strings() {
+        let missing = std::io::Error::new(std::io::ErrorKind::NotFound, "no such file");
+        assert_eq!(NBodyError::io("Failed to open file", missing).to_string(), "Failed to open file: no such file");
+        assert_eq!(
+            NBodyError::InvalidBody("expected 5 or 6 values, got 3".to_string()).to_string(),
+            "Invalid body data: expected 5 or 6 values, got 3"
+        );
+        assert_eq!(
+            NBodyError::GlShaderCompile("0:1: syntax error".to_string()).to_string(),
+            "Failed to compile shader: 0:1: syntax error"
+        );
+    }
+}
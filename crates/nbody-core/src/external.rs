@@ -0,0 +1,68 @@
+//! Static background acceleration fields.
+//!
+//! A field maps a position to the acceleration it imparts there and is added
+//! on top of self-gravity (see `Simulation::set_external_field`). Together
+//! with `Simulation::set_self_gravity(false)` this turns the bodies into test
+//! particles orbiting in a fixed potential.
+
+use alloc::boxed::Box;
+#[cfg(not(any(feature = "std", test)))]
+use crate::math::Float;
+use crate::Scalar;
+
+/// An external acceleration field evaluated at each body's position
+pub type ExternalField = Box<dyn Fn([Scalar; 2]) -> [Scalar; 2] + Send + Sync>;
+
+/// The same acceleration everywhere, like gravity near a planet's surface
+pub fn uniform(acceleration: [Scalar; 2]) -> ExternalField {
+    Box::new(move |_| acceleration)
+}
+
+/// Newtonian point mass at the origin with gravitational parameter `gm` (G M)
+pub fn point_mass(gm: Scalar) -> ExternalField {
+    Box::new(move |position| {
+        let r_sq = position[0] * position[0] + position[1] * position[1];
+        if r_sq == 0.0 {
+            return [0.0, 0.0];
+        }
+        let scale = -gm / (r_sq * r_sq.sqrt());
+        [scale * position[0], scale * position[1]]
+    })
+}
+
+/// Logarithmic halo `Φ = v0²/2 ln(r² + core_radius²)`, centred on the origin.
+///
+/// Its circular speed rises within `core_radius` and tends to `v0` outside it,
+/// giving the flat rotation curve of a galactic dark matter halo.
+pub fn logarithmic_halo(v0: Scalar, core_radius: Scalar) -> ExternalField {
+    Box::new(move |position| {
+        let r_sq = position[0] * position[0] + position[1] * position[1];
+        let scale = -v0 * v0 / (r_sq + core_radius * core_radius);
+        [scale * position[0], scale * position[1]]
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_logarithmic_halo_flat_rotation_curve() {
+        let field = logarithmic_halo(2.0, 0.1);
+        for r in [5.0, 10.0, 50.0] {
+            let a = field([r, 0.0]);
+            let v = (-a[0] * r).sqrt();
+            assert!((v - 2.0).abs() < 1e-3, "v({}) = {}", r, v);
+        }
+    }
+
+    #[test]
+    fn test_point_mass_inverse_square() {
+        let field = point_mass(3.0);
+        assert_eq!(field([0.0, 0.0]), [0.0, 0.0]);
+        let a = field([0.0, 2.0]);
+        assert!(a[0].abs() < 1e-12);
+        assert!((a[1] + 0.75).abs() < 1e-6);
+        assert_eq!(uniform([0.0, -9.8])([1.0, 2.0]), [0.0, -9.8]);
+    }
+}
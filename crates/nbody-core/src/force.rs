@@ -0,0 +1,424 @@
+#[cfg(not(any(feature = "std", test)))]
+use crate::math::Float;
+use crate::Scalar;
+
+/// The interaction law used for the force between bodies.
+///
+/// Every law is built on the softened Newtonian pair force
+/// `G m M / (r² + softening)`; the variants modify it.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ForceLaw {
+    /// Softened Newtonian gravity
+    #[default]
+    Newtonian,
+    /// A simple MOND-like modification: the total Newtonian acceleration
+    /// `a_N` on each body is boosted by `ν(a_N / a0)` with the "simple"
+    /// interpolating function `ν(y) = 1/2 + sqrt(1/4 + 1/y)`. Far below `a0`
+    /// the acceleration tends to `sqrt(a_N a0)`, which flattens rotation
+    /// curves. This is a toy for teaching, not a self-consistent MOND solver.
+    Modified { a0: Scalar },
+    /// Newtonian gravity plus a repulsive core between individual bodies,
+    /// the `r⁻¹²` term of the Lennard-Jones potential: `4 ε (σ / r)¹²`. It
+    /// is negligible beyond a few `sigma` but keeps bodies from passing
+    /// through each other, so close encounters bounce instead of blowing up.
+    ///
+    /// The core acts only body to body, never between a body and a distant
+    /// tree node, so `sigma` should be small compared to the spacing at
+    /// which the tree starts grouping bodies.
+    GravityPlusRepulsion { epsilon: Scalar, sigma: Scalar },
+    /// A toy: Newtonian gravity plus the leading post-Newtonian radiation
+    /// reaction (the 2.5PN term), a velocity-dependent drag that takes
+    /// orbital energy away as gravitational waves would, so tight binaries
+    /// slowly spiral in. `c` is the speed of light in simulation units; the
+    /// decay rate scales as `c⁻⁵`, so it only shows when orbital speeds are a
+    /// sizeable fraction of `c`.
+    ///
+    /// The conservative 1PN and 2PN terms (precession) are left out, and as
+    /// with the repulsive core the term acts only body to body, never
+    /// between a body and a distant tree node.
+    PostNewtonian { c: Scalar },
+}
+
+impl ForceLaw {
+    /// Magnitude of the attractive force between a pair, given `G m M`
+    pub(crate) fn pair_force(&self, g_mm: Scalar, distance_sq: Scalar, softening: Scalar) -> Scalar {
+        g_mm / (distance_sq + softening)
+    }
+
+    /// Potential energy of a pair, given `G m M`, taken to be zero at infinity.
+    ///
+    /// For the `Modified` law this is the Newtonian potential, since the
+    /// modification is not a pairwise interaction.
+    pub(crate) fn pair_potential(&self, g_mm: Scalar, distance: Scalar, softening: Scalar) -> Scalar {
+        softened_potential(g_mm, distance, softening)
+    }
+
+    /// Magnitude of the short-range repulsion between two bodies and its
+    /// potential energy, or `None` for laws without one
+    pub(crate) fn repulsion(&self, distance_sq: Scalar) -> Option<(Scalar, Scalar)> {
+        match *self {
+            ForceLaw::GravityPlusRepulsion { epsilon, sigma } => {
+                let s6 = (sigma * sigma / distance_sq).powi(3);
+                let potential = 4.0 * epsilon * s6 * s6;
+                Some((12.0 * potential / distance_sq.sqrt(), potential))
+            }
+            ForceLaw::Newtonian | ForceLaw::Modified { .. } | ForceLaw::PostNewtonian { .. } => None,
+        }
+    }
+
+    /// Radiation reaction force on a body of `mass` from one of `other_mass`,
+    /// given their separation and relative velocity (the body's minus the
+    /// other's), or `None` for laws without one.
+    ///
+    /// This is the 2.5PN relative acceleration of Lincoln & Will (1990),
+    /// `(8/5) η (G M)² / (c⁵ r³) [(18 v² + 2/3 G M / r - 25 ṙ²) ṙ n - (6 v² - 2 G M / r - 15 ṙ²) v]`,
+    /// times the reduced mass. For a circular orbit it removes energy at the
+    /// rate of the quadrupole formula. `softening` is added to `r²` as for
+    /// the Newtonian force.
+    pub(crate) fn radiation_reaction(
+        &self,
+        g: Scalar,
+        softening: Scalar,
+        [mass, other_mass]: [Scalar; 2],
+        separation: [Scalar; 2],
+        velocity: [Scalar; 2],
+    ) -> Option<[Scalar; 2]> {
+        let ForceLaw::PostNewtonian { c } = *self else {
+            return None;
+        };
+
+        let total_mass = mass + other_mass;
+        let r = (separation[0] * separation[0] + separation[1] * separation[1] + softening).sqrt();
+        if total_mass <= 0.0 || r == 0.0 {
+            return Some([0.0, 0.0]);
+        }
+        let reduced_mass = mass * other_mass / total_mass;
+        let eta = reduced_mass / total_mass;
+        let gm = g * total_mass;
+
+        let n = [separation[0] / r, separation[1] / r];
+        let v_sq = velocity[0] * velocity[0] + velocity[1] * velocity[1];
+        let r_dot = n[0] * velocity[0] + n[1] * velocity[1];
+
+        let scale = 1.6 * eta * gm * gm / (c.powi(5) * r * r * r);
+        let radial = (18.0 * v_sq + 2.0 / 3.0 * gm / r - 25.0 * r_dot * r_dot) * r_dot;
+        let along = 6.0 * v_sq - 2.0 * gm / r - 15.0 * r_dot * r_dot;
+        Some([
+            reduced_mass * scale * (radial * n[0] - along * velocity[0]),
+            reduced_mass * scale * (radial * n[1] - along * velocity[1]),
+        ])
+    }
+
+    /// Apply any modification that acts on the total force on a body
+    pub(crate) fn finish(&self, force: [Scalar; 2], mass: Scalar) -> [Scalar; 2] {
+        match *self {
+            ForceLaw::Newtonian | ForceLaw::GravityPlusRepulsion { .. } | ForceLaw::PostNewtonian { .. } => force,
+            ForceLaw::Modified { a0 } => {
+                let accel = (force[0] * force[0] + force[1] * force[1]).sqrt() / mass;
+                if accel == 0.0 || a0 <= 0.0 {
+                    return force;
+                }
+                let nu = 0.5 + (0.25 + a0 / accel).sqrt();
+                [force[0] * nu, force[1] * nu]
+            }
+        }
+    }
+}
+
+/// The Barnes-Hut opening criterion: when a tree node is far enough from a
+/// body to be replaced by a point mass at its centre of mass.
+///
+/// Each compares a node size to a distance and accepts the node when the
+/// ratio is below the tree threshold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OpeningCriterion {
+    /// Node diagonal over distance to its centre of mass (the most
+    /// conservative of the three at a given threshold)
+    #[default]
+    Diagonal,
+    /// Node width over distance to its centre of mass: the classic `s / d`
+    Width,
+    /// Node width over distance to the nearest point of the node, so a body
+    /// inside or right next to a node always opens it, however far the
+    /// centre of mass is
+    MinDistance,
+}
+
+/// Parameters for a Barnes-Hut force evaluation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ForceParams {
+    pub g: Scalar,
+    pub softening: Scalar,
+    pub threshold: Scalar,
+    pub law: ForceLaw,
+    pub opening: OpeningCriterion,
+    /// Sum the force terms with compensated (Kahan) summation, which keeps
+    /// the result accurate when large terms nearly cancel, at some cost in speed
+    pub compensated: bool,
+    /// Separate x and y softening, used in place of `softening` for the
+    /// force along each axis
+    pub anisotropic_softening: Option<[Scalar; 2]>,
+}
+
+impl ForceParams {
+    pub fn new(g: Scalar, softening: Scalar, threshold: Scalar) -> Self {
+        ForceParams {
+            g,
+            softening,
+            threshold,
+            law: ForceLaw::default(),
+            opening: OpeningCriterion::default(),
+            compensated: false,
+            anisotropic_softening: None,
+        }
+    }
+
+    /// Attractive pair force towards a point at `offset` from the body, at
+    /// squared distance `distance_sq` (nonzero), given `G m M`.
+    ///
+    /// With anisotropic softening each component is the isotropic force
+    /// along that axis with that axis's softening. The result is then not
+    /// the gradient of any potential, so energy is only approximately
+    /// conserved.
+    pub(crate) fn pair_force(&self, g_mm: Scalar, offset: [Scalar; 2], distance_sq: Scalar) -> [Scalar; 2] {
+        let distance = distance_sq.sqrt();
+        match self.anisotropic_softening {
+            None => {
+                let scale = self.law.pair_force(g_mm, distance_sq, self.softening) / distance;
+                [scale * offset[0], scale * offset[1]]
+            }
+            Some([softening_x, softening_y]) => [
+                self.law.pair_force(g_mm, distance_sq, softening_x) * offset[0] / distance,
+                self.law.pair_force(g_mm, distance_sq, softening_y) * offset[1] / distance,
+            ],
+        }
+    }
+
+    /// Softening for the pair potential: `softening`, or the mean of the
+    /// anisotropic softenings
+    pub(crate) fn potential_softening(&self) -> Scalar {
+        match self.anisotropic_softening {
+            Some([x, y]) => 0.5 * (x + y),
+            None => self.softening,
+        }
+    }
+}
+
+/// Running total of force (and potential) terms. Subtotals from different
+/// tree nodes are combined with `merge`.
+pub(crate) trait ForceSum<const N: usize>: Default {
+    fn add(&mut self, terms: [Scalar; N]);
+    fn merge(&mut self, other: Self);
+    fn total(self) -> [Scalar; N];
+}
+
+/// Plain floating point summation
+pub(crate) struct NaiveSum<const N: usize>([Scalar; N]);
+
+impl<const N: usize> Default for NaiveSum<N> {
+    fn default() -> Self {
+        NaiveSum([0.0; N])
+    }
+}
+
+impl<const N: usize> ForceSum<N> for NaiveSum<N> {
+    fn add(&mut self, terms: [Scalar; N]) {
+        for (sum, term) in self.0.iter_mut().zip(terms) {
+            *sum += term;
+        }
+    }
+
+    fn merge(&mut self, other: Self) {
+        self.add(other.0);
+    }
+
+    fn total(self) -> [Scalar; N] {
+        self.0
+    }
+}
+
+/// Compensated summation (Neumaier's variant of Kahan's, which also copes with
+/// terms larger than the running sum). The rounding error of every addition
+/// is accumulated separately and added back at the end, so the error no
+/// longer grows with the size of terms that cancel.
+pub(crate) struct CompensatedSum<const N: usize> {
+    sum: [Scalar; N],
+    compensation: [Scalar; N],
+}
+
+impl<const N: usize> Default for CompensatedSum<N> {
+    fn default() -> Self {
+        CompensatedSum { sum: [0.0; N], compensation: [0.0; N] }
+    }
+}
+
+impl<const N: usize> ForceSum<N> for CompensatedSum<N> {
+    fn add(&mut self, terms: [Scalar; N]) {
+        for (i, term) in terms.into_iter().enumerate() {
+            let sum = self.sum[i] + term;
+            self.compensation[i] += if self.sum[i].abs() >= term.abs() {
+                (self.sum[i] - sum) + term
+            } else {
+                (term - sum) + self.sum[i]
+            };
+            self.sum[i] = sum;
+        }
+    }
+
+    fn merge(&mut self, other: Self) {
+        self.add(other.sum);
+        for (compensation, other) in self.compensation.iter_mut().zip(other.compensation) {
+            *compensation += other;
+        }
+    }
+
+    fn total(self) -> [Scalar; N] {
+        let mut total = self.sum;
+        for (total, compensation) in total.iter_mut().zip(self.compensation) {
+            *total += compensation;
+        }
+        total
+    }
+}
+
+/// Potential energy of a pair separated by `distance` under the softened force
+/// `g_mm / (r² + softening)`, taken to be zero at infinity.
+pub(crate) fn softened_potential(g_mm: Scalar, distance: Scalar, softening: Scalar) -> Scalar {
+    if softening > 0.0 {
+        let eps = softening.sqrt();
+        -g_mm * (eps / distance).atan() / eps
+    } else {
+        -g_mm / distance
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Body, Bounds, QuadTree, Simulation};
+
+    /// Circular speeds of massless probes at the given radii around a point mass
+    fn rotation_curve(law: ForceLaw, radii: &[Scalar]) -> Vec<Scalar> {
+        let mut tree = QuadTree::new(Bounds::new([-100.0, -100.0], [100.0, 100.0]));
+        assert!(tree.insert(Body::new(1.0, 0.0, 0.0, 0.0, 0.0)));
+
+        let params = ForceParams { law, ..ForceParams::new(1.0, 0.0, 0.0) };
+        radii
+            .iter()
+            .map(|&r| {
+                let probe = Body::new(1.0, r, 0.0, 0.0, 0.0);
+                let force = tree.calculate_force_with(&probe, &params);
+                (force[0].abs() * r).sqrt()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_anisotropic_softening_weakens_one_axis() {
+        let mut tree = QuadTree::new(Bounds::new([-4.0, -4.0], [4.0, 4.0]));
+        assert!(tree.insert(Body::new(1.0, 0.0, 0.0, 0.0, 0.0)));
+        let probe = Body::new(1.0, 0.3, 0.4, 0.0, 0.0);
+
+        let isotropic = ForceParams::new(1.0, 0.01, 0.0);
+        let force = tree.calculate_force_with(&probe, &isotropic);
+        let same = ForceParams { anisotropic_softening: Some([0.01, 0.01]), ..isotropic };
+        assert_eq!(tree.calculate_force_with(&probe, &same), force);
+
+        // Heavy softening along y leaves the x force alone
+        let flattened = ForceParams { anisotropic_softening: Some([0.01, 1.0]), ..isotropic };
+        let anisotropic = tree.calculate_force_with(&probe, &flattened);
+        assert_eq!(anisotropic[0], force[0]);
+        assert!(anisotropic[1].abs() < 0.5 * force[1].abs(), "{:?} vs {:?}", anisotropic, force);
+        assert!(anisotropic[1] < 0.0);
+    }
+
+    #[test]
+    fn test_modified_law_flattens_rotation_curve() {
+        // With G M = a0 = 1 the transition radius is 1, so these probes are
+        // all in the low-acceleration regime
+        let radii = [4.0, 8.0, 16.0, 32.0, 64.0];
+        let newtonian = rotation_curve(ForceLaw::Newtonian, &radii);
+        let modified = rotation_curve(ForceLaw::Modified { a0: 1.0 }, &radii);
+
+        // Keplerian fall-off v ∝ r^-1/2
+        let newtonian_ratio = newtonian[4] / newtonian[0];
+        assert!((newtonian_ratio - 0.25).abs() < 1e-4);
+
+        // The modified curve falls far less and approaches (G M a0)^(1/4)
+        let modified_ratio = modified[4] / modified[0];
+        assert!(modified_ratio > 0.9, "ratio {}", modified_ratio);
+        assert!((modified[4] - 1.0).abs() < 0.01, "outer speed {}", modified[4]);
+
+        // Every radius is boosted relative to Newtonian
+        for (m, n) in modified.iter().zip(&newtonian) {
+            assert!(m > n);
+        }
+    }
+
+    #[test]
+    fn test_repulsive_core_bounces_bodies_apart() {
+        // Two bodies falling into each other head on
+        let bodies = vec![
+            Body::new(1.0, -0.5, 0.0, 0.5, 0.0),
+            Body::new(1.0, 0.5, 0.0, -0.5, 0.0),
+        ];
+        let mut sim = Simulation::new(bodies, 1e-4, 1.0, 1e-4, 0.5);
+        let sigma = 0.05;
+        sim.set_force_law(ForceLaw::GravityPlusRepulsion { epsilon: 1.0, sigma });
+
+        let separation = |sim: &Simulation| sim.bodies()[1].position[0] - sim.bodies()[0].position[0];
+        let mut closest = separation(&sim);
+        for _ in 0..20_000 {
+            sim.step();
+            closest = closest.min(separation(&sim));
+            if sim.bodies()[0].velocity[0] < 0.0 {
+                break;
+            }
+        }
+
+        // The core turned the bodies around before they overlapped
+        assert!(sim.bodies()[0].velocity[0] < 0.0 && sim.bodies()[1].velocity[0] > 0.0);
+        assert!(closest > 0.5 * sigma && closest < 2.0 * sigma, "closest approach {}", closest);
+    }
+
+    /// Separation of an equal-mass circular binary after `orbits` orbits
+    fn binary_separation(law: ForceLaw, orbits: usize) -> Scalar {
+        // G M = 2 and r = 1: relative speed √2, period π√2
+        let bodies = vec![
+            Body::new(1.0, -0.5, 0.0, 0.0, -0.5 * (2.0 as Scalar).sqrt()),
+            Body::new(1.0, 0.5, 0.0, 0.0, 0.5 * (2.0 as Scalar).sqrt()),
+        ];
+        let mut sim = Simulation::new(bodies, 1e-3, 1.0, 0.0, 0.5);
+        sim.set_integrator(crate::Integrator::Leapfrog);
+        sim.set_force_law(law);
+
+        let steps = (orbits as Scalar * core::f64::consts::PI as Scalar * (2.0 as Scalar).sqrt() / 1e-3) as usize;
+        for _ in 0..steps {
+            sim.step();
+        }
+        let [a, b] = [&sim.bodies()[0], &sim.bodies()[1]];
+        (a.position[0] - b.position[0]).hypot(a.position[1] - b.position[1])
+    }
+
+    #[test]
+    fn test_post_newtonian_binary_inspirals() {
+        let newtonian = binary_separation(ForceLaw::Newtonian, 10);
+        assert!((newtonian - 1.0).abs() < 1e-3, "Newtonian separation {}", newtonian);
+
+        // Quadrupole decay r(t) = r0 (1 - t / t_merge)^(1/4), with
+        // t_merge = 5 c⁵ r0⁴ / (256 G³ m1 m2 M) = 976.6 for c = 10
+        let c: Scalar = 10.0;
+        let t_merge = 5.0 * c.powi(5) / 512.0;
+        let t = 10.0 * core::f64::consts::PI as Scalar * (2.0 as Scalar).sqrt();
+        let expected = (1.0 - t / t_merge).powf(0.25);
+        let inspiral = binary_separation(ForceLaw::PostNewtonian { c }, 10);
+        assert!(inspiral < newtonian - 5e-3, "separation {}", inspiral);
+        assert!((inspiral - expected).abs() < 0.1 * (1.0 - expected), "{} vs {}", inspiral, expected);
+    }
+
+    #[test]
+    fn test_newtonian_finish_is_identity() {
+        let force = [1.5, -2.0];
+        assert_eq!(ForceLaw::Newtonian.finish(force, 3.0), force);
+    }
+}
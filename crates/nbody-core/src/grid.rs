@@ -0,0 +1,267 @@
+//! Uniform-grid spatial hash for short-range (cutoff) forces.
+//!
+//! For nearly uniform distributions where gravity is screened or truncated,
+//! binning bodies into square cells the size of the cutoff radius means every
+//! interacting pair lies in the same or an adjacent cell, so each body only
+//! needs to look at a 3x3 block of cells.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::Range;
+#[cfg(not(any(feature = "std", test)))]
+use crate::math::Float;
+use crate::body::Body;
+use crate::force::{CompensatedSum, ForceParams, ForceSum, NaiveSum};
+use crate::simulation::sequential_extents;
+use crate::Scalar;
+
+/// Most cells allowed per body, beyond which cells are widened so that a
+/// few far-flung bodies cannot blow up the grid
+const MAX_CELLS_PER_BODY: usize = 4;
+
+/// Bodies counting-sorted by cell over a dense row-major grid covering their
+/// finite positions. The bodies of a cell are contiguous, and so are those of
+/// a row of adjacent cells, so a 3x3 block is three slices.
+pub struct SpatialHash {
+    cutoff: Scalar,
+    // Side of the cells, at least `cutoff`
+    cell_width: Scalar,
+    origin: [Scalar; 2],
+    columns: usize,
+    rows: usize,
+    // Offset into `points` of the first body of each cell, plus the end
+    cell_start: Vec<usize>,
+    // Position, velocity and mass of each body, in cell order
+    points: Vec<([Scalar; 2], [Scalar; 2], Scalar)>,
+    // Index in the input of each body, in cell order
+    indices: Vec<usize>,
+}
+
+impl SpatialHash {
+    /// Bin `bodies` into square cells of side `cell_size` (or wider, if the
+    /// bodies are spread very far apart). Bodies with a non-finite position
+    /// are left out.
+    pub fn new(bodies: &[Body], cell_size: Scalar) -> Self {
+        let (min, max) = sequential_extents(bodies);
+        let origin = if min[0] <= max[0] { min } else { [0.0, 0.0] };
+        let span = [(max[0] - origin[0]).max(0.0), (max[1] - origin[1]).max(0.0)];
+
+        // Widen the cells until the grid is small against the body count
+        let max_cells = MAX_CELLS_PER_BODY * bodies.len() + 1;
+        let mut cell_width = cell_size;
+        let (columns, rows) = loop {
+            let columns = (span[0] / cell_width).floor() + 1.0;
+            let rows = (span[1] / cell_width).floor() + 1.0;
+            if columns * rows <= max_cells as Scalar {
+                break (columns as usize, rows as usize);
+            }
+            cell_width *= 2.0;
+        };
+
+        let mut hash = SpatialHash {
+            cutoff: cell_size,
+            cell_width,
+            origin,
+            columns,
+            rows,
+            cell_start: vec![0; columns * rows + 1],
+            points: Vec::with_capacity(bodies.len()),
+            indices: Vec::with_capacity(bodies.len()),
+        };
+
+        // Counting sort: count the bodies per cell, turn the counts into
+        // start offsets, then place each body at its cell's next free slot
+        let cells: Vec<Option<usize>> = bodies.iter().map(|body| hash.cell_of(body.position)).collect();
+        for cell in cells.iter().flatten() {
+            hash.cell_start[cell + 1] += 1;
+        }
+        for cell in 0..columns * rows {
+            hash.cell_start[cell + 1] += hash.cell_start[cell];
+        }
+        let placed = hash.cell_start[columns * rows];
+        let mut next = hash.cell_start.clone();
+        hash.points.resize(placed, ([0.0; 2], [0.0; 2], 0.0));
+        hash.indices.resize(placed, 0);
+        for (index, (body, cell)) in bodies.iter().zip(&cells).enumerate() {
+            if let Some(cell) = *cell {
+                hash.points[next[cell]] = (body.position, body.velocity, body.mass);
+                hash.indices[next[cell]] = index;
+                next[cell] += 1;
+            }
+        }
+
+        hash
+    }
+
+    /// The cutoff radius of `neighbors` and the forces, as passed to `new`
+    pub fn cell_size(&self) -> Scalar {
+        self.cutoff
+    }
+
+    /// Column and row of the cell at `position`, which may lie outside the
+    /// grid, or `None` if it is not finite
+    fn column_row(&self, position: [Scalar; 2]) -> Option<(i64, i64)> {
+        if !position.iter().all(|v| v.is_finite()) {
+            return None;
+        }
+        Some((
+            ((position[0] - self.origin[0]) / self.cell_width).floor() as i64,
+            ((position[1] - self.origin[1]) / self.cell_width).floor() as i64,
+        ))
+    }
+
+    /// Index of the grid cell holding `position`, if it has one
+    fn cell_of(&self, position: [Scalar; 2]) -> Option<usize> {
+        let (column, row) = self.column_row(position)?;
+        let in_grid = (0..self.columns as i64).contains(&column) && (0..self.rows as i64).contains(&row);
+        in_grid.then(|| row as usize * self.columns + column as usize)
+    }
+
+    /// Ranges of `points` for the 3x3 block of cells around `position`, one
+    /// per row of the block that lies in the grid
+    fn block(&self, position: [Scalar; 2]) -> impl Iterator<Item = Range<usize>> + '_ {
+        let around = self.column_row(position).and_then(|(column, row)| {
+            let first = (column - 1).max(0);
+            let last = (column + 1).min(self.columns as i64 - 1);
+            (first <= last).then_some((first as usize, last as usize, row))
+        });
+        around.into_iter().flat_map(move |(first, last, row)| {
+            (row - 1..=row + 1)
+                .filter(|&row| (0..self.rows as i64).contains(&row))
+                .map(move |row| {
+                    let row_start = row as usize * self.columns;
+                    self.cell_start[row_start + first]..self.cell_start[row_start + last + 1]
+                })
+        })
+    }
+
+    /// Indices of all bodies in the 3x3 block of cells around `position`,
+    /// which includes every body within `cell_size` of it
+    pub fn candidates(&self, position: [Scalar; 2]) -> impl Iterator<Item = usize> + '_ {
+        self.block(position).flatten().map(|slot| self.indices[slot])
+    }
+
+    /// Indices of all bodies strictly within `cell_size` of `position`
+    pub fn neighbors(&self, position: [Scalar; 2]) -> Vec<usize> {
+        let cutoff_sq = self.cutoff * self.cutoff;
+        self.block(position)
+            .flatten()
+            .filter(|&slot| distance_sq(position, self.points[slot].0) < cutoff_sq)
+            .map(|slot| self.indices[slot])
+            .collect()
+    }
+
+    /// Force on `body` from all bodies within the cutoff (`cell_size`).
+    ///
+    /// Bodies at exactly the same position as `body` (including itself)
+    /// contribute nothing, as in the tree. `params.threshold` is unused.
+    pub fn calculate_force_with(&self, body: &Body, params: &ForceParams) -> [Scalar; 2] {
+        self.calculate_force_and_potential_with(body, params).0
+    }
+
+    /// `calculate_force_with` together with the (truncated, unshifted)
+    /// potential energy of `body`
+    pub fn calculate_force_and_potential_with(&self, body: &Body, params: &ForceParams) -> ([Scalar; 2], Scalar) {
+        let [fx, fy, potential] = if params.compensated {
+            self.sum_pairs::<CompensatedSum<3>>(body, params)
+        } else {
+            self.sum_pairs::<NaiveSum<3>>(body, params)
+        };
+        (params.law.finish([fx, fy], body.mass), potential)
+    }
+
+    /// Total force and potential of `body` from the bodies within the cutoff
+    fn sum_pairs<S: ForceSum<3>>(&self, body: &Body, params: &ForceParams) -> [Scalar; 3] {
+        let cutoff_sq = self.cutoff * self.cutoff;
+        let mut total = S::default();
+
+        for slot in self.block(body.position).flatten() {
+            let (position, velocity, mass) = self.points[slot];
+            let distance_sq = distance_sq(body.position, position);
+            if distance_sq == 0.0 || distance_sq >= cutoff_sq {
+                continue;
+            }
+
+            let distance = distance_sq.sqrt();
+            let offset = [position[0] - body.position[0], position[1] - body.position[1]];
+            let g_mm = params.g * body.mass * mass;
+            let (repulsion, repulsive_potential) = params.law.repulsion(distance_sq).unwrap_or((0.0, 0.0));
+            let attraction = params.pair_force(g_mm, offset, distance_sq);
+            let drag = params.law
+                .radiation_reaction(
+                    params.g,
+                    params.softening,
+                    [body.mass, mass],
+                    [body.position[0] - position[0], body.position[1] - position[1]],
+                    [body.velocity[0] - velocity[0], body.velocity[1] - velocity[1]],
+                )
+                .unwrap_or([0.0, 0.0]);
+            total.add([
+                attraction[0] - repulsion * offset[0] / distance + drag[0],
+                attraction[1] - repulsion * offset[1] / distance + drag[1],
+                params.law.pair_potential(g_mm, distance, params.potential_softening()) + repulsive_potential,
+            ]);
+        }
+
+        total.total()
+    }
+}
+
+fn distance_sq(a: [Scalar; 2], b: [Scalar; 2]) -> Scalar {
+    let dx = a[0] - b[0];
+    let dy = a[1] - b[1];
+    dx * dx + dy * dy
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn test_neighbors_match_brute_force() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(5);
+        let bodies: Vec<Body> = (0..2000)
+            .map(|_| Body::new(1.0, rng.gen_range(-3.0..3.0), rng.gen_range(-3.0..3.0), 0.0, 0.0))
+            .collect();
+        let cutoff = 0.25;
+        let hash = SpatialHash::new(&bodies, cutoff);
+
+        for _ in 0..200 {
+            let probe = [rng.gen_range(-3.5..3.5), rng.gen_range(-3.5..3.5)];
+            let mut found = hash.neighbors(probe);
+            found.sort_unstable();
+            let expected: Vec<usize> = (0..bodies.len())
+                .filter(|&i| distance_sq(probe, bodies[i].position) < cutoff * cutoff)
+                .collect();
+            assert_eq!(found, expected);
+        }
+    }
+
+    #[test]
+    fn test_far_and_non_finite_bodies() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(9);
+        let mut bodies: Vec<Body> = (0..500)
+            .map(|_| Body::new(1.0, rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), 0.0, 0.0))
+            .collect();
+        bodies.push(Body::new(1.0, 1e12, 0.0, 0.0, 0.0));
+        bodies.push(Body::new(1.0, Scalar::NAN, 0.0, 0.0, 0.0));
+        bodies.push(Body::new(1.0, 0.0, Scalar::INFINITY, 0.0, 0.0));
+        let cutoff = 0.1;
+        let hash = SpatialHash::new(&bodies, cutoff);
+
+        // The outlier widens the cells instead of needing ~10^13 of them
+        assert!(hash.cell_width > cutoff);
+        assert!(hash.columns * hash.rows <= MAX_CELLS_PER_BODY * bodies.len() + 1);
+        assert_eq!(hash.indices.len(), bodies.len() - 2);
+
+        for probe in bodies.iter().map(|body| body.position) {
+            let mut found = hash.neighbors(probe);
+            found.sort_unstable();
+            let expected: Vec<usize> = (0..bodies.len())
+                .filter(|&i| distance_sq(probe, bodies[i].position) < cutoff * cutoff)
+                .collect();
+            assert_eq!(found, expected);
+        }
+    }
+}
@@ -0,0 +1,594 @@
+//! Random initial condition generators.
+//!
+//! Each generator has a `_with_rng` variant taking an explicit random number
+//! generator, so runs and tests can be made reproducible with a seeded RNG.
+
+use rand::Rng;
+use crate::body::Body;
+use crate::Scalar;
+
+const PI: Scalar = std::f64::consts::PI as Scalar;
+
+/// Region random positions are drawn from, centred on the origin
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shape {
+    /// Within radius 1, with the radius drawn uniformly so bodies crowd
+    /// towards the centre
+    Disk,
+    /// Uniformly over the annulus between two radii
+    Ring { inner: Scalar, outer: Scalar },
+    /// Normally distributed with standard deviation `sigma` along each axis
+    Gaussian { sigma: Scalar },
+    /// Uniformly over the square from `-half_width` to `half_width`
+    Uniform { half_width: Scalar },
+}
+
+impl Shape {
+    /// Draw one position
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> [Scalar; 2] {
+        match *self {
+            Shape::Disk => {
+                let r = rng.gen::<Scalar>() * 2.0 - 1.0; // Range [-1, 1]
+                let theta = 2.0 * PI * rng.gen::<Scalar>();
+                [r * theta.cos(), r * theta.sin()]
+            }
+            Shape::Ring { inner, outer } => {
+                // Uniform in r² gives a uniform density over the area
+                let r_sq = inner * inner + rng.gen::<Scalar>() * (outer * outer - inner * inner);
+                let theta = 2.0 * PI * rng.gen::<Scalar>();
+                let r = r_sq.sqrt();
+                [r * theta.cos(), r * theta.sin()]
+            }
+            Shape::Gaussian { sigma } => {
+                let [x, y] = gaussian_pair(rng);
+                [sigma * x, sigma * y]
+            }
+            Shape::Uniform { half_width } => [
+                half_width * (rng.gen::<Scalar>() * 2.0 - 1.0),
+                half_width * (rng.gen::<Scalar>() * 2.0 - 1.0),
+            ],
+        }
+    }
+}
+
+/// Slope of the Salpeter (1955) initial mass function, `dN/dm ∝ m^-2.35`
+pub const SALPETER_ALPHA: Scalar = 2.35;
+
+/// Distribution random body masses are drawn from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MassFunction {
+    /// Every body gets `mass`
+    Equal { mass: Scalar },
+    /// A power law `dN/dm ∝ m^-alpha` between `min` and `max`, such as the
+    /// Salpeter mass function (`alpha = SALPETER_ALPHA`)
+    PowerLaw { alpha: Scalar, min: Scalar, max: Scalar },
+}
+
+impl MassFunction {
+    /// Draw one mass
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> Scalar {
+        match *self {
+            MassFunction::Equal { mass } => mass,
+            MassFunction::PowerLaw { alpha, min, max } => {
+                // Inverting the cumulative distribution; alpha = 1 is
+                // uniform in log m
+                let u = rng.gen::<Scalar>();
+                if (alpha - 1.0).abs() < 1e-6 {
+                    min * (max / min).powf(u)
+                } else {
+                    let k = 1.0 - alpha;
+                    (min.powf(k) + u * (max.powf(k) - min.powf(k))).powf(1.0 / k)
+                }
+            }
+        }
+    }
+}
+
+/// A cold, uniformly filled disk for gravitational collapse demos.
+///
+/// `n` equal-mass bodies are placed uniformly within `radius` of the origin,
+/// sharing `total_mass`. Bodies start at rest apart from a solid-body rotation
+/// with angular velocity `rotation`, so the kinetic energy is exactly
+/// `0.5 * rotation² * Σ m r²`. Without rotation the disk collapses to a
+/// point; with it the collapse is halted into a rotating disk.
+///
+/// The first collapse produces a sharp spike in the forces, so use a small
+/// timestep (and some softening) to get through it without blowups.
+pub fn cold_collapse(n: usize, radius: Scalar, total_mass: Scalar, rotation: Scalar) -> Vec<Body> {
+    cold_collapse_with_rng(&mut rand::thread_rng(), n, radius, total_mass, rotation)
+}
+
+/// `cold_collapse` using the given random number generator
+pub fn cold_collapse_with_rng<R: Rng>(
+    rng: &mut R,
+    n: usize,
+    radius: Scalar,
+    total_mass: Scalar,
+    rotation: Scalar,
+) -> Vec<Body> {
+    let mass = total_mass / n.max(1) as Scalar;
+
+    (0..n)
+        .map(|_| {
+            // sqrt gives a uniform density over the disk area
+            let r = radius * rng.gen::<Scalar>().sqrt();
+            let theta = 2.0 * PI * rng.gen::<Scalar>();
+            let x = r * theta.cos();
+            let y = r * theta.sin();
+
+            Body::new(mass, x, y, -y * rotation, x * rotation)
+        })
+        .collect()
+}
+
+/// Fraction of the mass of the infinite Plummer disk that `plummer` samples;
+/// the rest is spread thinly out to infinity
+const PLUMMER_MASS_FRACTION: Scalar = 0.999;
+
+/// A non-rotating star cluster in virial equilibrium: the 2D counterpart of
+/// a Plummer sphere.
+///
+/// `n` equal-mass bodies sharing `total_mass` follow the surface density
+/// `Σ ∝ (1 + r²/a²)^(-3/2)` with scale radius `a` (a Kuzmin disk), whose
+/// potential in the plane is exactly the Plummer potential `-G M / √(r² + a²)`.
+/// Velocities are isotropic Gaussians with the dispersion that solves the
+/// Jeans equation, `σ² = -Φ / 4` per component, so the virial ratio is 1
+/// apart from sampling noise (and softening, if it is not small against
+/// `a²`). The outermost 0.1% of the mass is left out, which still puts a few
+/// bodies hundreds of `a` out. The centre of mass is at rest at the origin.
+pub fn plummer(n: usize, scale_radius: Scalar, total_mass: Scalar, g: Scalar) -> Vec<Body> {
+    plummer_with_rng(&mut rand::thread_rng(), n, scale_radius, total_mass, g)
+}
+
+/// `plummer` using the given random number generator
+pub fn plummer_with_rng<R: Rng>(
+    rng: &mut R,
+    n: usize,
+    scale_radius: Scalar,
+    total_mass: Scalar,
+    g: Scalar,
+) -> Vec<Body> {
+    let mass = total_mass / n.max(1) as Scalar;
+
+    let mut bodies: Vec<Body> = (0..n)
+        .map(|_| {
+            // Invert the enclosed mass fraction 1 - a / √(r² + a²)
+            let fraction = PLUMMER_MASS_FRACTION * rng.gen::<Scalar>();
+            let r = scale_radius * ((1.0 - fraction).powi(-2) - 1.0).sqrt();
+            let theta = 2.0 * PI * rng.gen::<Scalar>();
+
+            let sigma = (g * total_mass / (4.0 * (r * r + scale_radius * scale_radius).sqrt())).sqrt();
+            let [vx, vy] = gaussian_pair(rng);
+
+            Body::new(mass, r * theta.cos(), r * theta.sin(), sigma * vx, sigma * vy)
+        })
+        .collect();
+
+    // Remove the sampling noise in the centre of mass and its motion
+    let count = bodies.len().max(1) as Scalar;
+    let mut mean = [0.0; 4];
+    for body in &bodies {
+        mean[0] += body.position[0] / count;
+        mean[1] += body.position[1] / count;
+        mean[2] += body.velocity[0] / count;
+        mean[3] += body.velocity[1] / count;
+    }
+    for body in &mut bodies {
+        body.position[0] -= mean[0];
+        body.position[1] -= mean[1];
+        body.velocity[0] -= mean[2];
+        body.velocity[1] -= mean[3];
+    }
+
+    bodies
+}
+
+/// Two Plummer clusters on a collision course.
+///
+/// The `n` equal-mass bodies sharing `total_mass` are split between two
+/// `plummer` clusters of scale radius `scale_radius` (the first gets the odd
+/// body). Their centres of mass start `separation` apart along x and offset
+/// by the impact parameter `impact` along y, and close along x at the
+/// relative speed `velocity`. The combined centre of mass is at rest at the
+/// origin.
+#[allow(clippy::too_many_arguments)]
+pub fn colliding_clusters(
+    n: usize,
+    scale_radius: Scalar,
+    total_mass: Scalar,
+    g: Scalar,
+    separation: Scalar,
+    velocity: Scalar,
+    impact: Scalar,
+) -> Vec<Body> {
+    colliding_clusters_with_rng(
+        &mut rand::thread_rng(), n, scale_radius, total_mass, g, separation, velocity, impact,
+    )
+}
+
+/// `colliding_clusters` using the given random number generator
+#[allow(clippy::too_many_arguments)]
+pub fn colliding_clusters_with_rng<R: Rng>(
+    rng: &mut R,
+    n: usize,
+    scale_radius: Scalar,
+    total_mass: Scalar,
+    g: Scalar,
+    separation: Scalar,
+    velocity: Scalar,
+    impact: Scalar,
+) -> Vec<Body> {
+    let counts = [n - n / 2, n / 2];
+    let masses = counts.map(|count| total_mass * count as Scalar / n.max(1) as Scalar);
+    let total = masses[0] + masses[1];
+    if total <= 0.0 {
+        return Vec::new();
+    }
+
+    // Each cluster is offset from the centre of mass in proportion to the
+    // other's mass, so the system's centre of mass and momentum stay zero
+    let mut bodies = Vec::with_capacity(n);
+    for (i, sign) in [(0, -1.0), (1, 1.0)] {
+        let share = masses[1 - i] / total;
+        let offset = [sign * separation * share, sign * impact * share];
+        let drift = -sign * velocity * share;
+
+        let mut cluster = plummer_with_rng(rng, counts[i], scale_radius, masses[i], g);
+        for body in &mut cluster {
+            body.position[0] += offset[0];
+            body.position[1] += offset[1];
+            body.velocity[0] += drift;
+        }
+        bodies.append(&mut cluster);
+    }
+
+    bodies
+}
+
+/// Two independent standard normal samples (Box-Muller)
+fn gaussian_pair<R: Rng>(rng: &mut R) -> [Scalar; 2] {
+    // 1 - u lies in (0, 1], keeping the logarithm finite
+    let radius = (-2.0 * (1.0 - rng.gen::<Scalar>()).ln()).sqrt();
+    let theta = 2.0 * PI * rng.gen::<Scalar>();
+    [radius * theta.cos(), radius * theta.sin()]
+}
+
+/// `n` equal-mass central bodies on circular orbits about the origin.
+///
+/// The bodies share `total_mass` and sit at the corners of a regular polygon
+/// of circumradius `radius`, each moving counter-clockwise at the speed that
+/// balances the pull of the others (with gravitational constant `g`), so two
+/// bodies form a circular binary. A single body is placed at rest at the
+/// origin.
+pub fn central_ring(n: usize, total_mass: Scalar, radius: Scalar, g: Scalar) -> Vec<Body> {
+    if n == 1 {
+        return vec![Body::new(total_mass, 0.0, 0.0, 0.0, 0.0)];
+    }
+
+    let mass = total_mass / n as Scalar;
+    // Inward pull on one body from the other n - 1 is g m / r² times this sum
+    let geometry: Scalar = (1..n)
+        .map(|k| 1.0 / (PI * k as Scalar / n as Scalar).sin())
+        .sum::<Scalar>()
+        / 4.0;
+    let speed = (g * mass * geometry / radius).sqrt();
+
+    (0..n)
+        .map(|i| {
+            let theta = 2.0 * PI * i as Scalar / n as Scalar;
+            let (sin, cos) = theta.sin_cos();
+            Body::new(mass, radius * cos, radius * sin, -speed * sin, speed * cos)
+        })
+        .collect()
+}
+
+/// An `nx` by `ny` square grid of bodies of `mass` each, `spacing` apart and
+/// at rest, centred on the origin.
+///
+/// Bodies are ordered row by row from the bottom left. A lattice is a clean
+/// deterministic input for checking the integrator and forces, and since
+/// many bodies land exactly on tree cell boundaries it exercises how the tree
+/// breaks ties.
+pub fn lattice(nx: usize, ny: usize, spacing: Scalar, mass: Scalar) -> Vec<Body> {
+    let x0 = -0.5 * spacing * nx.saturating_sub(1) as Scalar;
+    let y0 = -0.5 * spacing * ny.saturating_sub(1) as Scalar;
+    (0..ny)
+        .flat_map(|j| (0..nx).map(move |i| (i, j)))
+        .map(|(i, j)| Body::new(mass, x0 + spacing * i as Scalar, y0 + spacing * j as Scalar, 0.0, 0.0))
+        .collect()
+}
+
+/// `n` test particles of `mass` each, scattered over an existing system.
+///
+/// The particles are placed uniformly within a disk around the centre of mass
+/// of `bodies`, out to its farthest body (radius 1 if they all coincide). Each
+/// is set on a circular orbit about the centre of mass, using the mass of
+/// `bodies` enclosed within its radius and turning the same way as the
+/// system. Keep `mass` small to probe the system's potential without
+/// disturbing it.
+pub fn test_particles(bodies: &[Body], n: usize, mass: Scalar, g: Scalar) -> Vec<Body> {
+    test_particles_with_rng(&mut rand::thread_rng(), bodies, n, mass, g)
+}
+
+/// `test_particles` using the given random number generator
+pub fn test_particles_with_rng<R: Rng>(
+    rng: &mut R,
+    bodies: &[Body],
+    n: usize,
+    mass: Scalar,
+    g: Scalar,
+) -> Vec<Body> {
+    let total_mass: Scalar = bodies.iter().map(|b| b.mass).sum();
+    let (center, drift) = if total_mass > 0.0 {
+        let weighted = |f: fn(&Body) -> [Scalar; 2]| {
+            let sum = bodies.iter().fold([0.0, 0.0], |acc, b| {
+                let v = f(b);
+                [acc[0] + b.mass * v[0], acc[1] + b.mass * v[1]]
+            });
+            [sum[0] / total_mass, sum[1] / total_mass]
+        };
+        (weighted(|b| b.position), weighted(|b| b.velocity))
+    } else {
+        ([0.0, 0.0], [0.0, 0.0])
+    };
+
+    // Radius and mass of each body about the centre of mass, by radius,
+    // and the system's sense of rotation
+    let mut shells: Vec<(Scalar, Scalar)> = Vec::with_capacity(bodies.len());
+    let mut angular_momentum = 0.0;
+    for body in bodies {
+        let x = body.position[0] - center[0];
+        let y = body.position[1] - center[1];
+        shells.push(((x * x + y * y).sqrt(), body.mass));
+        angular_momentum += body.mass
+            * (x * (body.velocity[1] - drift[1]) - y * (body.velocity[0] - drift[0]));
+    }
+    shells.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let enclosed: Vec<Scalar> = shells
+        .iter()
+        .scan(0.0, |sum, &(_, m)| {
+            *sum += m;
+            Some(*sum)
+        })
+        .collect();
+    let radius = shells.last().map_or(0.0, |&(r, _)| r);
+    let radius = if radius > 0.0 { radius } else { 1.0 };
+    let turn = if angular_momentum < 0.0 { -1.0 } else { 1.0 };
+
+    (0..n)
+        .map(|_| {
+            let r = radius * rng.gen::<Scalar>().sqrt();
+            let theta = 2.0 * PI * rng.gen::<Scalar>();
+            let (sin, cos) = theta.sin_cos();
+
+            let inside = shells.partition_point(|&(shell_r, _)| shell_r < r);
+            let mass_within = if inside > 0 { enclosed[inside - 1] } else { 0.0 };
+            let speed = if r > 0.0 { turn * (g * mass_within / r).sqrt() } else { 0.0 };
+
+            Body::new(
+                mass,
+                center[0] + r * cos,
+                center[1] + r * sin,
+                drift[0] - speed * sin,
+                drift[1] + speed * cos,
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use crate::Simulation;
+
+    fn separation(a: &Body, b: &Body) -> Scalar {
+        let dx = a.position[0] - b.position[0];
+        let dy = a.position[1] - b.position[1];
+        (dx * dx + dy * dy).sqrt()
+    }
+
+    #[test]
+    fn test_central_ring_binary_is_bound() {
+        let bodies = central_ring(2, 2.0, 0.5, 1.0);
+        assert_eq!(bodies.len(), 2);
+
+        let mut sim = Simulation::new(bodies, 1e-3, 1.0, 0.0, 0.5);
+        assert!(sim.total_energy() < 0.0);
+
+        // Circular orbit: period 2π r / v with v = sqrt(g m / 4r) = 1
+        let steps = (2.0 * PI * 0.5 / 1e-3) as usize;
+        for _ in 0..steps {
+            sim.step();
+            let d = separation(&sim.bodies()[0], &sim.bodies()[1]);
+            assert!((d - 1.0).abs() < 1e-3, "separation {}", d);
+        }
+        let com: Scalar = sim.bodies().iter().map(|b| b.position[0] + b.position[1]).sum();
+        assert!(com.abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_central_ring_single_body_at_rest() {
+        let bodies = central_ring(1, 5.0, 0.5, 1.0);
+        assert_eq!(bodies.len(), 1);
+        assert_eq!(bodies[0].position, [0.0, 0.0]);
+        assert_eq!(bodies[0].velocity, [0.0, 0.0]);
+        assert_eq!(bodies[0].mass, 5.0);
+    }
+
+    #[test]
+    fn test_lattice_count_and_spacing() {
+        let bodies = lattice(5, 3, 0.5, 2.0);
+        assert_eq!(bodies.len(), 15);
+        assert_eq!(bodies[0].position, [-1.0, -0.5]);
+        assert_eq!(bodies[14].position, [1.0, 0.5]);
+        assert!(bodies.iter().all(|b| b.mass == 2.0 && b.velocity == [0.0, 0.0]));
+
+        // Every body's nearest neighbour is one spacing away
+        for (i, body) in bodies.iter().enumerate() {
+            let nearest = bodies
+                .iter()
+                .enumerate()
+                .filter(|&(j, _)| j != i)
+                .map(|(_, other)| separation(body, other))
+                .fold(Scalar::INFINITY, Scalar::min);
+            assert!((nearest - 0.5).abs() < 1e-12, "{}", nearest);
+        }
+
+        // Bodies on cell boundaries all find a leaf of their own
+        let sim = Simulation::new(lattice(9, 9, 1.0, 1.0), 0.01, 1.0, 0.01, 0.5);
+        assert_eq!(sim.get_tree().body_count(), 81);
+        assert!(lattice(0, 4, 1.0, 1.0).is_empty());
+    }
+
+    #[test]
+    fn test_test_particles_orbit_central_mass() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(3);
+        // A heavy body moving at (1, 0), and a light one setting the extent
+        let system = vec![
+            Body::new(100.0, 2.0, 3.0, 1.0, 0.0),
+            Body::new(1e-9, 6.0, 3.0, 1.0, 0.0),
+        ];
+        let particles = test_particles_with_rng(&mut rng, &system, 500, 1e-6, 0.5);
+        assert_eq!(particles.len(), 500);
+
+        for p in &particles {
+            let (x, y) = (p.position[0] - 2.0, p.position[1] - 3.0);
+            let r = (x * x + y * y).sqrt();
+            assert!(r <= 4.0 + 1e-6);
+            assert_eq!(p.mass, 1e-6);
+
+            // Circular about the heavy body, relative to its motion
+            let (vx, vy) = (p.velocity[0] - 1.0, p.velocity[1]);
+            let expected = (0.5 * 100.0 / r).sqrt();
+            assert!(((vx * vx + vy * vy).sqrt() - expected).abs() < 1e-3 * expected);
+            assert!(x * vy - y * vx >= 0.0);
+        }
+    }
+
+    #[test]
+    fn test_colliding_clusters_start_apart() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+        let bodies = colliding_clusters_with_rng(&mut rng, 501, 1.0, 2.0, 1.0, 10.0, 0.5, 2.0);
+        assert_eq!(bodies.len(), 501);
+
+        let centre = |cluster: &[Body]| {
+            let mass: Scalar = cluster.iter().map(|b| b.mass).sum();
+            let weighted = |f: fn(&Body) -> [Scalar; 2]| {
+                cluster.iter().fold([0.0, 0.0], |acc, b| {
+                    let v = f(b);
+                    [acc[0] + b.mass * v[0] / mass, acc[1] + b.mass * v[1] / mass]
+                })
+            };
+            (weighted(|b| b.position), weighted(|b| b.velocity))
+        };
+        let (first, first_velocity) = centre(&bodies[..251]);
+        let (second, second_velocity) = centre(&bodies[251..]);
+        let (whole, whole_velocity) = centre(&bodies);
+
+        assert!((second[0] - first[0] - 10.0).abs() < 1e-4);
+        assert!((second[1] - first[1] - 2.0).abs() < 1e-4);
+        assert!((first_velocity[0] - second_velocity[0] - 0.5).abs() < 1e-5);
+        assert!(whole.iter().chain(&whole_velocity).all(|v| v.abs() < 1e-5));
+    }
+
+    #[test]
+    fn test_shapes_stay_in_their_regions() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(5);
+        let radius = |[x, y]: [Scalar; 2]| x.hypot(y);
+
+        for _ in 0..1000 {
+            assert!(radius(Shape::Disk.sample(&mut rng)) <= 1.0);
+
+            let r = radius(Shape::Ring { inner: 2.0, outer: 3.0 }.sample(&mut rng));
+            assert!((2.0 - 1e-4..=3.0 + 1e-4).contains(&r), "ring radius {}", r);
+
+            let [x, y] = Shape::Uniform { half_width: 0.5 }.sample(&mut rng);
+            assert!(x.abs() <= 0.5 && y.abs() <= 0.5);
+        }
+
+        // About 39% of a 2D Gaussian lies within one sigma of the centre
+        let sigma = 0.2;
+        let samples: Vec<[Scalar; 2]> = (0..10_000)
+            .map(|_| Shape::Gaussian { sigma }.sample(&mut rng))
+            .collect();
+        let within = samples.iter().filter(|&&p| radius(p) < sigma).count() as Scalar / 10_000.0;
+        assert!((within - 0.393).abs() < 0.02, "{} within sigma", within);
+        let variance = samples.iter().map(|p| p[0] * p[0]).sum::<Scalar>() / 10_000.0;
+        assert!((variance.sqrt() - sigma).abs() < 0.03 * sigma);
+    }
+
+    #[test]
+    fn test_power_law_masses_follow_slope() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(6);
+        let salpeter = MassFunction::PowerLaw { alpha: SALPETER_ALPHA, min: 1.0, max: 100.0 };
+        let masses: Vec<Scalar> = (0..50_000).map(|_| salpeter.sample(&mut rng)).collect();
+        assert!(masses.iter().all(|m| (1.0..=100.0).contains(m)));
+
+        // Number per unit mass in [1, 2) and [10, 20) differ by 10^-alpha
+        let density = |low: Scalar| masses.iter().filter(|&&m| m >= low && m < 2.0 * low).count() as Scalar / low;
+        let slope = -(density(10.0) / density(1.0)).log10();
+        assert!((slope - SALPETER_ALPHA).abs() < 0.1, "slope {}", slope);
+
+        // alpha = 1 is uniform in log m: half the bodies below 10
+        let log_uniform = MassFunction::PowerLaw { alpha: 1.0, min: 1.0, max: 100.0 };
+        let below = (0..10_000).filter(|_| log_uniform.sample(&mut rng) < 10.0).count();
+        assert!((below as Scalar / 10_000.0 - 0.5).abs() < 0.02, "{} below 10", below);
+
+        assert_eq!(MassFunction::Equal { mass: 3.0 }.sample(&mut rng), 3.0);
+    }
+
+    #[test]
+    fn test_plummer_is_virialized() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(4);
+        let bodies = plummer_with_rng(&mut rng, 4000, 0.5, 10.0, 2.0);
+        assert_eq!(bodies.len(), 4000);
+
+        // Half the mass within r = a√3 (where a / √(r² + a²) = 1/2)
+        let within = bodies
+            .iter()
+            .filter(|b| b.position[0].hypot(b.position[1]) < 0.5 * (3.0 as Scalar).sqrt())
+            .count();
+        assert!((within as Scalar / 4000.0 - 0.5).abs() < 0.03, "{} within", within);
+
+        // Softening (ε² here) would make the potential noticeably shallower
+        let sim = Simulation::new(bodies, 0.01, 2.0, 1e-6, 0.3);
+        let ratio = sim.virial_ratio();
+        assert!((ratio - 1.0).abs() < 0.05, "virial ratio {}", ratio);
+    }
+
+    #[test]
+    fn test_cold_collapse_within_radius() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(1);
+        let bodies = cold_collapse_with_rng(&mut rng, 1000, 2.0, 10.0, 0.0);
+
+        assert_eq!(bodies.len(), 1000);
+        let total_mass: Scalar = bodies.iter().map(|b| b.mass).sum();
+        assert!((total_mass - 10.0).abs() < 1e4 * Scalar::EPSILON);
+        for body in &bodies {
+            let r = (body.position[0].powi(2) + body.position[1].powi(2)).sqrt();
+            assert!(r <= 2.0);
+            assert_eq!(body.velocity, [0.0, 0.0]);
+        }
+    }
+
+    #[test]
+    fn test_cold_collapse_kinetic_energy() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(2);
+        let (radius, total_mass, rotation) = (1.5, 4.0, 0.2);
+        let bodies = cold_collapse_with_rng(&mut rng, 20_000, radius, total_mass, rotation);
+
+        // Exact for solid-body rotation
+        let inertia: Scalar = bodies
+            .iter()
+            .map(|b| b.mass * (b.position[0].powi(2) + b.position[1].powi(2)))
+            .sum();
+        let sim = Simulation::new(bodies, 0.1, 1.0, 0.01, 0.5);
+        let ke = sim.kinetic_energy();
+        assert!((ke - 0.5 * rotation * rotation * inertia).abs() < 1e4 * Scalar::EPSILON * ke);
+
+        // And close to the uniform disk value M ω² R² / 4
+        let expected = total_mass * rotation * rotation * radius * radius / 4.0;
+        assert!(((ke - expected) / expected).abs() < 0.03, "{} vs {}", ke, expected);
+    }
+}
@@ -0,0 +1,91 @@
+//! Float functions the numerical core needs that `core` does not provide.
+//!
+//! With the `std` feature the standard library's inherent methods are used
+//! and this module is not compiled. Without it, modules import `Float` to get
+//! the `libm` versions under the same method names.
+
+pub(crate) trait Float {
+    fn sqrt(self) -> Self;
+    fn atan(self) -> Self;
+    fn powi(self, n: i32) -> Self;
+    fn floor(self) -> Self;
+    fn ln(self) -> Self;
+}
+
+impl Float for f64 {
+    fn sqrt(self) -> Self {
+        libm::sqrt(self)
+    }
+
+    fn atan(self) -> Self {
+        libm::atan(self)
+    }
+
+    fn powi(self, n: i32) -> Self {
+        libm::pow(self, n as f64)
+    }
+
+    fn floor(self) -> Self {
+        libm::floor(self)
+    }
+
+    fn ln(self) -> Self {
+        libm::log(self)
+    }
+}
+
+impl Float for f32 {
+    fn sqrt(self) -> Self {
+        libm::sqrtf(self)
+    }
+
+    fn atan(self) -> Self {
+        libm::atanf(self)
+    }
+
+    fn powi(self, n: i32) -> Self {
+        libm::powf(self, n as f32)
+    }
+
+    fn floor(self) -> Self {
+        libm::floorf(self)
+    }
+
+    fn ln(self) -> Self {
+        libm::logf(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Scalar;
+
+    #[test]
+    fn test_matches_std() {
+        for &x in &[0.0, 1e-310, 1e-20, 0.1, 0.25, 0.5, 1.0, 2.0, 3.7, 1e5, 1e300, -0.3, -7.5] {
+            let x = x as Scalar;
+            if !x.is_finite() {
+                continue; // 1e300 in single precision
+            }
+            let close = |a: Scalar, b: Scalar| (a - b).abs() <= 4.0 * Scalar::EPSILON * b.abs().max(Scalar::MIN_POSITIVE);
+            if x >= 0.0 {
+                assert!(close(Float::sqrt(x), x.sqrt()), "sqrt({})", x);
+            }
+            assert!(close(Float::atan(x), x.atan()), "atan({})", x);
+            assert_eq!(Float::floor(x), x.floor(), "floor({})", x);
+            if x > 0.0 {
+                // Relative to 1 since ln is near zero around x = 1
+                let ln = Float::ln(x);
+                assert!((ln - x.ln()).abs() <= 4.0 * Scalar::EPSILON * x.ln().abs().max(1.0), "ln({})", x);
+            }
+            if (1e-5..1e5).contains(&x.abs()) {
+                assert!(close(Float::powi(x, 3), x.powi(3)), "powi({}, 3)", x);
+                assert!(close(Float::powi(x, -2), x.powi(-2)), "powi({}, -2)", x);
+            }
+        }
+        assert!(Float::sqrt(-1.0 as Scalar).is_nan());
+        assert_eq!(Float::floor(-0.5 as Scalar), -1.0);
+        assert_eq!(Float::ln(1.0 as Scalar), 0.0);
+    }
+}
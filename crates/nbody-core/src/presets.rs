@@ -0,0 +1,154 @@
+//! Classic few-body test problems with known solutions.
+//!
+//! Each preset is returned in its centre-of-mass frame and is exact for the
+//! gravitational constant it is given, so runs with zero softening can be
+//! compared directly against the analytic behaviour.
+
+use crate::body::Body;
+use crate::Scalar;
+
+/// Period of the figure-eight choreography for G = 1 and unit masses
+pub const FIGURE_EIGHT_PERIOD: Scalar = 6.325_913_98;
+
+/// Two bodies on a circular orbit about their common centre of mass.
+///
+/// The bodies start on the x axis `separation` apart and move along y, so the
+/// orbit has period `2π sqrt(separation³ / (G (m1 + m2)))`.
+pub fn two_body_circular(g: Scalar, m1: Scalar, m2: Scalar, separation: Scalar) -> Vec<Body> {
+    let total = m1 + m2;
+    let speed = (g * total / separation).sqrt();
+
+    vec![
+        Body::new(
+            m1,
+            -separation * m2 / total, 0.0,
+            0.0, -speed * m2 / total,
+        ),
+        Body::new(
+            m2,
+            separation * m1 / total, 0.0,
+            0.0, speed * m1 / total,
+        ),
+    ]
+}
+
+/// The Chenciner-Montgomery figure-eight three-body choreography.
+///
+/// Uses unit masses; velocities are scaled by `sqrt(G)` so the same orbit is
+/// traced for any G, with period `FIGURE_EIGHT_PERIOD / sqrt(G)`.
+pub fn figure_eight(g: Scalar) -> Vec<Body> {
+    let (x, y) = (0.970_004_36, -0.243_087_53);
+    let (vx, vy) = (-0.932_407_37, -0.864_731_46);
+    let scale = g.sqrt();
+
+    vec![
+        Body::new(1.0, x, y, -0.5 * vx * scale, -0.5 * vy * scale),
+        Body::new(1.0, -x, -y, -0.5 * vx * scale, -0.5 * vy * scale),
+        Body::new(1.0, 0.0, 0.0, vx * scale, vy * scale),
+    ]
+}
+
+/// A hierarchical Sun-Earth-Moon-like triple on circular orbits.
+///
+/// Masses are in solar masses and distances in AU-like units: the "Earth"
+/// orbits at radius 1 and the "Moon" orbits the Earth at radius 0.00257.
+pub fn sun_earth_moon(g: Scalar) -> Vec<Body> {
+    let m_sun = 1.0;
+    let m_earth = 3.003e-6;
+    let m_moon = 3.694e-8;
+    let r_earth = 1.0;
+    let r_moon = 0.002_57;
+
+    let v_earth = (g * (m_sun + m_earth + m_moon) / r_earth).sqrt();
+    let v_moon = (g * (m_earth + m_moon) / r_moon).sqrt();
+
+    // Earth-Moon barycentre on a circular orbit, each body offset from it
+    let bary = m_earth + m_moon;
+    let mut bodies = vec![
+        Body::new(m_sun, 0.0, 0.0, 0.0, 0.0),
+        Body::new(
+            m_earth,
+            r_earth - r_moon * m_moon / bary, 0.0,
+            0.0, v_earth - v_moon * m_moon / bary,
+        ),
+        Body::new(
+            m_moon,
+            r_earth + r_moon * m_earth / bary, 0.0,
+            0.0, v_earth + v_moon * m_earth / bary,
+        ),
+    ];
+
+    to_com_frame(&mut bodies);
+    bodies
+}
+
+/// Shift positions and velocities so the centre of mass is at rest at the origin
+fn to_com_frame(bodies: &mut [Body]) {
+    let total: Scalar = bodies.iter().map(|b| b.mass).sum();
+    let mut com = [0.0; 2];
+    let mut vcom = [0.0; 2];
+    for body in bodies.iter() {
+        for k in 0..2 {
+            com[k] += body.mass * body.position[k] / total;
+            vcom[k] += body.mass * body.velocity[k] / total;
+        }
+    }
+    for body in bodies.iter_mut() {
+        for k in 0..2 {
+            body.position[k] -= com[k];
+            body.velocity[k] -= vcom[k];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Simulation;
+
+    fn distance(a: [Scalar; 2], b: [Scalar; 2]) -> Scalar {
+        ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2)).sqrt()
+    }
+
+    #[test]
+    fn test_two_body_separation_constant() {
+        let mut sim = Simulation::new(two_body_circular(1.0, 1.0, 0.5, 1.0), 1e-3, 1.0, 0.0, 0.0);
+
+        for _ in 0..5000 {
+            sim.step();
+            let d = distance(sim.bodies()[0].position, sim.bodies()[1].position);
+            assert!((d - 1.0).abs() < 1e-2, "separation drifted to {}", d);
+        }
+    }
+
+    #[test]
+    fn test_figure_eight_is_periodic() {
+        let initial = figure_eight(1.0);
+        let dt = 5e-4;
+        let steps = (FIGURE_EIGHT_PERIOD / dt).round() as usize;
+        let mut sim = Simulation::new(initial.clone(), dt, 1.0, 0.0, 0.0);
+
+        for period in 1..=3 {
+            for _ in 0..steps {
+                sim.step();
+            }
+            for (body, start) in sim.bodies().iter().zip(&initial) {
+                let err = distance(body.position, start.position);
+                assert!(err < 1e-2 * period as Scalar, "period {}: error {}", period, err);
+            }
+        }
+    }
+
+    #[test]
+    fn test_presets_in_com_frame() {
+        for bodies in [two_body_circular(1.0, 2.0, 1.0, 3.0), figure_eight(1.0), sun_earth_moon(1.0)] {
+            let mut momentum = [0.0; 2];
+            for body in &bodies {
+                momentum[0] += body.mass * body.velocity[0];
+                momentum[1] += body.mass * body.velocity[1];
+            }
+            assert!(momentum[0].abs() < 1e-12);
+            assert!(momentum[1].abs() < 1e-12);
+        }
+    }
+}
@@ -1,86 +1,209 @@
 use glow::*;
+use std::collections::BTreeMap;
 use std::sync::Arc;
-use crate::{Body, QuadTree};
+use crate::bloom::Bloom;
+use crate::{Body, NBodyError, QuadTree, Scalar};
+
+/// How bodies are colored
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorMode {
+    /// Every body in white
+    #[default]
+    Uniform,
+    /// Each species in its own palette color
+    Species,
+    /// Each body in its own `Body::color`
+    PerBody,
+}
+
+/// A square RGBA image drawn in place of each body's flat point, see
+/// `Renderer::set_sprite`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sprite {
+    size: u32,
+    rgba: Vec<u8>,
+}
+
+impl Sprite {
+    /// A sprite from `size` × `size` RGBA pixels, row by row. The color is
+    /// multiplied by the body's color, and the alpha sets how much of it
+    /// shows.
+    pub fn new(size: u32, rgba: Vec<u8>) -> Result<Self, NBodyError> {
+        let expected = size as usize * size as usize * 4;
+        if size == 0 || rgba.len() != expected {
+            return Err(NBodyError::Gl(format!(
+                "Sprite of size {} needs {} bytes of RGBA data, got {}",
+                size, expected, rgba.len()
+            )));
+        }
+        Ok(Sprite { size, rgba })
+    }
+
+    /// A white star glow: alpha falls off from the centre as a gaussian
+    /// with a standard deviation of a sixth of `size`, so it has all but
+    /// vanished at the edges
+    pub fn glow(size: u32) -> Self {
+        let size = size.max(1);
+        let sigma = size as f32 / 6.0;
+        let center = 0.5 * size as f32;
+        let rgba = (0..size * size)
+            .flat_map(|i| {
+                let x = (i % size) as f32 + 0.5 - center;
+                let y = (i / size) as f32 + 0.5 - center;
+                let alpha = (-(x * x + y * y) / (2.0 * sigma * sigma)).exp();
+                [255, 255, 255, (255.0 * alpha).round() as u8]
+            })
+            .collect();
+        Sprite { size, rgba }
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    pub fn rgba(&self) -> &[u8] {
+        &self.rgba
+    }
+}
 
 pub struct Renderer {
     gl: Arc<Context>,
     program: Program,
     vertex_buffer: Buffer,
+    color_buffer: Buffer,
     vertex_array: VertexArray,
     color_location: UniformLocation,
     point_size_location: UniformLocation,
+    use_sprite_location: UniformLocation,
+    sprite_texture: Option<Texture>,
     point_size: f32,
     fixed_scale: bool,
+    show_wireframe: bool,
+    tree_color_by_mass: bool,
+    color_mode: ColorMode,
+    highlighted: Option<usize>,
+    velocity_scale: Option<f32>,
+    render_sample: Option<usize>,
+    wire_width: f32,
+    wire_min_mass: f32,
+    max_line_width: f32,
+    viewport: (i32, i32),
+    aspect: f32,
+    bloom: Option<Bloom>,
+    time_tint: Option<f32>,
+    time: f32,
+}
+
+/// Mapping from world coordinates to clip space for one frame
+#[derive(Debug, Clone, PartialEq)]
+struct View {
+    /// Clip space units per world unit along the shorter window axis
+    scale: f32,
+    /// Factors squeezing the longer window axis so world units stay square
+    aspect: [f32; 2],
+    center: [f32; 2],
+}
+
+impl View {
+    fn new(scale: f32, center: [f32; 2], aspect: f32) -> Self {
+        let aspect = if aspect >= 1.0 { [1.0 / aspect, 1.0] } else { [1.0, aspect] };
+        View { scale, aspect, center }
+    }
+
+    fn to_clip(&self, x: f32, y: f32) -> [f32; 2] {
+        [
+            (x - self.center[0]) * self.scale * self.aspect[0],
+            (y - self.center[1]) * self.scale * self.aspect[1],
+        ]
+    }
+
+    fn to_world(&self, ndc: [f32; 2]) -> [f32; 2] {
+        [
+            ndc[0] / (self.scale * self.aspect[0]) + self.center[0],
+            ndc[1] / (self.scale * self.aspect[1]) + self.center[1],
+        ]
+    }
+}
+
+/// Clear color behind the bodies
+const BACKGROUND: [f32; 3] = [0.0, 0.0, 0.1];
+
+/// Saturation and value of the time tinted background, dark enough that
+/// bodies and the tree stay the brightest things on screen
+const TIME_TINT_SATURATION: f32 = 0.6;
+const TIME_TINT_VALUE: f32 = 0.15;
+
+/// Texture unit the sprite is bound to, one the bloom pass never uses, so
+/// its textures are never sampled while being rendered to
+const SPRITE_TEXTURE_UNIT: u32 = 2;
+
+/// Number of decades of mass fraction spanned by the tree color ramp
+const MASS_COLOR_DECADES: f32 = 6.0;
+
+/// GLSL version headers to compile shaders with, in order of preference.
+/// Shader sources are written without one so that they compile under each.
+#[cfg(target_arch = "wasm32")]
+const GLSL_HEADERS: &[&str] = &["#version 300 es\nprecision mediump float;\n"];
+
+/// GLSL version headers to compile shaders with, in order of preference:
+/// 4.10, then 3.30 for drivers limited to OpenGL 3.3. Shader sources are
+/// written without one, using only features both versions have.
+#[cfg(not(target_arch = "wasm32"))]
+const GLSL_HEADERS: &[&str] = &["#version 410\n", "#version 330\n"];
+
+const VERTEX_SHADER_SOURCE: &str = r#"
+layout (location = 0) in vec2 position;
+layout (location = 1) in vec3 bodyColor;
+uniform float pointSize;
+uniform vec4 color;
+out vec4 vColor;
+
+void main() {
+    gl_Position = vec4(position.xy, 0.0, 1.0);
+    gl_PointSize = pointSize;
+    vColor = color * vec4(bodyColor, 1.0);
 }
+"#;
+
+const FRAGMENT_SHADER_SOURCE: &str = r#"
+in vec4 vColor;
+uniform bool useSprite;
+uniform sampler2D sprite;
+out vec4 fragColor;
+
+void main() {
+    fragColor = useSprite ? vColor * texture(sprite, gl_PointCoord) : vColor;
+}
+"#;
+
+/// Body colors by species id, repeating for ids past the end
+const SPECIES_PALETTE: [[f32; 4]; 8] = [
+    [1.0, 1.0, 1.0, 1.0], // white
+    [0.4, 0.7, 1.0, 1.0], // blue
+    [1.0, 0.7, 0.3, 1.0], // orange
+    [0.5, 1.0, 0.5, 1.0], // green
+    [1.0, 0.5, 0.8, 1.0], // pink
+    [1.0, 1.0, 0.4, 1.0], // yellow
+    [0.6, 0.5, 1.0, 1.0], // violet
+    [0.4, 1.0, 1.0, 1.0], // cyan
+];
 
 impl Renderer {
     pub fn new(
         gl: Arc<Context>,
         point_size: f32,
         fixed_scale: bool,
-    ) -> Result<Self, String> {
+    ) -> Result<Self, NBodyError> {
         unsafe {
-            // Define shaders based on target platform
-            #[cfg(target_arch = "wasm32")]
-            let (vertex_shader_source, fragment_shader_source) = (
-                // WebGL (GLSL ES 300)
-                r#"#version 300 es
-                layout (location = 0) in vec2 position;
-                uniform float pointSize;
-                uniform vec4 color;
-                out vec4 vColor;
-
-                void main() {
-                    gl_Position = vec4(position.xy, 0.0, 1.0);
-                    gl_PointSize = pointSize;
-                    vColor = color;
-                }
-                "#,
-                r#"#version 300 es
-                precision mediump float;
-                in vec4 vColor;
-                out vec4 fragColor;
-
-                void main() {
-                    fragColor = vColor;
-                }
-                "#
-            );
+            log::debug!("Creating program...");
 
-            #[cfg(not(target_arch = "wasm32"))]
-            let (vertex_shader_source, fragment_shader_source) = (
-                // Desktop OpenGL (GLSL 410)
-                r#"#version 410
-                layout (location = 0) in vec2 position;
-                uniform float pointSize;
-                uniform vec4 color;
-                out vec4 vColor;
-
-                void main() {
-                    gl_Position = vec4(position.xy, 0.0, 1.0);
-                    gl_PointSize = pointSize;
-                    vColor = color;
-                }
-                "#,
-                r#"#version 410
-                in vec4 vColor;
-                out vec4 fragColor;
-
-                void main() {
-                    fragColor = vColor;
-                }
-                "#
-            );
-
-            println!("Creating program...");
-
-            let program = create_program(&gl, vertex_shader_source, fragment_shader_source)?;
+            let program = create_program(&gl, VERTEX_SHADER_SOURCE, FRAGMENT_SHADER_SOURCE)?;
 
             let vertex_array = gl.create_vertex_array()
-                .map_err(|e| format!("Failed to create vertex array: {}", e))?;
+                .map_err(|e| NBodyError::Gl(format!("Failed to create vertex array: {}", e)))?;
 
             let vertex_buffer = gl.create_buffer()
-                .map_err(|e| format!("Failed to create vertex buffer: {}", e))?;
+                .map_err(|e| NBodyError::Gl(format!("Failed to create vertex buffer: {}", e)))?;
 
             gl.bind_vertex_array(Some(vertex_array));
             gl.bind_buffer(ARRAY_BUFFER, Some(vertex_buffer));
@@ -95,177 +218,844 @@ impl Renderer {
                 0,          // offset
             );
 
+            // Per-vertex colors, only enabled while drawing ColorMode::PerBody;
+            // otherwise every vertex reads the constant white set here
+            let color_buffer = gl.create_buffer()
+                .map_err(|e| NBodyError::Gl(format!("Failed to create color buffer: {}", e)))?;
+            gl.bind_buffer(ARRAY_BUFFER, Some(color_buffer));
+            gl.vertex_attrib_pointer_f32(1, 3, FLOAT, false, 0, 0);
+            gl.vertex_attrib_3_f32(1, 1.0, 1.0, 1.0);
+
             let color_location = gl.get_uniform_location(program, "color")
-                .ok_or_else(|| "Failed to get color uniform location".to_string())?;
+                .ok_or_else(|| NBodyError::Gl("Failed to get color uniform location".to_string()))?;
 
             let point_size_location = gl.get_uniform_location(program, "pointSize")
-                .ok_or_else(|| "Failed to get pointSize uniform location".to_string())?;
+                .ok_or_else(|| NBodyError::Gl("Failed to get pointSize uniform location".to_string()))?;
+
+            let use_sprite_location = gl.get_uniform_location(program, "useSprite")
+                .ok_or_else(|| NBodyError::Gl("Failed to get useSprite uniform location".to_string()))?;
 
             // Initial setup
             gl.use_program(Some(program));
-            gl.clear_color(0.0, 0.0, 0.1, 1.0);
+            if let Some(sprite) = gl.get_uniform_location(program, "sprite") {
+                gl.uniform_1_i32(Some(&sprite), SPRITE_TEXTURE_UNIT as i32);
+            }
+            gl.clear_color(BACKGROUND[0], BACKGROUND[1], BACKGROUND[2], 1.0);
             gl.enable(BLEND);
             gl.enable(PROGRAM_POINT_SIZE);
             gl.blend_func(SRC_ALPHA, ONE_MINUS_SRC_ALPHA);
 
+            // Antialiased tree lines where the platform has them (WebGL does not)
+            #[cfg(not(target_arch = "wasm32"))]
+            let line_width_range = {
+                gl.enable(LINE_SMOOTH);
+                SMOOTH_LINE_WIDTH_RANGE
+            };
+            #[cfg(target_arch = "wasm32")]
+            let line_width_range = ALIASED_LINE_WIDTH_RANGE;
+
+            let mut line_widths = [1.0f32; 2];
+            gl.get_parameter_f32_slice(line_width_range, &mut line_widths);
+
+            let mut viewport = [0i32; 4];
+            gl.get_parameter_i32_slice(VIEWPORT, &mut viewport);
+
             Ok(Renderer {
                 gl,
                 program,
                 vertex_buffer,
+                color_buffer,
                 vertex_array,
                 color_location,
                 point_size_location,
+                use_sprite_location,
+                sprite_texture: None,
                 point_size,
                 fixed_scale,
+                show_wireframe: true,
+                tree_color_by_mass: false,
+                color_mode: ColorMode::default(),
+                highlighted: None,
+                velocity_scale: None,
+                render_sample: None,
+                wire_width: 1.0,
+                wire_min_mass: 0.0,
+                max_line_width: line_widths[1].max(1.0),
+                viewport: (viewport[2], viewport[3]),
+                aspect: viewport[2].max(1) as f32 / viewport[3].max(1) as f32,
+                bloom: None,
+                time_tint: None,
+                time: 0.0,
             })
         }
     }
 
+    /// Keep the view fixed around the origin instead of following the bodies
+    pub fn set_fixed_scale(&mut self, enabled: bool) {
+        self.fixed_scale = enabled;
+    }
+
+    /// Tint each tree node box by its total mass (log-scaled against the root)
+    pub fn set_tree_color_by_mass(&mut self, enabled: bool) {
+        self.tree_color_by_mass = enabled;
+    }
+
+    /// Set how bodies are colored
+    pub fn set_color_mode(&mut self, mode: ColorMode) {
+        self.color_mode = mode;
+    }
+
+    /// How bodies are colored
+    pub fn color_mode(&self) -> ColorMode {
+        self.color_mode
+    }
+
+    /// Set the diameter of body points in pixels, before any view scaling
+    pub fn set_point_size(&mut self, size: f32) {
+        self.point_size = size.max(0.0);
+    }
+
+    /// Diameter of body points in pixels, before any view scaling
+    pub fn point_size(&self) -> f32 {
+        self.point_size
+    }
+
+    /// Draw the body at `index` in a highlight color on top of the others
+    pub fn set_highlighted(&mut self, index: Option<usize>) {
+        self.highlighted = index;
+    }
+
+    /// Draw at most `max` bodies (every `ceil(n / max)`-th one), or all of
+    /// them with `None`. The simulation still advances every body; this only
+    /// thins out what is drawn. The subset is the same every frame as long as
+    /// the body count does not change.
+    pub fn set_render_sample(&mut self, max: Option<usize>) {
+        self.render_sample = max;
+    }
+
+    /// Show or hide the tree wireframe (shown by default)
+    pub fn set_wireframe(&mut self, enabled: bool) {
+        self.show_wireframe = enabled;
+    }
+
+    /// Whether the tree wireframe is drawn
+    pub fn wireframe(&self) -> bool {
+        self.show_wireframe
+    }
+
+    /// Set the width in pixels of the tree wireframe lines.
+    ///
+    /// Widths below 1 are drawn as 1. Widths beyond what the driver supports
+    /// for lines (often just 1, e.g. on core profile macOS) are drawn as thin
+    /// quads instead.
+    pub fn set_wireframe_width(&mut self, width: f32) {
+        self.wire_width = width.max(1.0);
+    }
+
+    /// Draw only the tree nodes holding at least `fraction` of the total mass
+    /// (0, the default, draws every node). Child nodes never hold more mass
+    /// than their parent, so a light node's whole subtree is skipped, which
+    /// keeps the wireframe legible and cheap to draw for large trees.
+    pub fn set_wireframe_min_mass(&mut self, fraction: f32) {
+        self.wire_min_mass = fraction.clamp(0.0, 1.0);
+    }
+
+    /// The fraction of the total mass below which tree nodes are not drawn
+    pub fn wireframe_min_mass(&self) -> f32 {
+        self.wire_min_mass
+    }
+
+    /// Draw each body's velocity as a line from its position to
+    /// `position + velocity * scale` (in world units), or hide them with `None`
+    pub fn set_show_velocities(&mut self, scale: Option<f32>) {
+        self.velocity_scale = scale;
+    }
+
+    /// Slowly cycle the background hue, once every `period` units of
+    /// simulation time, as a visual clock for long timelapses, or keep the
+    /// plain background with `None`. The time is set with `set_time`.
+    pub fn set_time_tint(&mut self, period: Option<f32>) {
+        self.time_tint = period.filter(|period| *period > 0.0);
+    }
+
+    /// Set the simulation time shown by the time tint
+    pub fn set_time(&mut self, time: f32) {
+        self.time = time;
+    }
+
+    /// Enable or disable the bloom post-process.
+    ///
+    /// Bodies are rendered to an offscreen texture whose bright parts are
+    /// blurred and added back with the given `strength`, giving points a glow.
+    pub fn set_bloom(&mut self, enabled: bool, strength: f32) -> Result<(), NBodyError> {
+        match (enabled, self.bloom.as_mut()) {
+            (true, Some(bloom)) => bloom.set_strength(strength),
+            (true, None) => {
+                let (width, height) = self.viewport;
+                self.bloom = Some(Bloom::new(&self.gl, width, height, strength)?);
+            }
+            (false, _) => {
+                if let Some(bloom) = self.bloom.take() {
+                    bloom.delete(&self.gl);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Draw bodies as textured point sprites, or as flat points with `None`
+    /// (the default).
+    ///
+    /// Sprites are blended additively, so overlapping glows brighten each
+    /// other like a real star field. `Sprite::glow` makes a suitable image;
+    /// a larger point size than for flat points suits it.
+    pub fn set_sprite(&mut self, sprite: Option<&Sprite>) -> Result<(), NBodyError> {
+        unsafe {
+            if let Some(texture) = self.sprite_texture.take() {
+                self.gl.delete_texture(texture);
+            }
+            let Some(sprite) = sprite else {
+                return Ok(());
+            };
+
+            let texture = self.gl.create_texture()
+                .map_err(|e| NBodyError::Gl(format!("Failed to create sprite texture: {}", e)))?;
+            self.gl.active_texture(TEXTURE0 + SPRITE_TEXTURE_UNIT);
+            self.gl.bind_texture(TEXTURE_2D, Some(texture));
+            self.gl.tex_image_2d(
+                TEXTURE_2D,
+                0,
+                RGBA8 as i32,
+                sprite.size as i32,
+                sprite.size as i32,
+                0,
+                RGBA,
+                UNSIGNED_BYTE,
+                Some(&sprite.rgba),
+            );
+            self.gl.tex_parameter_i32(TEXTURE_2D, TEXTURE_MIN_FILTER, LINEAR as i32);
+            self.gl.tex_parameter_i32(TEXTURE_2D, TEXTURE_MAG_FILTER, LINEAR as i32);
+            self.gl.tex_parameter_i32(TEXTURE_2D, TEXTURE_WRAP_S, CLAMP_TO_EDGE as i32);
+            self.gl.tex_parameter_i32(TEXTURE_2D, TEXTURE_WRAP_T, CLAMP_TO_EDGE as i32);
+            self.gl.active_texture(TEXTURE0);
+            self.sprite_texture = Some(texture);
+        }
+        Ok(())
+    }
+
+    /// Set the width / height ratio of the window, so that the view keeps
+    /// world units square and fits the bodies along the shorter side
+    pub fn set_aspect(&mut self, aspect: f32) {
+        if aspect.is_finite() && aspect > 0.0 {
+            self.aspect = aspect;
+        }
+    }
+
+    /// Update the viewport, aspect ratio and any offscreen targets after a
+    /// window resize
+    pub fn resize(&mut self, width: u32, height: u32) -> Result<(), NBodyError> {
+        self.viewport = (width as i32, height as i32);
+        self.set_aspect(width.max(1) as f32 / height.max(1) as f32);
+        unsafe {
+            self.gl.viewport(0, 0, width as i32, height as i32);
+        }
+        if let Some(bloom) = self.bloom.as_mut() {
+            bloom.resize(&self.gl, width as i32, height as i32)?;
+        }
+        Ok(())
+    }
+
+    /// Mapping from world coordinates to clip space for this tree
+    fn view_transform(&self, tree: &QuadTree) -> View {
+        let scale = if self.fixed_scale {
+            0.8f32
+        } else {
+            let bounds = tree.get_bounds();
+            let width = (bounds.max[0] - bounds.min[0]).abs() as f32;
+            let height = (bounds.max[1] - bounds.min[1]).abs() as f32;
+            1.6f32 / width.max(height)
+        };
+
+        let (center_x, center_y) = if self.fixed_scale {
+            (0.0, 0.0)
+        } else {
+            let bounds = tree.get_bounds();
+            (
+                (bounds.min[0] + bounds.max[0]) as f32 * 0.5,
+                (bounds.min[1] + bounds.max[1]) as f32 * 0.5,
+            )
+        };
+
+        View::new(scale, [center_x, center_y], self.aspect)
+    }
+
+    /// Convert a point in normalized device coordinates ([-1, 1], y up) to
+    /// world coordinates, using the same view `render` would use for `tree`
+    pub fn screen_to_world(&self, tree: &QuadTree, ndc: [f32; 2]) -> [Scalar; 2] {
+        let [x, y] = self.view_transform(tree).to_world(ndc);
+        [x as Scalar, y as Scalar]
+    }
+
     pub fn render(&self, bodies: &[Body], tree: &QuadTree) {
+        if let Some(ref bloom) = self.bloom {
+            bloom.begin(&self.gl);
+        }
+
         unsafe {
+            if let Some(period) = self.time_tint {
+                let [r, g, b] = time_tint_color(self.time, period);
+                self.gl.clear_color(r, g, b, 1.0);
+            }
             self.gl.clear(COLOR_BUFFER_BIT);
             self.gl.use_program(Some(self.program));
             self.gl.bind_vertex_array(Some(self.vertex_array));
 
-            let scale = if self.fixed_scale {
-                0.8f32
-            } else {
-                let bounds = tree.get_bounds();
-                let width = (bounds.max[0] - bounds.min[0]).abs() as f32;
-                let height = (bounds.max[1] - bounds.min[1]).abs() as f32;
-                1.6f32 / width.max(height)
-            };
-
-            let (center_x, center_y) = if self.fixed_scale {
-                (0.0, 0.0)
-            } else {
-                let bounds = tree.get_bounds();
-                (
-                    (bounds.min[0] + bounds.max[0]) as f32 * 0.5,
-                    (bounds.min[1] + bounds.max[1]) as f32 * 0.5,
-                )
-            };
+            let view = self.view_transform(tree);
 
             // Draw tree boxes with thin lines
-            self.gl.line_width(1.0);
-            self.gl.uniform_4_f32(Some(&self.color_location), 0.3, 0.3, 0.3, 0.8);
-            self.gl.uniform_1_f32(Some(&self.point_size_location), 1.0);
-            self.draw_tree(tree, scale, center_x, center_y);
-
-            // Draw bodies as points
-            self.gl.uniform_4_f32(Some(&self.color_location), 1.0, 1.0, 1.0, 1.0);
-            self.gl.uniform_1_f32(Some(&self.point_size_location), self.point_size * scale);
-            self.draw_bodies(bodies, scale, center_x, center_y);
+            if self.show_wireframe {
+                if self.wire_width <= self.max_line_width {
+                    self.gl.line_width(self.wire_width);
+                }
+                self.gl.uniform_4_f32(Some(&self.color_location), 0.3, 0.3, 0.3, 0.8);
+                self.gl.uniform_1_f32(Some(&self.point_size_location), 1.0);
+                self.draw_tree(tree, tree.total_mass(), &view);
+            }
+
+            // Draw velocity lines underneath the bodies
+            if let Some(velocity_scale) = self.velocity_scale {
+                self.gl.uniform_4_f32(Some(&self.color_location), 0.2, 0.8, 0.5, 0.7);
+                self.draw_velocities(bodies, velocity_scale, &view);
+            }
+
+            // Draw bodies as points, or as additively blended sprites
+            self.gl.uniform_1_f32(Some(&self.point_size_location), self.point_size * view.scale);
+            if self.sprite_texture.is_some() {
+                self.gl.uniform_1_i32(Some(&self.use_sprite_location), 1);
+                self.gl.blend_func(SRC_ALPHA, ONE);
+            }
+            match self.color_mode {
+                ColorMode::Uniform => {
+                    self.gl.uniform_4_f32(Some(&self.color_location), 1.0, 1.0, 1.0, 1.0);
+                    self.draw_bodies(bodies, &view);
+                }
+                ColorMode::Species => self.draw_species(bodies, &view),
+                ColorMode::PerBody => {
+                    self.gl.uniform_4_f32(Some(&self.color_location), 1.0, 1.0, 1.0, 1.0);
+                    self.draw_colored_bodies(bodies, &view);
+                }
+            }
+            if self.sprite_texture.is_some() {
+                self.gl.uniform_1_i32(Some(&self.use_sprite_location), 0);
+                self.gl.blend_func(SRC_ALPHA, ONE_MINUS_SRC_ALPHA);
+            }
+
+            // Draw the selected body again, larger and in a highlight color
+            if let Some(index) = self.highlighted.filter(|&i| i < bodies.len()) {
+                self.gl.uniform_4_f32(Some(&self.color_location), 1.0, 0.3, 0.3, 1.0);
+                self.gl.uniform_1_f32(Some(&self.point_size_location), 3.0 * self.point_size * view.scale);
+                self.draw_bodies(&bodies[index..=index], &view);
+            }
+        }
+
+        if let Some(ref bloom) = self.bloom {
+            bloom.finish(&self.gl);
         }
     }
 
-    fn draw_tree(&self, tree: &QuadTree, scale: f32, center_x: f32, center_y: f32) {
+    /// Read back the rendered frame as `(width, height, rgba)`, with rows
+    /// ordered top to bottom. Call after `render` and before swapping buffers.
+    pub fn read_pixels(&self) -> (u32, u32, Vec<u8>) {
+        let (width, height) = (self.viewport.0.max(0) as usize, self.viewport.1.max(0) as usize);
+        if width == 0 || height == 0 {
+            return (0, 0, Vec::new());
+        }
+        let mut pixels = vec![0u8; width * height * 4];
+
+        unsafe {
+            self.gl.bind_framebuffer(FRAMEBUFFER, None);
+            self.gl.read_pixels(
+                0, 0, width as i32, height as i32,
+                RGBA, UNSIGNED_BYTE,
+                PixelPackData::Slice(&mut pixels),
+            );
+        }
+
+        // GL returns rows bottom to top
+        let rows: Vec<&[u8]> = pixels.chunks_exact(width * 4).rev().collect();
+        (width as u32, height as u32, rows.concat())
+    }
+
+    fn draw_tree(&self, tree: &QuadTree, root_mass: Scalar, view: &View) {
+        if !wire_visible(tree.total_mass(), root_mass, self.wire_min_mass) {
+            return;
+        }
         let bounds = tree.get_bounds();
-        let vertices: Vec<f32> = vec![
-            (bounds.min[0] as f32 - center_x) * scale, (bounds.min[1] as f32 - center_y) * scale,
-            (bounds.max[0] as f32 - center_x) * scale, (bounds.min[1] as f32 - center_y) * scale,
-            (bounds.max[0] as f32 - center_x) * scale, (bounds.max[1] as f32 - center_y) * scale,
-            (bounds.min[0] as f32 - center_x) * scale, (bounds.max[1] as f32 - center_y) * scale,
-            (bounds.min[0] as f32 - center_x) * scale, (bounds.min[1] as f32 - center_y) * scale,
-        ];
+        let [x0, y0] = view.to_clip(bounds.min[0] as f32, bounds.min[1] as f32);
+        let [x1, y1] = view.to_clip(bounds.max[0] as f32, bounds.max[1] as f32);
 
         unsafe {
-            self.gl.bind_buffer(ARRAY_BUFFER, Some(self.vertex_buffer));
+            if self.tree_color_by_mass {
+                let [r, g, b, a] = mass_color(tree.total_mass(), root_mass);
+                self.gl.uniform_4_f32(Some(&self.color_location), r, g, b, a);
+            }
+
+            if self.wire_width <= self.max_line_width {
+                self.draw_vertices(&[x0, y0, x1, y0, x1, y1, x0, y1, x0, y0], LINE_STRIP);
+            } else {
+                self.draw_wide_box([x0, y0], [x1, y1]);
+            }
+
+            for child in tree.get_children().iter().flatten() {
+                self.draw_tree(child, root_mass, view);
+            }
+        }
+    }
+
+    fn draw_bodies(&self, bodies: &[Body], view: &View) {
+        let vertices = body_vertices(sample(bodies, self.render_sample), view);
+        self.draw_points(&vertices);
+    }
+
+    /// Draw bodies with one draw call per species, each in its palette color
+    fn draw_species(&self, bodies: &[Body], view: &View) {
+        let mut groups: BTreeMap<u8, Vec<f32>> = BTreeMap::new();
+        for body in sample(bodies, self.render_sample) {
+            groups
+                .entry(body.species)
+                .or_default()
+                .extend(view.to_clip(body.position[0] as f32, body.position[1] as f32));
+        }
+
+        for (species, vertices) in groups {
+            let [r, g, b, a] = species_color(species);
+            unsafe {
+                self.gl.uniform_4_f32(Some(&self.color_location), r, g, b, a);
+            }
+            self.draw_points(&vertices);
+        }
+    }
+
+    /// Draw bodies in their own colors, uploaded as a second vertex attribute
+    fn draw_colored_bodies(&self, bodies: &[Body], view: &View) {
+        let colors = body_colors(sample(bodies, self.render_sample));
+        unsafe {
+            self.gl.bind_buffer(ARRAY_BUFFER, Some(self.color_buffer));
             self.gl.buffer_data_u8_slice(
                 ARRAY_BUFFER,
                 std::slice::from_raw_parts(
-                    vertices.as_ptr() as *const u8,
-                    vertices.len() * std::mem::size_of::<f32>(),
+                    colors.as_ptr() as *const u8,
+                    std::mem::size_of_val(colors.as_slice()),
                 ),
                 STREAM_DRAW,
             );
+            self.gl.enable_vertex_attrib_array(1);
+        }
 
-            self.gl.draw_arrays(LINE_STRIP, 0, vertices.len() as i32 / 2);
+        self.draw_bodies(bodies, view);
 
-            for child in tree.get_children().iter().flatten() {
-                self.draw_tree(child, scale, center_x, center_y);
-            }
+        unsafe {
+            self.gl.disable_vertex_attrib_array(1);
         }
     }
 
-    fn draw_bodies(&self, bodies: &[Body], scale: f32, center_x: f32, center_y: f32) {
-        let vertices: Vec<f32> = bodies
+    /// Draw the outline of an axis-aligned box (in clip space) as four bars,
+    /// `wire_width` pixels thick, for widths the driver cannot draw as lines
+    fn draw_wide_box(&self, min: [f32; 2], max: [f32; 2]) {
+        // Half the width in clip space units along each axis
+        let hx = self.wire_width / self.viewport.0.max(1) as f32;
+        let hy = self.wire_width / self.viewport.1.max(1) as f32;
+
+        let bars = [
+            [min[0] - hx, min[1] - hy, max[0] + hx, min[1] + hy], // bottom
+            [min[0] - hx, max[1] - hy, max[0] + hx, max[1] + hy], // top
+            [min[0] - hx, min[1] + hy, min[0] + hx, max[1] - hy], // left
+            [max[0] - hx, min[1] + hy, max[0] + hx, max[1] - hy], // right
+        ];
+        let vertices: Vec<f32> = bars
             .iter()
-            .flat_map(|body| [
-                (body.position[0] as f32 - center_x) * scale,
-                (body.position[1] as f32 - center_y) * scale,
-            ])
+            .flat_map(|&[x0, y0, x1, y1]| [x0, y0, x1, y0, x1, y1, x0, y0, x1, y1, x0, y1])
+            .collect();
+
+        self.draw_vertices(&vertices, TRIANGLES);
+    }
+
+    /// Draw one line segment per body from its position along its velocity
+    fn draw_velocities(&self, bodies: &[Body], velocity_scale: f32, view: &View) {
+        let vertices: Vec<f32> = sample(bodies, self.render_sample)
+            .flat_map(|body| {
+                let x = body.position[0] as f32;
+                let y = body.position[1] as f32;
+                let tip_x = x + body.velocity[0] as f32 * velocity_scale;
+                let tip_y = y + body.velocity[1] as f32 * velocity_scale;
+                let [x0, y0] = view.to_clip(x, y);
+                let [x1, y1] = view.to_clip(tip_x, tip_y);
+                [x0, y0, x1, y1]
+            })
             .collect();
 
+        self.draw_vertices(&vertices, LINES);
+    }
+
+    /// Draw interleaved x, y vertices as points
+    fn draw_points(&self, vertices: &[f32]) {
+        self.draw_vertices(vertices, POINTS);
+    }
+
+    /// Upload interleaved x, y vertices and draw them with the given primitive
+    fn draw_vertices(&self, vertices: &[f32], mode: u32) {
         unsafe {
             self.gl.bind_buffer(ARRAY_BUFFER, Some(self.vertex_buffer));
             self.gl.buffer_data_u8_slice(
                 ARRAY_BUFFER,
                 std::slice::from_raw_parts(
                     vertices.as_ptr() as *const u8,
-                    vertices.len() * std::mem::size_of::<f32>(),
+                    std::mem::size_of_val(vertices),
                 ),
                 STREAM_DRAW,
             );
 
-            self.gl.draw_arrays(POINTS, 0, bodies.len() as i32);
+            self.gl.draw_arrays(mode, 0, (vertices.len() / 2) as i32);
         }
     }
 }
 
-fn create_program(
+/// Every `ceil(n / max)`-th body, so at most `max` of them (all with `None`)
+fn sample(bodies: &[Body], max: Option<usize>) -> impl Iterator<Item = &Body> {
+    let max = max.unwrap_or(usize::MAX);
+    let stride = bodies.len().div_ceil(max.max(1)).max(1);
+    bodies.iter().step_by(stride).take(max)
+}
+
+/// Interleaved x, y clip space positions of `bodies`
+fn body_vertices<'a>(
+    bodies: impl Iterator<Item = &'a Body>,
+    view: &View,
+) -> Vec<f32> {
+    bodies
+        .flat_map(|body| view.to_clip(body.position[0] as f32, body.position[1] as f32))
+        .collect()
+}
+
+/// Interleaved r, g, b colors of `bodies`, matching `body_vertices`
+fn body_colors<'a>(bodies: impl Iterator<Item = &'a Body>) -> Vec<f32> {
+    bodies.flat_map(|body| body.color).collect()
+}
+
+/// Palette color for a species id
+fn species_color(species: u8) -> [f32; 4] {
+    SPECIES_PALETTE[species as usize % SPECIES_PALETTE.len()]
+}
+
+/// Background color at `time`, going once around the hue circle (starting
+/// from red) every `period`
+fn time_tint_color(time: f32, period: f32) -> [f32; 3] {
+    hsv_to_rgb((time / period).rem_euclid(1.0), TIME_TINT_SATURATION, TIME_TINT_VALUE)
+}
+
+/// Convert a color from HSV, with hue in turns ([0, 1) going once around)
+/// and saturation and value in [0, 1], to RGB
+fn hsv_to_rgb(hue: f32, saturation: f32, value: f32) -> [f32; 3] {
+    let sector = (hue.rem_euclid(1.0) * 6.0).min(6.0 - f32::EPSILON);
+    let fraction = sector.fract();
+    let p = value * (1.0 - saturation);
+    let q = value * (1.0 - saturation * fraction);
+    let t = value * (1.0 - saturation * (1.0 - fraction));
+    match sector as u32 {
+        0 => [value, t, p],
+        1 => [q, value, p],
+        2 => [p, value, t],
+        3 => [p, q, value],
+        4 => [t, p, value],
+        _ => [value, p, q],
+    }
+}
+
+/// Whether a tree node of `mass` is heavy enough to draw with the
+/// wireframe mass threshold `min_fraction`
+fn wire_visible(mass: Scalar, root_mass: Scalar, min_fraction: f32) -> bool {
+    min_fraction <= 0.0 || (root_mass > 0.0 && (mass / root_mass) as f32 >= min_fraction)
+}
+
+/// Map a node mass to a gray-to-orange color, log-scaled by its fraction of the root mass
+fn mass_color(mass: Scalar, root_mass: Scalar) -> [f32; 4] {
+    let fraction = if root_mass > 0.0 { (mass / root_mass) as f32 } else { 0.0 };
+    let t = if fraction > 0.0 {
+        (1.0 + fraction.log10() / MASS_COLOR_DECADES).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    [0.3 + 0.7 * t, 0.3 + 0.3 * t, 0.3 - 0.2 * t, 0.5 + 0.5 * t]
+}
+
+/// Compile and link a shader program from vertex and fragment sources
+/// without a `#version` line, trying each of `GLSL_HEADERS` in turn
+pub(crate) fn create_program(
     gl: &Context,
     vert_source: &str,
     frag_source: &str,
-) -> Result<Program, String> {
-    unsafe {
-        let program = gl.create_program()
-            .map_err(|e| format!("Failed to create program: {}", e))?;
-
-        let shader_sources = [
-            (VERTEX_SHADER, vert_source),
-            (FRAGMENT_SHADER, frag_source),
-        ];
+) -> Result<Program, NBodyError> {
+    let (program, header) = with_glsl_fallback(GLSL_HEADERS, |header| unsafe {
+        compile_program(
+            gl,
+            &format!("{}{}", header, vert_source),
+            &format!("{}{}", header, frag_source),
+        )
+    })?;
+    log::debug!("Compiled shaders as {}", header.lines().next().unwrap_or_default());
+    Ok(program)
+}
 
-        let mut shaders = Vec::with_capacity(shader_sources.len());
+/// Run `build` with each GLSL version header in turn until one succeeds,
+/// returning its result and the header used, or the last header's error
+fn with_glsl_fallback<T>(
+    headers: &[&'static str],
+    mut build: impl FnMut(&str) -> Result<T, NBodyError>,
+) -> Result<(T, &'static str), NBodyError> {
+    let mut last_error = NBodyError::Gl("No GLSL version to compile shaders with".to_string());
+    for (i, &header) in headers.iter().enumerate() {
+        match build(header) {
+            Ok(result) => return Ok((result, header)),
+            Err(e) => {
+                if let Some(next) = headers.get(i + 1) {
+                    log::info!(
+                        "{} shaders failed ({}), falling back to {}",
+                        header.lines().next().unwrap_or_default(),
+                        e,
+                        next.lines().next().unwrap_or_default()
+                    );
+                }
+                last_error = e;
+            }
+        }
+    }
+    Err(last_error)
+}
+
+/// Compile and link one program from complete sources, deleting everything
+/// created on failure
+unsafe fn compile_program(gl: &Context, vert_source: &str, frag_source: &str) -> Result<Program, NBodyError> {
+    let program = gl.create_program()
+        .map_err(|e| NBodyError::Gl(format!("Failed to create program: {}", e)))?;
 
-        for (shader_type, shader_source) in shader_sources.iter() {
-            let shader = gl.create_shader(*shader_type)
-                .map_err(|e| format!("Failed to create shader: {}", e))?;
+    let shader_sources = [
+        (VERTEX_SHADER, vert_source),
+        (FRAGMENT_SHADER, frag_source),
+    ];
 
-            gl.shader_source(shader, shader_source);
-            gl.compile_shader(shader);
+    let mut shaders = Vec::with_capacity(shader_sources.len());
+    let mut result = Ok(());
 
-            if !gl.get_shader_compile_status(shader) {
-                let error = gl.get_shader_info_log(shader);
-                return Err(format!("Failed to compile shader: {}", error));
+    for (shader_type, shader_source) in shader_sources.iter() {
+        let shader = match gl.create_shader(*shader_type) {
+            Ok(shader) => shader,
+            Err(e) => {
+                result = Err(NBodyError::Gl(format!("Failed to create shader: {}", e)));
+                break;
             }
+        };
+        shaders.push(shader);
 
-            gl.attach_shader(program, shader);
-            shaders.push(shader);
-        }
+        gl.shader_source(shader, shader_source);
+        gl.compile_shader(shader);
 
-        gl.link_program(program);
-
-        for shader in shaders {
-            gl.delete_shader(shader);
+        if !gl.get_shader_compile_status(shader) {
+            result = Err(NBodyError::GlShaderCompile(gl.get_shader_info_log(shader)));
+            break;
         }
 
+        gl.attach_shader(program, shader);
+    }
+
+    if result.is_ok() {
+        gl.link_program(program);
         if !gl.get_program_link_status(program) {
-            let error = gl.get_program_info_log(program);
-            return Err(format!("Failed to link program: {}", error));
+            result = Err(NBodyError::GlLink(gl.get_program_info_log(program)));
         }
+    }
+
+    for shader in shaders {
+        gl.delete_shader(shader);
+    }
 
-        Ok(program)
+    match result {
+        Ok(()) => Ok(program),
+        Err(e) => {
+            gl.delete_program(program);
+            Err(e)
+        }
     }
 }
 
 impl Drop for Renderer {
     fn drop(&mut self) {
+        if let Some(bloom) = self.bloom.take() {
+            bloom.delete(&self.gl);
+        }
+
         unsafe {
+            if let Some(texture) = self.sprite_texture.take() {
+                self.gl.delete_texture(texture);
+            }
             self.gl.delete_buffer(self.vertex_buffer);
+            self.gl.delete_buffer(self.color_buffer);
             self.gl.delete_vertex_array(self.vertex_array);
             self.gl.delete_program(self.program);
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_sample_vertex_count() {
+        let bodies: Vec<Body> = (0..1000).map(|i| Body::new(1.0, i as Scalar, 0.0, 0.0, 0.0)).collect();
+        let view = View::new(1.0, [0.0, 0.0], 1.0);
+        let vertices = |max| body_vertices(sample(&bodies, max), &view);
+
+        assert_eq!(vertices(None).len(), 2000);
+        assert_eq!(vertices(Some(100)).len(), 200);
+        assert_eq!(vertices(Some(300)).len(), 2 * 250); // every 4th body
+        assert_eq!(vertices(Some(5000)).len(), 2000);
+        assert!(vertices(Some(0)).is_empty());
+
+        // The same indices every time
+        assert_eq!(vertices(Some(300)), vertices(Some(300)));
+        assert_eq!(&vertices(Some(300))[..4], &[0.0, 0.0, 4.0, 0.0]);
+    }
+
+    /// Number of nodes `draw_tree` draws with the given mass threshold
+    fn drawn_nodes(tree: &QuadTree, root_mass: Scalar, min_fraction: f32) -> usize {
+        if !wire_visible(tree.total_mass(), root_mass, min_fraction) {
+            return 0;
+        }
+        1 + tree
+            .get_children()
+            .iter()
+            .flatten()
+            .map(|child| drawn_nodes(child, root_mass, min_fraction))
+            .sum::<usize>()
+    }
+
+    #[test]
+    fn test_wire_mass_threshold_prunes_light_nodes() {
+        let mut tree = QuadTree::new(crate::Bounds::new([0.0, 0.0], [1.0, 1.0]));
+        for i in 0..1000 {
+            let x = (i as Scalar * 0.618_034).fract();
+            let y = (i as Scalar * 0.414_214).fract();
+            assert!(tree.insert(Body::new(1.0, x, y, 0.0, 0.0)));
+        }
+        let root_mass = tree.total_mass();
+
+        let all = drawn_nodes(&tree, root_mass, 0.0);
+        let heavy = drawn_nodes(&tree, root_mass, 0.01);
+        assert!(heavy > 1 && heavy < all / 10, "{} of {} nodes", heavy, all);
+        assert_eq!(drawn_nodes(&tree, root_mass, 1.0), 1);
+
+        // A node only holding one body is drawn only without a threshold
+        assert!(wire_visible(1.0, 1000.0, 0.0));
+        assert!(!wire_visible(1.0, 1000.0, 0.01));
+        assert!(!wire_visible(0.0, 0.0, 0.5));
+    }
+
+    #[test]
+    fn test_glsl_fallback() {
+        let headers = &["#version 410\n", "#version 330\n"];
+        let mut tried = Vec::new();
+        let (source, header) = with_glsl_fallback(headers, |header| {
+            tried.push(header.to_string());
+            if header.contains("410") {
+                Err(NBodyError::GlShaderCompile("version 410 not supported".to_string()))
+            } else {
+                Ok(format!("{}void main() {{}}", header))
+            }
+        })
+        .unwrap();
+        assert_eq!(header, "#version 330\n");
+        assert_eq!(source, "#version 330\nvoid main() {}");
+        assert_eq!(tried, headers);
+
+        // The preferred version is used when it compiles
+        assert_eq!(with_glsl_fallback(headers, |_| Ok(())).unwrap().1, "#version 410\n");
+
+        // When none compiles the last error is returned
+        let error = with_glsl_fallback(headers, |header| {
+            Err::<(), _>(NBodyError::GlShaderCompile(header.trim().to_string()))
+        })
+        .unwrap_err();
+        assert_eq!(error.to_string(), "Failed to compile shader: #version 330");
+    }
+
+    #[test]
+    fn test_glow_sprite() {
+        let sprite = Sprite::glow(32);
+        assert_eq!(sprite.rgba().len(), 32 * 32 * 4);
+        let alpha = |x: usize, y: usize| sprite.rgba()[(y * 32 + x) * 4 + 3];
+
+        // Bright in the middle, faded at the edges and symmetric
+        assert!(alpha(15, 15) > 250);
+        assert!(alpha(0, 0) < 3 && alpha(0, 15) < 10);
+        assert_eq!(alpha(3, 10), alpha(28, 10));
+        assert_eq!(alpha(3, 10), alpha(10, 3));
+        assert!(sprite.rgba().chunks(4).all(|p| p[..3] == [255, 255, 255]));
+
+        assert!(Sprite::new(2, vec![0; 16]).is_ok());
+        assert!(Sprite::new(2, vec![0; 12]).is_err());
+        assert!(Sprite::new(0, Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_hsv_to_rgb() {
+        let close = |a: [f32; 3], b: [f32; 3]| a.iter().zip(b).all(|(x, y)| (x - y).abs() < 1e-5);
+        assert!(close(hsv_to_rgb(0.0, 1.0, 1.0), [1.0, 0.0, 0.0]));
+        assert!(close(hsv_to_rgb(1.0 / 3.0, 1.0, 1.0), [0.0, 1.0, 0.0]));
+        assert!(close(hsv_to_rgb(2.0 / 3.0, 1.0, 0.5), [0.0, 0.0, 0.5]));
+        assert!(close(hsv_to_rgb(1.0 / 6.0, 1.0, 1.0), [1.0, 1.0, 0.0]));
+        assert!(close(hsv_to_rgb(0.25, 0.0, 0.3), [0.3, 0.3, 0.3]));
+        assert!(close(hsv_to_rgb(0.999_999, 1.0, 1.0), [1.0, 0.0, 0.0]));
+
+        // The tint repeats every period, also before time zero
+        assert!(close(time_tint_color(2.5, 10.0), time_tint_color(12.5, 10.0)));
+        assert!(close(time_tint_color(-7.5, 10.0), time_tint_color(2.5, 10.0)));
+        let max = time_tint_color(3.0, 10.0).into_iter().fold(0.0, f32::max);
+        assert!((max - TIME_TINT_VALUE).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_per_body_color_buffer() {
+        let bodies = vec![
+            Body::new(1.0, 0.0, 0.0, 0.0, 0.0).with_color([1.0, 0.0, 0.0]),
+            Body::new(1.0, 1.0, 0.0, 0.0, 0.0),
+            Body::new(1.0, 2.0, 0.0, 0.0, 0.0).with_color([0.0, 0.5, 0.25]),
+        ];
+        assert_eq!(
+            body_colors(bodies.iter()),
+            [1.0, 0.0, 0.0, 1.0, 1.0, 1.0, 0.0, 0.5, 0.25]
+        );
+
+        // Sampled colors line up with the sampled vertices
+        let view = View::new(1.0, [0.0, 0.0], 1.0);
+        let colors = body_colors(sample(&bodies, Some(2)));
+        let vertices = body_vertices(sample(&bodies, Some(2)), &view);
+        assert_eq!(colors, [1.0, 0.0, 0.0, 0.0, 0.5, 0.25]);
+        assert_eq!(colors.len() / 3, vertices.len() / 2);
+    }
+
+    #[test]
+    fn test_view_aspect_keeps_world_units_square() {
+        // A 2:1 window squeezes x so a unit step covers as many pixels
+        // horizontally as vertically
+        let view = View::new(0.5, [1.0, 1.0], 2.0);
+        assert_eq!(view.to_clip(3.0, 3.0), [0.5, 1.0]);
+        assert_eq!(view.to_world([0.5, 1.0]), [3.0, 3.0]);
+
+        // On a tall window the shorter x axis keeps the full scale
+        let view = View::new(0.5, [0.0, 0.0], 0.5);
+        assert_eq!(view.to_clip(2.0, 2.0), [1.0, 0.5]);
+
+        // Square windows are unchanged
+        assert_eq!(View::new(0.5, [0.0, 0.0], 1.0).to_clip(2.0, -2.0), [1.0, -1.0]);
+    }
+}
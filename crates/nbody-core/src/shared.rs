@@ -0,0 +1,97 @@
+//! Running a simulation on its own thread.
+//!
+//! The compute thread owns the `Simulation` and its bodies outright; nothing
+//! else can reach them while it runs. After every step it publishes an
+//! immutable `Snapshot` of the positions by swapping in a new `Arc`, so a
+//! reader only holds the lock long enough to clone that `Arc` and never waits
+//! for a step to finish. A snapshot stays valid for as long as the reader
+//! keeps it, however far the simulation has moved on.
+
+use std::sync::{Arc, RwLock};
+use std::thread::{self, JoinHandle};
+use crate::{Scalar, Simulation};
+
+/// Body positions after a given step
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub step: usize,
+    pub time: Scalar,
+    pub positions: Vec<[Scalar; 2]>,
+}
+
+impl Snapshot {
+    fn of(simulation: &Simulation) -> Self {
+        Snapshot {
+            step: simulation.step_count(),
+            time: simulation.time(),
+            positions: simulation.bodies().iter().map(|body| body.position).collect(),
+        }
+    }
+}
+
+/// Read handle on the latest snapshot published by the compute thread.
+///
+/// Clones share the same simulation. The thread keeps stepping while any
+/// reader is alive and stops once the last one is dropped.
+#[derive(Clone)]
+pub struct PositionReader {
+    latest: Arc<RwLock<Arc<Snapshot>>>,
+}
+
+impl PositionReader {
+    /// The most recently published snapshot
+    pub fn latest(&self) -> Arc<Snapshot> {
+        // A writer cannot panic while holding the lock, so ignore poisoning
+        let latest = self.latest.read().unwrap_or_else(|e| e.into_inner());
+        Arc::clone(&latest)
+    }
+}
+
+/// Step `simulation` on a new thread until every `PositionReader` is dropped.
+///
+/// The reader starts with a snapshot of the initial state. Joining the handle
+/// returns the simulation, advanced to wherever it stopped.
+pub fn spawn(mut simulation: Simulation) -> (JoinHandle<Simulation>, PositionReader) {
+    let latest = Arc::new(RwLock::new(Arc::new(Snapshot::of(&simulation))));
+    let reader = PositionReader { latest: Arc::clone(&latest) };
+
+    let handle = thread::spawn(move || {
+        while Arc::strong_count(&latest) > 1 {
+            simulation.step();
+            let snapshot = Arc::new(Snapshot::of(&simulation));
+            *latest.write().unwrap_or_else(|e| e.into_inner()) = snapshot;
+        }
+        simulation
+    });
+
+    (handle, reader)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Body;
+
+    #[test]
+    fn test_reader_sees_advancing_positions() {
+        // A single body drifting along x at unit speed
+        let simulation = Simulation::new(vec![Body::new(1.0, 0.0, 0.0, 1.0, 0.0)], 0.01, 1.0, 0.0, 0.5);
+        let (handle, reader) = spawn(simulation);
+
+        let first = reader.latest();
+        let mut last = Arc::clone(&first);
+        while last.step < first.step + 10 {
+            let next = reader.latest();
+            assert!(next.step >= last.step);
+            assert!(next.positions[0][0] >= last.positions[0][0]);
+            last = next;
+            thread::yield_now();
+        }
+        assert!(last.positions[0][0] > first.positions[0][0]);
+        assert_eq!(first.positions.len(), 1);
+
+        drop(reader);
+        let simulation = handle.join().unwrap();
+        assert!(simulation.step_count() >= last.step);
+    }
+}
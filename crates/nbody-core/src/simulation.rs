@@ -1,25 +1,319 @@
 #[cfg(feature = "parallel")]
 use rayon::prelude::*;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::sync::mpsc::{SyncSender, TrySendError};
+use alloc::vec;
+use alloc::vec::Vec;
 use crate::body::Body;
+#[cfg(feature = "std")]
+use crate::error::NBodyError;
+use crate::external::ExternalField;
+use crate::grid::SpatialHash;
+#[cfg(not(any(feature = "std", test)))]
+use crate::math::Float;
+use crate::force::{ForceLaw, ForceParams, OpeningCriterion};
 use crate::tree::{QuadTree, Bounds};
+use crate::Scalar;
+
+/// Relative padding of the tree bounds when the tree is updated incrementally,
+/// so that bodies near the edge do not force a rebuild as soon as they move out
+const INCREMENTAL_TREE_PADDING: Scalar = 0.05;
+
+/// Neighbours (besides the body itself) whose distance sets a body's
+/// adaptive softening
+const ADAPTIVE_SOFTENING_NEIGHBORS: usize = 8;
+
+/// Number of steps the timestep stays reduced after an energy jump
+const ENERGY_GUARD_BRAKE_STEPS: usize = 16;
+
+/// Drift and kick weights of the drift-kick-drift leapfrog
+const LEAPFROG_DRIFTS: [Scalar; 2] = [0.5, 0.5];
+const LEAPFROG_KICKS: [Scalar; 1] = [1.0];
+
+/// Yoshida's fourth-order composition of three leapfrogs of `w1 dt`, `w0 dt`
+/// and `w1 dt`, with `w1 = 1 / (2 - 2^(1/3))` and `w0 = 1 - 2 w1`
+const YOSHIDA_W1: Scalar = 1.351_207_191_959_657_8;
+const YOSHIDA_W0: Scalar = -1.702_414_383_919_315_3;
+const YOSHIDA_DRIFTS: [Scalar; 4] = [
+    0.5 * YOSHIDA_W1,
+    0.5 * (YOSHIDA_W0 + YOSHIDA_W1),
+    0.5 * (YOSHIDA_W0 + YOSHIDA_W1),
+    0.5 * YOSHIDA_W1,
+];
+const YOSHIDA_KICKS: [Scalar; 3] = [YOSHIDA_W1, YOSHIDA_W0, YOSHIDA_W1];
+
+/// Watchdog that halves the timestep after a sudden jump in total energy
+#[derive(Debug, Clone, PartialEq)]
+struct EnergyGuard {
+    max_rel_jump: Scalar,
+    last_energy: Option<Scalar>,
+    dt_factor: Scalar,
+    brake_steps: usize,
+}
+
+impl EnergyGuard {
+    /// Compare the energy at the start of this step with the previous one,
+    /// braking on a large jump and releasing the brake once it runs out
+    fn check(&mut self, energy: Scalar) {
+        if let Some(last) = self.last_energy {
+            let jump = (energy - last).abs() / last.abs().max(Scalar::MIN_POSITIVE);
+            if jump > self.max_rel_jump {
+                self.dt_factor *= 0.5;
+                self.brake_steps = ENERGY_GUARD_BRAKE_STEPS;
+            } else if self.brake_steps > 0 {
+                self.brake_steps -= 1;
+                if self.brake_steps == 0 {
+                    self.dt_factor = 1.0;
+                }
+            }
+        }
+        self.last_energy = Some(energy);
+    }
+}
+
+/// How the forces between bodies are evaluated
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ForceMethod {
+    /// Barnes-Hut tree, opened according to the tree ratio
+    #[default]
+    BarnesHut,
+    /// Only pairs closer than `cutoff`, found with a uniform-grid spatial
+    /// hash. Suited to nearly uniform distributions where gravity is screened
+    /// or truncated; the force jumps at the cutoff, so energy is not exactly
+    /// conserved.
+    GridCutoff { cutoff: Scalar },
+}
+
+/// How `Simulation::step` advances velocities and positions
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Integrator {
+    /// Semi-implicit Euler: kick with the current forces, then drift. First
+    /// order, with one force evaluation per step.
+    #[default]
+    SymplecticEuler,
+    /// Drift-kick-drift leapfrog (equivalent to velocity Verlet). Second
+    /// order, with one force evaluation per step.
+    Leapfrog,
+    /// Yoshida's fourth-order symplectic integrator: three leapfrog sub-steps,
+    /// the middle one backwards in time. Three force evaluations per step, but
+    /// the energy error falls as `dt⁴` and stays bounded over long runs.
+    Yoshida4,
+    /// Kick-drift-kick leapfrog with block timesteps. Each step, every body
+    /// is put on the coarsest of `levels` rungs, with substeps `dt / 2^rung`,
+    /// on which `|a| h² <= accuracy` for its acceleration `a` and substep
+    /// `h` (the finest rung if none is). Bodies are kicked only at the ends
+    /// of their own substeps, so forces are evaluated often only for the
+    /// few bodies in dense regions, while everything drifts together. One
+    /// level is a plain kick-drift-kick leapfrog; at most 16 are used.
+    BlockLeapfrog { levels: u32, accuracy: Scalar },
+}
+
+/// What happens to bodies at the edge of the simulated region
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum BoundaryCondition {
+    /// No boundary; bodies move freely
+    #[default]
+    Open,
+    /// Elastic walls at the faces of `bounds`: a body that crosses a face is
+    /// put back on the wall and its velocity component normal to it reversed
+    Reflecting { bounds: Bounds },
+}
+
+impl BoundaryCondition {
+    fn apply(&self, body: &mut Body) {
+        match self {
+            BoundaryCondition::Open => {}
+            BoundaryCondition::Reflecting { bounds } => {
+                for axis in 0..2 {
+                    if body.position[axis] < bounds.min[axis] {
+                        body.position[axis] = bounds.min[axis];
+                        body.velocity[axis] = body.velocity[axis].abs();
+                    } else if body.position[axis] > bounds.max[axis] {
+                        body.position[axis] = bounds.max[axis];
+                        body.velocity[axis] = -body.velocity[axis].abs();
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The structure forces are evaluated with for one step
+enum ForceSolver {
+    Tree(QuadTree),
+    Grid(SpatialHash),
+}
+
+impl ForceSolver {
+    fn force(&self, body: &Body, params: &ForceParams, with_potential: bool) -> ([Scalar; 2], Scalar) {
+        match self {
+            ForceSolver::Tree(tree) if with_potential => tree.calculate_force_and_potential_with(body, params),
+            ForceSolver::Tree(tree) => (tree.calculate_force_with(body, params), 0.0),
+            ForceSolver::Grid(grid) if with_potential => grid.calculate_force_and_potential_with(body, params),
+            ForceSolver::Grid(grid) => (grid.calculate_force_with(body, params), 0.0),
+        }
+    }
+}
+
+/// Destination for per-step position snapshots
+#[cfg(feature = "std")]
+struct SnapshotSender {
+    sender: SyncSender<Vec<f32>>,
+    stride: usize,
+}
+
+/// In-memory copy of a simulation's bodies and settings, taken with
+/// `Simulation::snapshot` and put back with `Simulation::restore`.
+///
+/// Cheaper than a round trip through a file for checkpointing in algorithms,
+/// e.g. bisecting for the time of a close encounter or trying several
+/// timesteps from the same state. The tree is not copied; it is rebuilt on
+/// the next step after a restore.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimulationSnapshot {
+    bodies: Vec<Body>,
+    time: Scalar,
+    step_count: usize,
+    timestep: Scalar,
+    g: Scalar,
+    softening: Scalar,
+    anisotropic_softening: Option<[Scalar; 2]>,
+    adaptive_softening: bool,
+    tree_threshold: Scalar,
+    force_law: ForceLaw,
+    opening: OpeningCriterion,
+    energy_guard: Option<EnergyGuard>,
+    incremental_tree: bool,
+    tree_rebuild_interval: usize,
+    max_tree_depth: Option<usize>,
+    self_gravity: bool,
+    force_method: ForceMethod,
+    boundary: BoundaryCondition,
+    integrator: Integrator,
+    compensated_summation: bool,
+    drag: Scalar,
+}
+
+impl SimulationSnapshot {
+    /// The bodies at the time the snapshot was taken
+    pub fn bodies(&self) -> &[Body] {
+        &self.bodies
+    }
+
+    /// Simulation time at which the snapshot was taken
+    pub fn time(&self) -> Scalar {
+        self.time
+    }
+
+    /// Number of steps taken before the snapshot
+    pub fn step_count(&self) -> usize {
+        self.step_count
+    }
+}
 
 pub struct Simulation {
     bodies: Vec<Body>,
-    timestep: f64,
-    g: f64,
-    softening: f64,
-    tree_threshold: f64,
+    timestep: Scalar,
+    g: Scalar,
+    softening: Scalar,
+    anisotropic_softening: Option<[Scalar; 2]>,
+    adaptive_softening: bool,
+    tree_threshold: Scalar,
+    force_law: ForceLaw,
+    opening: OpeningCriterion,
+    time: Scalar,
+    step_count: usize,
+    #[cfg(feature = "std")]
+    snapshot_sender: Option<SnapshotSender>,
+    energy_guard: Option<EnergyGuard>,
+    incremental_tree: bool,
+    tree_rebuild_interval: usize,
+    max_tree_depth: Option<usize>,
+    // Force evaluations since the cached tree's structure was last rebuilt
+    tree_age: usize,
+    cached_tree: Option<QuadTree>,
+    self_gravity: bool,
+    external_field: Option<ExternalField>,
+    force_method: ForceMethod,
+    boundary: BoundaryCondition,
+    integrator: Integrator,
+    compensated_summation: bool,
+    drag: Scalar,
+    /// Per-body softening for the current step, when adaptive
+    body_softening: Vec<Scalar>,
+    /// Whether every body's acceleration is that of its current position
+    /// under the current settings, so `Integrator::BlockLeapfrog` can start
+    /// a step without a force pass
+    accelerations_current: bool,
 }
 
 impl Simulation {
-    pub fn new(bodies: Vec<Body>, timestep: f64, g: f64, softening: f64, tree_threshold: f64) -> Self {
+    pub fn new(bodies: Vec<Body>, timestep: Scalar, g: Scalar, softening: Scalar, tree_threshold: Scalar) -> Self {
         Simulation {
             bodies,
             timestep,
             g,
             softening,
+            anisotropic_softening: None,
+            adaptive_softening: false,
             tree_threshold,
+            force_law: ForceLaw::default(),
+            opening: OpeningCriterion::default(),
+            time: 0.0,
+            step_count: 0,
+            #[cfg(feature = "std")]
+            snapshot_sender: None,
+            energy_guard: None,
+            incremental_tree: false,
+            tree_rebuild_interval: 1,
+            max_tree_depth: None,
+            tree_age: 0,
+            cached_tree: None,
+            self_gravity: true,
+            external_field: None,
+            force_method: ForceMethod::default(),
+            boundary: BoundaryCondition::default(),
+            integrator: Integrator::default(),
+            compensated_summation: false,
+            drag: 0.0,
+            body_softening: Vec::new(),
+            accelerations_current: false,
+        }
+    }
+
+    /// Build a simulation from structure-of-arrays columns, as found in
+    /// columnar data: body `i` has mass `masses[i]`, position `positions[i]`
+    /// and velocity `velocities[i]`.
+    ///
+    /// Fails with `NBodyError::InvalidBody` unless the three columns have the
+    /// same length.
+    #[cfg(feature = "std")]
+    pub fn from_soa(
+        masses: &[Scalar],
+        positions: &[[Scalar; 2]],
+        velocities: &[[Scalar; 2]],
+        timestep: Scalar,
+        g: Scalar,
+        softening: Scalar,
+        tree_threshold: Scalar,
+    ) -> Result<Self, NBodyError> {
+        if positions.len() != masses.len() || velocities.len() != masses.len() {
+            return Err(NBodyError::InvalidBody(format!(
+                "{} masses, {} positions and {} velocities",
+                masses.len(),
+                positions.len(),
+                velocities.len()
+            )));
         }
+
+        let bodies = masses
+            .iter()
+            .zip(positions)
+            .zip(velocities)
+            .map(|((&mass, &[x, y]), &[vx, vy])| Body::new(mass, x, y, vx, vy))
+            .collect();
+        Ok(Simulation::new(bodies, timestep, g, softening, tree_threshold))
     }
 
     /// Get a reference to the current bodies in the simulation
@@ -27,222 +321,2500 @@ impl Simulation {
         &self.bodies
     }
 
-    /// Calculate the boundaries that contain all bodies
-    fn compute_bounds(&self) -> Bounds {
-        if self.bodies.is_empty() {
-            return Bounds::new([-1.0, -1.0], [1.0, 1.0]); // Default bounds for empty system
+    /// Get the body at `index`, or `None` past the end
+    pub fn body(&self, index: usize) -> Option<&Body> {
+        self.bodies.get(index)
+    }
+
+    /// Get mutable access to the body at `index`, e.g. to nudge it from an
+    /// interactive tool; `None` past the end.
+    ///
+    /// Since the body may be changed in any way, taking it invalidates
+    /// everything derived from the bodies, as `add_body` does: the tree kept
+    /// between steps is rebuilt, accelerations are recomputed at the next
+    /// step and the energy guard starts over.
+    pub fn body_mut(&mut self, index: usize) -> Option<&mut Body> {
+        let body = self.bodies.get_mut(index)?;
+        self.cached_tree = None;
+        self.accelerations_current = false;
+        if let Some(guard) = self.energy_guard.as_mut() {
+            guard.last_energy = None;
         }
+        Some(body)
+    }
 
-        // Start with the first body's position
-        let first_pos = self.bodies[0].position;
-        let mut min_x = first_pos[0];
-        let mut min_y = first_pos[1];
-        let mut max_x = first_pos[0];
-        let mut max_y = first_pos[1];
+    /// Get the simulation time elapsed over all steps taken so far
+    pub fn time(&self) -> Scalar {
+        self.time
+    }
 
-        // Find the actual extents of all bodies
-        for body in &self.bodies[1..] {
-            min_x = min_x.min(body.position[0]);
-            min_y = min_y.min(body.position[1]);
-            max_x = max_x.max(body.position[0]);
-            max_y = max_y.max(body.position[1]);
-        }
+    /// Get the number of steps taken so far
+    pub fn step_count(&self) -> usize {
+        self.step_count
+    }
 
-        // Handle the case where all bodies are at exactly the same point
-        if (max_x - min_x).abs() < f64::EPSILON {
-            max_x += f64::EPSILON;
-            min_x -= f64::EPSILON;
-        }
-        if (max_y - min_y).abs() < f64::EPSILON {
-            max_y += f64::EPSILON;
-            min_y -= f64::EPSILON;
-        }
+    /// Get the simulation timestep
+    pub fn timestep(&self) -> Scalar {
+        self.timestep
+    }
 
-        Bounds::new([min_x, min_y], [max_x, max_y])
+    /// Get the gravitational constant
+    pub fn g(&self) -> Scalar {
+        self.g
     }
 
-    /// Build the quad tree from the current body positions
-    fn build_tree(&self) -> QuadTree {
-        let bounds = self.compute_bounds();
-        let mut tree = QuadTree::new(bounds);
+    /// Get the softening factor
+    pub fn softening(&self) -> Scalar {
+        self.softening
+    }
 
-        // Insert all bodies into the tree
-        for body in &self.bodies {
-            tree.insert(body.clone());
+    /// Get the Barnes-Hut tree ratio threshold
+    pub fn tree_ratio(&self) -> Scalar {
+        self.tree_threshold
+    }
+
+    /// Get the force law between bodies
+    pub fn force_law(&self) -> ForceLaw {
+        self.force_law
+    }
+
+    /// Get the Barnes-Hut opening criterion
+    pub fn opening_criterion(&self) -> OpeningCriterion {
+        self.opening
+    }
+
+    /// Set the simulation timestep used by subsequent steps
+    pub fn set_timestep(&mut self, dt: Scalar) {
+        self.timestep = dt;
+    }
+
+    /// Set the gravitational constant used by subsequent steps
+    pub fn set_g(&mut self, g: Scalar) {
+        self.g = g;
+        self.accelerations_current = false;
+    }
+
+    /// Set the softening factor used by subsequent steps.
+    ///
+    /// Softening is part of the force law, so changing it mid-run changes the
+    /// dynamics (and the conserved energy) from that step onwards.
+    pub fn set_softening(&mut self, eps: Scalar) {
+        self.softening = eps;
+        self.body_softening.clear();
+        self.accelerations_current = false;
+    }
+
+    /// Advanced: soften the force along x and y separately, for flattened
+    /// systems whose scales differ between the axes. Each component of a pair
+    /// force uses that axis's softening in place of the isotropic one, so a
+    /// large y softening weakens the y pull of close neighbours while leaving
+    /// their x pull as it was.
+    ///
+    /// Such a force has no potential; `potential_energy` uses the mean of the
+    /// two softenings, so the total energy is only approximately conserved.
+    pub fn set_anisotropic_softening(&mut self, softening: [Scalar; 2]) {
+        self.anisotropic_softening = Some(softening);
+        self.accelerations_current = false;
+    }
+
+    /// Go back to the isotropic softening (the default)
+    pub fn clear_anisotropic_softening(&mut self) {
+        self.anisotropic_softening = None;
+        self.accelerations_current = false;
+    }
+
+    /// Scale each body's softening with the local spacing of the bodies, so
+    /// dense regions are resolved more finely without sparse ones becoming
+    /// noisy (off by default).
+    ///
+    /// At the first Barnes-Hut force evaluation of each step, every body's
+    /// distance `d` to its 8th nearest neighbour is found from the tree, and
+    /// the body's forces are softened with `softening (d / d̄)²` (the
+    /// softening being squared length), where `d̄` is the mean of `d` over
+    /// all bodies. The rest of the step's force evaluations, such as the
+    /// block integrator's substeps, reuse these softenings. Each
+    /// body uses its own softening for the forces on it, so pair forces are
+    /// not quite symmetric and, as the softening changes, energy is only
+    /// approximately conserved; `potential_energy` uses the global softening.
+    /// Anisotropic softening, when set, takes precedence, and the grid force
+    /// method is unaffected.
+    pub fn set_adaptive_softening(&mut self, enabled: bool) {
+        self.adaptive_softening = enabled;
+        self.body_softening.clear();
+        self.accelerations_current = false;
+    }
+
+    /// Each body's softening in the last step with adaptive softening, in
+    /// body order; empty before then or without it
+    pub fn body_softenings(&self) -> &[Scalar] {
+        &self.body_softening
+    }
+
+    /// Set the Barnes-Hut tree ratio threshold used by subsequent steps
+    pub fn set_tree_ratio(&mut self, t: Scalar) {
+        self.tree_threshold = t;
+        self.accelerations_current = false;
+    }
+
+    /// Set the force law used by subsequent steps (Newtonian by default)
+    pub fn set_force_law(&mut self, law: ForceLaw) {
+        self.force_law = law;
+        self.accelerations_current = false;
+    }
+
+    /// Set the Barnes-Hut opening criterion the tree ratio is compared
+    /// against (`OpeningCriterion::Diagonal` by default)
+    pub fn set_opening_criterion(&mut self, opening: OpeningCriterion) {
+        self.opening = opening;
+        self.accelerations_current = false;
+    }
+
+    /// Add a static external acceleration field to every body's acceleration
+    /// (see the `external` module for built-in fields).
+    ///
+    /// The field's potential is not included in `potential_energy`.
+    pub fn set_external_field(&mut self, field: ExternalField) {
+        self.external_field = Some(field);
+        self.accelerations_current = false;
+    }
+
+    /// Accelerate every body by the same constant `acceleration`, such as
+    /// `[0.0, -9.8]` for "down". With a reflecting boundary as the floor this
+    /// makes a simple sandbox.
+    ///
+    /// Shorthand for `set_external_field(external::uniform(acceleration))`,
+    /// so it replaces any other external field.
+    pub fn set_uniform_acceleration(&mut self, acceleration: [Scalar; 2]) {
+        self.set_external_field(crate::external::uniform(acceleration));
+    }
+
+    /// Remove the external acceleration field
+    pub fn clear_external_field(&mut self) {
+        self.external_field = None;
+        self.accelerations_current = false;
+    }
+
+    /// Set how forces between bodies are evaluated (Barnes-Hut by default)
+    pub fn set_force_method(&mut self, method: ForceMethod) {
+        self.force_method = method;
+        self.cached_tree = None;
+        self.accelerations_current = false;
+    }
+
+    /// Sum the force terms on each body with compensated (Kahan) summation
+    /// (off by default). More accurate when large forces nearly cancel, such
+    /// as deep inside a dense, symmetric system, but slower.
+    pub fn set_compensated_summation(&mut self, enabled: bool) {
+        self.compensated_summation = enabled;
+    }
+
+    /// Get the integration scheme
+    pub fn integrator(&self) -> Integrator {
+        self.integrator
+    }
+
+    /// Set the integration scheme used by subsequent steps
+    /// (`Integrator::SymplecticEuler` by default)
+    pub fn set_integrator(&mut self, integrator: Integrator) {
+        self.integrator = integrator;
+    }
+
+    /// Damp every velocity by `v -> v (1 - drag dt)` with each kick, which
+    /// artificially removes kinetic energy so that a hot or collapsing system
+    /// settles instead of bouncing. Explicitly non-conservative; 0 (the
+    /// default) turns it off. The factor is clamped at zero for `drag dt > 1`.
+    pub fn set_drag(&mut self, drag: Scalar) {
+        self.drag = drag.max(0.0);
+    }
+
+    /// Set the boundary applied after each position update (open by default)
+    pub fn set_boundary(&mut self, boundary: BoundaryCondition) {
+        self.boundary = boundary;
+    }
+
+    /// Turn the gravity between bodies on or off (on by default). With it
+    /// off the bodies are test particles moving only in the external field.
+    pub fn set_self_gravity(&mut self, enabled: bool) {
+        self.self_gravity = enabled;
+        self.cached_tree = None;
+        self.accelerations_current = false;
+    }
+
+    /// Experimental: keep the tree between steps and update it in place with
+    /// `QuadTree::update_positions` instead of rebuilding it every step.
+    ///
+    /// This pays off when the timestep is small enough that few bodies change
+    /// cell per step. The tree falls back to a full rebuild whenever too many
+    /// bodies move or one leaves the (slightly padded) tree bounds.
+    pub fn set_incremental_tree(&mut self, enabled: bool) {
+        self.incremental_tree = enabled;
+        self.cached_tree = None;
+    }
+
+    /// Approximation: rebuild the tree only every `k` force evaluations
+    /// (three per step with `Integrator::Yoshida4`, one per finest substep
+    /// with `Integrator::BlockLeapfrog`, one otherwise) and in
+    /// between reuse its structure, recomputing only the node masses and
+    /// centres of mass from the moved bodies (see `QuadTree::refit`).
+    ///
+    /// With a small timestep this saves most of the tree construction cost
+    /// for a small loss of accuracy. 0 and 1 both rebuild every time.
+    pub fn set_tree_rebuild_interval(&mut self, k: usize) {
+        self.tree_rebuild_interval = k.max(1);
+        self.cached_tree = None;
+    }
+
+    /// Stop subdividing tree cells below `depth` (the root is depth 0), or
+    /// subdivide until bodies are separated with `None`, the default.
+    ///
+    /// Bounds the tree's depth and memory on tightly clustered inputs. Leaves
+    /// at the cap keep all their bodies, which then interact pairwise, so
+    /// forces stay exact within a leaf but get slower the more it holds;
+    /// `capped_tree_leaves` tells how many leaves are affected.
+    pub fn set_max_tree_depth(&mut self, depth: Option<usize>) {
+        self.max_tree_depth = depth;
+        self.cached_tree = None;
+    }
+
+    /// Add a body to the system, e.g. one placed interactively.
+    ///
+    /// Its acceleration is computed with the others at the next step. Any
+    /// tree kept between steps is rebuilt, and the energy guard starts over
+    /// since the total energy changes.
+    pub fn add_body(&mut self, body: Body) {
+        self.bodies.push(body);
+        self.cached_tree = None;
+        self.accelerations_current = false;
+        if let Some(guard) = self.energy_guard.as_mut() {
+            guard.last_energy = None;
         }
+    }
 
-        tree
+    /// Add `delta_v` to the velocity of every body within `radius` of
+    /// `center`, an impulsive perturbation such as a supernova or a passing
+    /// object. Returns the number of bodies kicked.
+    ///
+    /// Accelerations are recomputed at the next step, since some force laws
+    /// (the 2.5PN term of `ForceLaw::PostNewtonian`) depend on velocity, and
+    /// the energy guard starts over since the total energy changes.
+    pub fn kick_region(&mut self, center: [Scalar; 2], radius: Scalar, delta_v: [Scalar; 2]) -> usize {
+        let radius_sq = radius * radius;
+        let mut kicked = 0;
+        for body in self.bodies.iter_mut() {
+            let dx = body.position[0] - center[0];
+            let dy = body.position[1] - center[1];
+            if dx * dx + dy * dy <= radius_sq {
+                body.velocity[0] += delta_v[0];
+                body.velocity[1] += delta_v[1];
+                kicked += 1;
+            }
+        }
+
+        if kicked > 0 {
+            self.accelerations_current = false;
+            if let Some(guard) = self.energy_guard.as_mut() {
+                guard.last_energy = None;
+            }
+        }
+        kicked
     }
 
-    /// Calculate accelerations for all bodies using the Barnes-Hut algorithm
-    fn calculate_accelerations(&mut self) {
-        // Build the quad tree
-        let tree = self.build_tree();
-        let g = self.g;
-        let softening = self.softening;
-        let threshold = self.tree_threshold;
+    /// Rescale the system into standard (Hénon/Heggie) N-body units, where
+    /// `G = 1`, the total mass is 1 and the total energy is -1/4.
+    ///
+    /// Masses are divided by the total mass, lengths by
+    /// `G M² / (4 |E|)` and times by the matching unit of `√(L³ / (G M))`.
+    /// The softening, timestep, drag, block timestep accuracy and elapsed
+    /// time are converted with them, so the same orbits are followed, just
+    /// measured in the new units.
+    /// Parameters of non-Newtonian force laws, external fields and boundaries
+    /// are left as they are.
+    ///
+    /// Returns false and changes nothing unless the system has mass and is
+    /// bound (negative total energy).
+    pub fn to_nbody_units(&mut self) -> bool {
+        let total_mass = self.total_mass();
+        let energy = self.total_energy();
+        if total_mass <= 0.0 || energy >= 0.0 || !energy.is_finite() {
+            return false;
+        }
 
-        // Calculate forces/accelerations using parallel or sequential iteration
-        #[cfg(feature = "parallel")]
-        {
-            self.bodies.par_iter_mut().for_each(|body| {
-                // Reset acceleration
-                body.acceleration = [0.0, 0.0];
-                
-                // Calculate force
-                let force = tree.calculate_force(
-                    body,
-                    g,
-                    softening,
-                    threshold
-                );
-
-                // Update acceleration (F = ma -> a = F/m)
-                body.acceleration = [
-                    force[0] / body.mass,
-                    force[1] / body.mass
-                ];
-            });
+        let length = -self.g * total_mass * total_mass / (4.0 * energy);
+        let velocity = (self.g * total_mass / length).sqrt();
+        let time = length / velocity;
+
+        for body in &mut self.bodies {
+            body.mass /= total_mass;
+            body.position = [body.position[0] / length, body.position[1] / length];
+            body.velocity = [body.velocity[0] / velocity, body.velocity[1] / velocity];
+        }
+        self.g = 1.0;
+        // Softening is added to r², so it scales as a squared length
+        self.softening /= length * length;
+        if let Some(ref mut softening) = self.anisotropic_softening {
+            *softening = [softening[0] / (length * length), softening[1] / (length * length)];
+        }
+        self.timestep /= time;
+        self.time /= time;
+        self.drag *= time;
+        if let Integrator::BlockLeapfrog { ref mut accuracy, .. } = self.integrator {
+            *accuracy /= length;
         }
 
-        #[cfg(not(feature = "parallel"))]
-        {
-            self.bodies.iter_mut().for_each(|body| {
-                // Reset acceleration
-                body.acceleration = [0.0, 0.0];
-                
-                // Calculate force
-                let force = tree.calculate_force(
-                    body,
-                    g,
-                    softening,
-                    threshold
-                );
-
-                // Update acceleration (F = ma -> a = F/m)
-                body.acceleration = [
-                    force[0] / body.mass,
-                    force[1] / body.mass
-                ];
-            });
+        self.cached_tree = None;
+        self.accelerations_current = false;
+        if let Some(guard) = self.energy_guard.as_mut() {
+            guard.last_energy = None;
         }
+        true
     }
 
-    /// Update velocities based on current accelerations
-    fn update_velocities(&mut self) {
-        let dt = self.timestep;
-        
-        #[cfg(feature = "parallel")]
-        {
-            self.bodies.par_iter_mut().for_each(|body| {
-                body.update_velocity(dt);
-            });
+    /// Copy the bodies, clock and settings so they can be restored later.
+    ///
+    /// The external field (a closure) and any snapshot sender are not part
+    /// of the copy and are left as they are by `restore`.
+    pub fn snapshot(&self) -> SimulationSnapshot {
+        SimulationSnapshot {
+            bodies: self.bodies.clone(),
+            time: self.time,
+            step_count: self.step_count,
+            timestep: self.timestep,
+            g: self.g,
+            softening: self.softening,
+            anisotropic_softening: self.anisotropic_softening,
+            adaptive_softening: self.adaptive_softening,
+            tree_threshold: self.tree_threshold,
+            force_law: self.force_law,
+            opening: self.opening,
+            energy_guard: self.energy_guard.clone(),
+            incremental_tree: self.incremental_tree,
+            tree_rebuild_interval: self.tree_rebuild_interval,
+            max_tree_depth: self.max_tree_depth,
+            self_gravity: self.self_gravity,
+            force_method: self.force_method,
+            boundary: self.boundary.clone(),
+            integrator: self.integrator,
+            compensated_summation: self.compensated_summation,
+            drag: self.drag,
         }
+    }
 
-        #[cfg(not(feature = "parallel"))]
-        {
-            self.bodies.iter_mut().for_each(|body| {
-                body.update_velocity(dt);
-            });
+    /// Return to the state saved by `snapshot`. Any tree kept between steps
+    /// is dropped and rebuilt from the restored bodies.
+    pub fn restore(&mut self, snapshot: &SimulationSnapshot) {
+        let SimulationSnapshot {
+            bodies,
+            time,
+            step_count,
+            timestep,
+            g,
+            softening,
+            anisotropic_softening,
+            adaptive_softening,
+            tree_threshold,
+            force_law,
+            opening,
+            energy_guard,
+            incremental_tree,
+            tree_rebuild_interval,
+            max_tree_depth,
+            self_gravity,
+            force_method,
+            boundary,
+            integrator,
+            compensated_summation,
+            drag,
+        } = snapshot.clone();
+
+        self.bodies = bodies;
+        self.time = time;
+        self.step_count = step_count;
+        self.timestep = timestep;
+        self.g = g;
+        self.softening = softening;
+        self.anisotropic_softening = anisotropic_softening;
+        self.adaptive_softening = adaptive_softening;
+        self.body_softening.clear();
+        self.tree_threshold = tree_threshold;
+        self.force_law = force_law;
+        self.opening = opening;
+        self.energy_guard = energy_guard;
+        self.incremental_tree = incremental_tree;
+        self.tree_rebuild_interval = tree_rebuild_interval;
+        self.max_tree_depth = max_tree_depth;
+        self.self_gravity = self_gravity;
+        self.force_method = force_method;
+        self.boundary = boundary;
+        self.integrator = integrator;
+        self.compensated_summation = compensated_summation;
+        self.drag = drag;
+        self.cached_tree = None;
+        self.tree_age = 0;
+        self.accelerations_current = false;
+    }
+
+    /// Total mass of all bodies
+    pub fn total_mass(&self) -> Scalar {
+        self.bodies.iter().map(|body| body.mass).sum()
+    }
+
+    /// Mass-weighted mean position of all bodies (the origin if massless)
+    pub fn center_of_mass(&self) -> [Scalar; 2] {
+        let total_mass = self.total_mass();
+        if total_mass == 0.0 {
+            return [0.0, 0.0];
         }
+
+        let weighted = self.bodies.iter().fold([0.0, 0.0], |acc, body| {
+            [acc[0] + body.mass * body.position[0], acc[1] + body.mass * body.position[1]]
+        });
+        [weighted[0] / total_mass, weighted[1] / total_mass]
     }
 
-    /// Update positions based on current velocities
-    fn update_positions(&mut self) {
-        let dt = self.timestep;
-        
+    /// Smallest and largest finite x and y over all bodies, as `(min, max)`.
+    /// With no finite coordinates on an axis it is `INFINITY` in `min` and
+    /// `NEG_INFINITY` in `max`.
+    pub fn extents(&self) -> ([Scalar; 2], [Scalar; 2]) {
+        // min/max is order independent, so the parallel reduction gives
+        // exactly the sequential result
         #[cfg(feature = "parallel")]
-        {
-            self.bodies.par_iter_mut().for_each(|body| {
-                body.update_position(dt);
-            });
-        }
+        let extents = parallel_extents(&self.bodies);
 
         #[cfg(not(feature = "parallel"))]
-        {
-            self.bodies.iter_mut().for_each(|body| {
-                body.update_position(dt);
-            });
-        }
+        let extents = sequential_extents(&self.bodies);
+
+        extents
     }
 
-    /// Perform one simulation step
-    pub fn step(&mut self) {
-        // Calculate new accelerations
-        self.calculate_accelerations();
+    /// Total linear momentum of all bodies
+    pub fn momentum(&self) -> [Scalar; 2] {
+        self.bodies.iter().fold([0.0, 0.0], |acc, body| {
+            [acc[0] + body.mass * body.velocity[0], acc[1] + body.mass * body.velocity[1]]
+        })
+    }
 
-        // Update velocities and positions
-        self.update_velocities();
-        self.update_positions();
+    /// Total angular momentum of all bodies about the origin (the z
+    /// component; positive is counterclockwise)
+    pub fn angular_momentum(&self) -> Scalar {
+        self.bodies
+            .iter()
+            .map(|body| body.mass * (body.position[0] * body.velocity[1] - body.position[1] * body.velocity[0]))
+            .sum()
     }
 
-    /// Get a reference to the quad tree for visualization purposes
-    pub fn get_tree(&self) -> QuadTree {
-        self.build_tree()
+    /// Total kinetic energy of all bodies
+    pub fn kinetic_energy(&self) -> Scalar {
+        self.bodies.iter().map(Body::kinetic_energy).sum()
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Histogram of body speeds in `bins` equal bins from 0 to the highest
+    /// speed, as `(lower edge, count)` pairs; the fastest body is counted in
+    /// the last bin. A relaxed system approaches a Maxwell-Boltzmann-like
+    /// shape. Empty without bodies or bins.
+    pub fn speed_histogram(&self, bins: usize) -> Vec<(Scalar, usize)> {
+        if bins == 0 || self.bodies.is_empty() {
+            return Vec::new();
+        }
 
-    #[test]
-    fn test_simulation_creation() {
-        let bodies = vec![
-            Body::new(1.0, 0.0, 0.0, 0.0, 0.0),
-            Body::new(1.0, 1.0, 0.0, 0.0, 0.0),
-        ];
-        let sim = Simulation::new(bodies, 0.1, 1.0, 0.001, 0.5);
-        assert_eq!(sim.bodies.len(), 2);
+        let speeds: Vec<Scalar> = self.bodies.iter().map(Body::speed).collect();
+        let max_speed = speeds.iter().cloned().fold(0.0, Scalar::max);
+        let width = max_speed / bins as Scalar;
+
+        let mut counts = vec![0; bins];
+        for speed in speeds {
+            let bin = if width > 0.0 { (speed / width) as usize } else { 0 };
+            counts[bin.min(bins - 1)] += 1;
+        }
+        counts.into_iter().enumerate().map(|(i, count)| (i as Scalar * width, count)).collect()
     }
 
-    #[test]
-    fn test_bounds_growth() {
-        // Create two bodies moving outward
-        let bodies = vec![
-            Body::new(1.0, -1.0, -1.0, -1.0, -1.0),  // Moving left and down
-            Body::new(1.0, 1.0, 1.0, 1.0, 1.0),      // Moving right and up
-        ];
-        let mut sim = Simulation::new(bodies, 0.1, 0.0, 0.001, 0.5);  // Set g=0 to prevent attraction
+    /// Lagrangian radii: for each of `fractions` (of the total mass, from 0
+    /// to 1), the distance from the centre of mass within which that
+    /// fraction of the mass lies, i.e. the distance of the body at which the
+    /// mass enclosed, counting bodies outwards, first reaches it.
+    ///
+    /// Followed over time, the inner radii shrinking while the outer ones
+    /// grow is the signature of core collapse. All zero without bodies.
+    pub fn lagrangian_radii(&self, fractions: &[Scalar]) -> Vec<Scalar> {
+        if self.bodies.is_empty() {
+            return vec![0.0; fractions.len()];
+        }
 
-        // Get initial bounds
-        let initial_bounds = sim.get_tree().get_bounds().clone();
+        let com = self.center_of_mass();
+        let mut shells: Vec<(Scalar, Scalar)> = self.bodies
+            .iter()
+            .map(|body| {
+                let dx = body.position[0] - com[0];
+                let dy = body.position[1] - com[1];
+                ((dx * dx + dy * dy).sqrt(), body.mass)
+            })
+            .collect();
+        shells.sort_by(|a, b| a.0.total_cmp(&b.0));
 
-        // Step simulation several times
-        for _ in 0..10 {
-            sim.step();
+        let enclosed: Vec<Scalar> = shells
+            .iter()
+            .scan(0.0, |sum, &(_, mass)| {
+                *sum += mass;
+                Some(*sum)
+            })
+            .collect();
+        let total = enclosed[enclosed.len() - 1];
+
+        fractions
+            .iter()
+            .map(|&fraction| {
+                let index = enclosed.partition_point(|&mass| mass < fraction * total);
+                shells[index.min(shells.len() - 1)].0
+            })
+            .collect()
+    }
+
+    /// Total gravitational potential energy, approximated with the Barnes-Hut tree.
+    ///
+    /// Under `ForceLaw::Modified` this is still the Newtonian potential, so the
+    /// total energy is not conserved by that law.
+    pub fn potential_energy(&self) -> Scalar {
+        if !self.self_gravity {
+            return 0.0;
         }
 
-        // Get new bounds
-        let binding = sim.get_tree();
-        let new_bounds = binding.get_bounds();
+        // Per-body potentials may be computed in parallel, but they are summed
+        // sequentially so the result does not depend on the thread count.
+        // Every pair is counted from both sides.
+        0.5 * self.body_potentials().iter().sum::<Scalar>()
+    }
 
-        // Verify bounds have grown
-        assert!(new_bounds.min[0] < initial_bounds.min[0]);
-        assert!(new_bounds.min[1] < initial_bounds.min[1]);
-        assert!(new_bounds.max[0] > initial_bounds.max[0]);
-        assert!(new_bounds.max[1] > initial_bounds.max[1]);
+    /// Fraction of the bodies that are gravitationally bound: those whose
+    /// kinetic energy in the centre-of-mass frame plus their potential energy
+    /// in the field of all the others is negative.
+    ///
+    /// The standard measure of how much of a system survives a disruptive
+    /// event such as a collision. Potentials come from the force method (the
+    /// tree by default); the external field is not included. 0 for an empty
+    /// system.
+    pub fn bound_fraction(&self) -> Scalar {
+        if self.bodies.is_empty() {
+            return 0.0;
+        }
+
+        let total_mass = self.total_mass();
+        let momentum = self.momentum();
+        let drift = if total_mass > 0.0 {
+            [momentum[0] / total_mass, momentum[1] / total_mass]
+        } else {
+            [0.0, 0.0]
+        };
+
+        let potentials = self.body_potentials();
+        let bound = self.bodies
+            .iter()
+            .zip(&potentials)
+            .filter(|&(body, &potential)| {
+                let vx = body.velocity[0] - drift[0];
+                let vy = body.velocity[1] - drift[1];
+                0.5 * body.mass * (vx * vx + vy * vy) + potential < 0.0
+            })
+            .count();
+        bound as Scalar / self.bodies.len() as Scalar
     }
 
-    #[test]
-    fn test_simulation_step() {
-        // Create two bodies that should attract each other
-        let bodies = vec![
-            Body::new(1.0, -0.5, 0.0, 0.0, 0.0),
-            Body::new(1.0, 0.5, 0.0, 0.0, 0.0),
-        ];
+    /// Each body's potential energy in the field of all the others, zero
+    /// without self-gravity
+    fn body_potentials(&self) -> Vec<Scalar> {
+        if !self.self_gravity {
+            return vec![0.0; self.bodies.len()];
+        }
 
-        let mut sim = Simulation::new(bodies, 0.1, 1.0, 0.001, 0.5);
+        let solver = self.build_solver();
+        let params = self.force_params();
 
-        // Store initial positions
-        let initial_x1 = sim.bodies[0].position[0];
-        let initial_x2 = sim.bodies[1].position[0];
+        #[cfg(feature = "parallel")]
+        let potentials = self.bodies
+            .par_iter()
+            .map(|body| solver.force(body, &params, true).1)
+            .collect();
 
-        // Run one step
-        sim.step();
+        #[cfg(not(feature = "parallel"))]
+        let potentials = self.bodies
+            .iter()
+            .map(|body| solver.force(body, &params, true).1)
+            .collect();
 
-        // Bodies should move towards each other
-        assert!(sim.bodies[0].position[0] > initial_x1);
-        assert!(sim.bodies[1].position[0] < initial_x2);
+        potentials
+    }
+
+    /// Mean number of tree nodes a force evaluation opens for the current
+    /// positions, tree ratio and opening criterion.
+    ///
+    /// A direct measure of the cost side of the Barnes-Hut accuracy trade-off,
+    /// for tuning `tree_ratio` and the criterion. Measured on the full tree
+    /// whatever the force method.
+    pub fn mean_nodes_opened(&self) -> Scalar {
+        if self.bodies.is_empty() {
+            return 0.0;
+        }
+
+        let tree = self.build_tree();
+        let params = self.force_params();
+
+        #[cfg(feature = "parallel")]
+        let opened: usize = self.bodies.par_iter().map(|body| tree.nodes_opened(body, &params)).sum();
+
+        #[cfg(not(feature = "parallel"))]
+        let opened: usize = self.bodies.iter().map(|body| tree.nodes_opened(body, &params)).sum();
+
+        opened as Scalar / self.bodies.len() as Scalar
+    }
+
+    /// Virial ratio `2T / |U|` of the kinetic and potential energy.
+    ///
+    /// A self-gravitating system in equilibrium has a ratio of about 1; below
+    /// 1 it will collapse and above 1 it will expand. Without self-gravity
+    /// (or with a single body) the potential is zero and the ratio infinite.
+    pub fn virial_ratio(&self) -> Scalar {
+        2.0 * self.kinetic_energy() / self.potential_energy().abs()
+    }
+
+    /// Time for a typical body to cross the system: the virial radius
+    /// `G M² / (2 |U|)` over the rms speed relative to the mean motion.
+    ///
+    /// Unlike the half-mass radius, the virial radius does not depend on
+    /// where the centre is taken to be, so a few far-flung bodies barely
+    /// change it. Infinite for a system at rest, without mass or without
+    /// self-gravity.
+    pub fn crossing_time(&self) -> Scalar {
+        let total_mass = self.total_mass();
+        let potential = self.potential_energy();
+        if total_mass <= 0.0 || potential == 0.0 {
+            return Scalar::INFINITY;
+        }
+
+        let momentum = self.momentum();
+        let drift = [momentum[0] / total_mass, momentum[1] / total_mass];
+        let speed_sq: Scalar = self.bodies
+            .iter()
+            .map(|body| {
+                let vx = body.velocity[0] - drift[0];
+                let vy = body.velocity[1] - drift[1];
+                body.mass * (vx * vx + vy * vy)
+            })
+            .sum::<Scalar>()
+            / total_mass;
+
+        let virial_radius = self.g * total_mass * total_mass / (2.0 * potential.abs());
+        virial_radius / speed_sq.sqrt()
     }
-}
\ No newline at end of file
+
+    /// Two-body relaxation time `N / (8 ln N)` crossing times (Binney &
+    /// Tremaine eq. 1.38), after which encounters between individual bodies
+    /// have changed their velocities as much as the smooth potential has.
+    ///
+    /// The estimate assumes a roughly virialized, self-gravitating system of
+    /// similar masses and takes the Coulomb logarithm as `ln N`, so it is
+    /// good to a factor of a few at best. It comes from 3D clusters and
+    /// means little when one heavy body dominates, as in the default disk.
+    /// Infinite for fewer than two bodies.
+    pub fn relaxation_time(&self) -> Scalar {
+        let n = self.bodies.len() as Scalar;
+        if n < 2.0 {
+            return Scalar::INFINITY;
+        }
+        n / (8.0 * n.ln()) * self.crossing_time()
+    }
+
+    /// Gravitational potential per unit mass sampled on a `resolution` x
+    /// `resolution` grid over `bounds`, for contour plots or heatmaps.
+    ///
+    /// Samples are taken at the cell centres, row by row from `bounds.min`
+    /// (x varies fastest). Each is the potential a massless probe would feel,
+    /// evaluated through the tree (or grid) like the forces, so the cost is
+    /// O(resolution² log n). Without self-gravity every sample is zero.
+    pub fn potential_grid(&self, resolution: usize, bounds: Bounds) -> Vec<Scalar> {
+        if !self.self_gravity {
+            return vec![0.0; resolution * resolution];
+        }
+
+        let solver = self.build_solver();
+        let params = self.force_params();
+        let dx = (bounds.max[0] - bounds.min[0]) / resolution as Scalar;
+        let dy = (bounds.max[1] - bounds.min[1]) / resolution as Scalar;
+        let sample = |index: usize| {
+            let x = bounds.min[0] + ((index % resolution) as Scalar + 0.5) * dx;
+            let y = bounds.min[1] + ((index / resolution) as Scalar + 0.5) * dy;
+            solver.force(&Body::new(1.0, x, y, 0.0, 0.0), &params, true).1
+        };
+
+        #[cfg(feature = "parallel")]
+        let samples = (0..resolution * resolution).into_par_iter().map(sample).collect();
+
+        #[cfg(not(feature = "parallel"))]
+        let samples = (0..resolution * resolution).map(sample).collect();
+
+        samples
+    }
+
+    /// Number of leaves of the tree for the current positions that reached
+    /// the depth limit holding more than one body (see `set_max_tree_depth`)
+    pub fn capped_tree_leaves(&self) -> usize {
+        self.build_tree().capped_leaves()
+    }
+
+    /// Acceleration a massless probe at `point` would feel from the bodies
+    /// and any external field, e.g. for test particle orbits.
+    ///
+    /// The probe is evaluated through the tree (or grid) like a body, so the
+    /// same approximation applies. Each call builds the tree for the current
+    /// positions; use `accelerations_at` for many points.
+    pub fn acceleration_at(&self, point: [Scalar; 2]) -> [Scalar; 2] {
+        self.accelerations_at(&[point])[0]
+    }
+
+    /// Accelerations at each of `points`, as `acceleration_at` gives them,
+    /// building the tree once for all of them. Suited to drawing a vector
+    /// field over the system.
+    pub fn accelerations_at(&self, points: &[[Scalar; 2]]) -> Vec<[Scalar; 2]> {
+        let solver = if self.self_gravity { Some(self.build_solver()) } else { None };
+        let params = self.force_params();
+        let external_field = self.external_field.as_deref();
+        let probe = |&[x, y]: &[Scalar; 2]| {
+            // A unit mass, so the force is the acceleration
+            let mut acceleration = match solver {
+                Some(ref solver) => solver.force(&Body::new(1.0, x, y, 0.0, 0.0), &params, false).0,
+                None => [0.0, 0.0],
+            };
+            if let Some(field) = external_field {
+                let external = field([x, y]);
+                acceleration = [acceleration[0] + external[0], acceleration[1] + external[1]];
+            }
+            acceleration
+        };
+
+        #[cfg(feature = "parallel")]
+        let accelerations = points.par_iter().map(probe).collect();
+
+        #[cfg(not(feature = "parallel"))]
+        let accelerations = points.iter().map(probe).collect();
+
+        accelerations
+    }
+
+    /// Total energy (kinetic plus potential)
+    pub fn total_energy(&self) -> Scalar {
+        self.kinetic_energy() + self.potential_energy()
+    }
+
+    /// Enable a watchdog on the total energy.
+    ///
+    /// The energy is evaluated every step (in the same tree traversal as the
+    /// forces). If it changes by more than `max_rel_jump` (relative) in one
+    /// step, typically because of a bad close encounter, the timestep is
+    /// halved for the next few steps. Repeated jumps keep halving it.
+    pub fn set_energy_guard(&mut self, max_rel_jump: Scalar) {
+        self.energy_guard = Some(EnergyGuard {
+            max_rel_jump,
+            last_energy: None,
+            dt_factor: 1.0,
+            brake_steps: 0,
+        });
+    }
+
+    /// Disable the energy watchdog
+    pub fn clear_energy_guard(&mut self) {
+        self.energy_guard = None;
+    }
+
+    /// The timestep the next step will use, after any energy guard braking
+    pub fn current_timestep(&self) -> Scalar {
+        match self.energy_guard {
+            Some(ref guard) => self.timestep * guard.dt_factor,
+            None => self.timestep,
+        }
+    }
+
+    /// Publish a position snapshot on `sender` after every step.
+    ///
+    /// Each snapshot is a flat `[x0, y0, x1, y1, ...]` vector containing every
+    /// `stride`-th body. Sending never blocks the simulation: if the channel is
+    /// full the snapshot is dropped, and if the receiver has hung up the
+    /// sender is detached.
+    #[cfg(feature = "std")]
+    pub fn attach_snapshot_sender(&mut self, sender: SyncSender<Vec<f32>>, stride: usize) {
+        self.snapshot_sender = Some(SnapshotSender {
+            sender,
+            stride: stride.max(1),
+        });
+    }
+
+    /// Stop publishing position snapshots
+    #[cfg(feature = "std")]
+    pub fn detach_snapshot_sender(&mut self) {
+        self.snapshot_sender = None;
+    }
+
+    /// Send a snapshot of the current positions to the attached channel, if any
+    #[cfg(feature = "std")]
+    fn publish_snapshot(&mut self) {
+        let Some(ref snapshot) = self.snapshot_sender else {
+            return;
+        };
+
+        let positions: Vec<f32> = self.bodies
+            .iter()
+            .step_by(snapshot.stride)
+            .flat_map(|body| [body.position[0] as f32, body.position[1] as f32])
+            .collect();
+
+        if let Err(TrySendError::Disconnected(_)) = snapshot.sender.try_send(positions) {
+            self.snapshot_sender = None;
+        }
+    }
+
+    /// Calculate the boundaries that contain all bodies
+    fn compute_bounds(&self) -> Bounds {
+        if self.bodies.is_empty() {
+            return Bounds::new([-1.0, -1.0], [1.0, 1.0]); // Default bounds for empty system
+        }
+
+        // Find the actual extents of all bodies
+        let (min, max) = self.extents();
+
+        // Non-finite coordinates are skipped, so only a system with no
+        // finite positions at all ends up here
+        if !min.iter().chain(max.iter()).all(|v| v.is_finite()) {
+            return Bounds::new([-1.0, -1.0], [1.0, 1.0]);
+        }
+
+        let [mut min_x, mut min_y] = min;
+        let [mut max_x, mut max_y] = max;
+
+        // Handle the case where all bodies are at exactly the same point.
+        // The padding is relative so it survives rounding away from the origin.
+        if (max_x - min_x).abs() < Scalar::EPSILON {
+            let pad = Scalar::EPSILON * max_x.abs().max(1.0);
+            max_x += pad;
+            min_x -= pad;
+        }
+        if (max_y - min_y).abs() < Scalar::EPSILON {
+            let pad = Scalar::EPSILON * max_y.abs().max(1.0);
+            max_y += pad;
+            min_y -= pad;
+        }
+
+        Bounds::new([min_x, min_y], [max_x, max_y])
+    }
+
+    fn force_params(&self) -> ForceParams {
+        ForceParams {
+            g: self.g,
+            softening: self.softening,
+            threshold: self.tree_threshold,
+            law: self.force_law,
+            opening: self.opening,
+            compensated: self.compensated_summation,
+            anisotropic_softening: self.anisotropic_softening,
+        }
+    }
+
+    /// Build the structure the force method evaluates forces with
+    fn build_solver(&self) -> ForceSolver {
+        match self.force_method {
+            ForceMethod::BarnesHut => ForceSolver::Tree(self.build_tree()),
+            ForceMethod::GridCutoff { cutoff } => ForceSolver::Grid(SpatialHash::new(&self.bodies, cutoff)),
+        }
+    }
+
+    /// Build the quad tree from the current body positions
+    fn build_tree(&self) -> QuadTree {
+        self.build_tree_in(self.compute_bounds())
+    }
+
+    fn build_tree_in(&self, bounds: Bounds) -> QuadTree {
+        let mut tree = match self.max_tree_depth {
+            Some(depth) => QuadTree::with_max_depth(bounds, depth),
+            None => QuadTree::new(bounds),
+        };
+
+        // Insert all bodies into the tree. The bounds cover every finite
+        // position, so only bodies with a NaN or infinite coordinate are
+        // rejected, and they are left out of the forces.
+        for body in &self.bodies {
+            let _ = tree.insert(body.clone());
+        }
+
+        tree
+    }
+
+    /// Take the tree for the current positions: the cached tree refitted or
+    /// updated in place if possible, otherwise a newly built one
+    fn take_tree(&mut self) -> QuadTree {
+        if let Some(mut tree) = self.cached_tree.take() {
+            if self.tree_age < self.tree_rebuild_interval {
+                if tree.refit(&self.bodies) {
+                    self.tree_age += 1;
+                    return tree;
+                }
+            } else if self.incremental_tree && tree.update_positions(&self.bodies) {
+                self.tree_age = 1;
+                return tree;
+            }
+        }
+        self.tree_age = 1;
+
+        if !self.incremental_tree {
+            return self.build_tree();
+        }
+
+        let bounds = self.compute_bounds();
+        let pad_x = (bounds.max[0] - bounds.min[0]) * INCREMENTAL_TREE_PADDING;
+        let pad_y = (bounds.max[1] - bounds.min[1]) * INCREMENTAL_TREE_PADDING;
+        self.build_tree_in(Bounds::new(
+            [bounds.min[0] - pad_x, bounds.min[1] - pad_y],
+            [bounds.max[0] + pad_x, bounds.max[1] + pad_y],
+        ))
+    }
+
+    /// Calculate accelerations for all bodies using the Barnes-Hut algorithm.
+    ///
+    /// When `with_potential` is set, the potential energy of the system is
+    /// accumulated in the same tree traversal and returned; otherwise the
+    /// return value is zero.
+    fn calculate_accelerations(&mut self, with_potential: bool) -> Scalar {
+        self.calculate_accelerations_of(with_potential, None)
+    }
+
+    /// `calculate_accelerations` for only the bodies marked in `active`
+    /// (all of them with `None`); every body still contributes to the forces.
+    /// With `with_potential` all bodies are evaluated, whatever `active` says.
+    fn calculate_accelerations_of(&mut self, with_potential: bool, active: Option<&[bool]>) -> Scalar {
+        // Build (or update) the quad tree, or bin the bodies for the grid
+        let solver = match self.force_method {
+            _ if !self.self_gravity => None,
+            ForceMethod::BarnesHut => Some(ForceSolver::Tree(self.take_tree())),
+            ForceMethod::GridCutoff { cutoff } => Some(ForceSolver::Grid(SpatialHash::new(&self.bodies, cutoff))),
+        };
+        let params = self.force_params();
+        let external_field = self.external_field.as_deref();
+
+        match solver {
+            Some(ForceSolver::Tree(ref tree)) if self.adaptive_softening => {
+                // `step` clears them, so they are found once per step
+                if self.body_softening.len() != self.bodies.len() {
+                    self.body_softening = adaptive_softenings(tree, &self.bodies, self.softening);
+                }
+            }
+            _ => self.body_softening.clear(),
+        }
+        let body_softening = &self.body_softening;
+
+        // Calculate force, update acceleration (F = ma -> a = F/m) and return
+        // the body's potential energy
+        let accelerate = |(i, body): (usize, &mut Body)| -> Scalar {
+            let params = match body_softening.get(i) {
+                Some(&softening) => ForceParams { softening, ..params },
+                None => params,
+            };
+            let (force, potential) = match solver {
+                Some(ref solver) => solver.force(body, &params, with_potential),
+                None => ([0.0, 0.0], 0.0),
+            };
+
+            body.acceleration = [
+                force[0] / body.mass,
+                force[1] / body.mass
+            ];
+            if let Some(field) = external_field {
+                let external = field(body.position);
+                body.acceleration[0] += external[0];
+                body.acceleration[1] += external[1];
+            }
+            potential
+        };
+
+        // Calculate forces/accelerations using parallel or sequential iteration.
+        // Potentials are summed sequentially so the result is deterministic.
+        #[cfg(feature = "parallel")]
+        let potentials: Vec<Scalar> = match active {
+            _ if with_potential => self.bodies.par_iter_mut().enumerate().map(accelerate).collect(),
+            Some(active) => {
+                self.bodies.par_iter_mut().enumerate().zip(active).for_each(|(body, &active)| {
+                    if active {
+                        accelerate(body);
+                    }
+                });
+                Vec::new()
+            }
+            None => {
+                self.bodies.par_iter_mut().enumerate().for_each(|body| { accelerate(body); });
+                Vec::new()
+            }
+        };
+
+        #[cfg(not(feature = "parallel"))]
+        let potentials: Vec<Scalar> = match active {
+            _ if with_potential => self.bodies.iter_mut().enumerate().map(accelerate).collect(),
+            Some(active) => {
+                self.bodies.iter_mut().enumerate().zip(active).for_each(|(body, &active)| {
+                    if active {
+                        accelerate(body);
+                    }
+                });
+                Vec::new()
+            }
+            None => {
+                self.bodies.iter_mut().enumerate().for_each(|body| { accelerate(body); });
+                Vec::new()
+            }
+        };
+
+        if let Some(ForceSolver::Tree(tree)) = solver {
+            if self.incremental_tree || self.tree_rebuild_interval > 1 {
+                self.cached_tree = Some(tree);
+            }
+        }
+
+        // Every pair is counted from both sides
+        0.5 * potentials.iter().sum::<Scalar>()
+    }
+
+    /// Update velocities based on current accelerations, then apply any drag
+    fn update_velocities(&mut self, dt: Scalar) {
+        let damping = (1.0 - self.drag * dt).max(0.0);
+        let kick = |body: &mut Body| {
+            body.update_velocity(dt);
+            if damping < 1.0 {
+                body.velocity = [body.velocity[0] * damping, body.velocity[1] * damping];
+            }
+        };
+
+        #[cfg(feature = "parallel")]
+        self.bodies.par_iter_mut().for_each(kick);
+
+        #[cfg(not(feature = "parallel"))]
+        self.bodies.iter_mut().for_each(kick);
+    }
+
+    /// Update positions based on current velocities, then apply the boundary
+    fn update_positions(&mut self, dt: Scalar) {
+        let boundary = &self.boundary;
+
+        #[cfg(feature = "parallel")]
+        {
+            self.bodies.par_iter_mut().for_each(|body| {
+                body.update_position(dt);
+                boundary.apply(body);
+            });
+        }
+
+        #[cfg(not(feature = "parallel"))]
+        {
+            self.bodies.iter_mut().for_each(|body| {
+                body.update_position(dt);
+                boundary.apply(body);
+            });
+        }
+    }
+
+    /// Calculate new accelerations, checking the energy (which needs the
+    /// potential too) against the energy guard if there is one
+    fn update_accelerations(&mut self, check_energy: bool) {
+        let guarded = check_energy && self.energy_guard.is_some();
+        let kinetic = if guarded { self.kinetic_energy() } else { 0.0 };
+        let potential = self.calculate_accelerations(guarded);
+
+        if let Some(guard) = self.energy_guard.as_mut().filter(|_| guarded) {
+            guard.check(kinetic + potential);
+        }
+    }
+
+    /// Alternate drifts and kicks of the given fractions of `dt`, starting
+    /// and ending with a drift. The energy guard checks the first kick only.
+    fn drift_kick(&mut self, drifts: &[Scalar], kicks: &[Scalar], dt: Scalar) {
+        for (i, &kick) in kicks.iter().enumerate() {
+            self.update_positions(drifts[i] * dt);
+            self.update_accelerations(i == 0);
+            self.update_velocities(kick * dt);
+        }
+        self.update_positions(drifts[kicks.len()] * dt);
+    }
+
+    /// Half-kick the bodies whose substeps begin or end at `boundary`, one
+    /// of the `2^(levels - 1)` finest substeps of length `h` into the step
+    fn kick_rungs(&mut self, rungs: &[u32], levels: u32, boundary: usize, h: Scalar) {
+        let drag = self.drag;
+        let kick = |(body, &rung): (&mut Body, &u32)| {
+            let period = 1usize << (levels - 1 - rung);
+            if boundary.is_multiple_of(period) {
+                let dt = 0.5 * h * period as Scalar;
+                let damping = (1.0 - drag * dt).max(0.0);
+                body.update_velocity(dt);
+                if damping < 1.0 {
+                    body.velocity = [body.velocity[0] * damping, body.velocity[1] * damping];
+                }
+            }
+        };
+
+        #[cfg(feature = "parallel")]
+        self.bodies.par_iter_mut().zip(rungs).for_each(kick);
+
+        #[cfg(not(feature = "parallel"))]
+        self.bodies.iter_mut().zip(rungs).for_each(kick);
+    }
+
+    /// One `Integrator::BlockLeapfrog` step of `dt`
+    fn block_step(&mut self, dt: Scalar, levels: u32, accuracy: Scalar) {
+        let levels = levels.clamp(1, 16);
+        // The guard needs the potential at the start of the step
+        if !self.accelerations_current || self.energy_guard.is_some() {
+            self.update_accelerations(true);
+        }
+
+        let rungs: Vec<u32> = self.bodies
+            .iter()
+            .map(|body| {
+                let a_sq = body.acceleration[0] * body.acceleration[0]
+                    + body.acceleration[1] * body.acceleration[1];
+                let mut rung = 0;
+                let mut h = dt;
+                while rung + 1 < levels && a_sq * h * h * h * h > accuracy * accuracy {
+                    rung += 1;
+                    h *= 0.5;
+                }
+                rung
+            })
+            .collect();
+
+        // Only subdivide as far as the finest rung in use
+        let levels = rungs.iter().max().map_or(1, |&rung| rung + 1);
+        let substeps = 1usize << (levels - 1);
+        let h = dt / substeps as Scalar;
+        for substep in 0..substeps {
+            self.kick_rungs(&rungs, levels, substep, h);
+            self.update_positions(h);
+
+            let boundary = substep + 1;
+            let active: Vec<bool> = rungs
+                .iter()
+                .map(|&rung| boundary.is_multiple_of(1 << (levels - 1 - rung)))
+                .collect();
+            self.calculate_accelerations_of(false, Some(&active));
+            self.kick_rungs(&rungs, levels, boundary, h);
+        }
+
+        // Every body ended its last substep with a force evaluation
+        self.accelerations_current = true;
+    }
+
+    /// Perform one simulation step.
+    ///
+    /// Stepping is deterministic: the same bodies and settings give
+    /// bit-identical results with or without the `parallel` feature and for
+    /// any number of threads. Each body's force is summed on its own in a
+    /// fixed tree order, and the only reductions across bodies (the bounds
+    /// and the potential energy) are either exact or done sequentially.
+    /// Any new cross-body reduction has to keep this property.
+    pub fn step(&mut self) {
+        let dt = self.current_timestep();
+        self.body_softening.clear();
+
+        match self.integrator {
+            Integrator::SymplecticEuler => {
+                self.update_accelerations(true);
+                self.update_velocities(dt);
+                self.update_positions(dt);
+            }
+            Integrator::Leapfrog => self.drift_kick(&LEAPFROG_DRIFTS, &LEAPFROG_KICKS, dt),
+            Integrator::Yoshida4 => self.drift_kick(&YOSHIDA_DRIFTS, &YOSHIDA_KICKS, dt),
+            Integrator::BlockLeapfrog { levels, accuracy } => self.block_step(dt, levels, accuracy),
+        }
+        // The other integrators leave accelerations from before the last drift
+        if !matches!(self.integrator, Integrator::BlockLeapfrog { .. }) {
+            self.accelerations_current = false;
+        }
+
+        self.time += dt;
+        self.step_count += 1;
+
+        #[cfg(feature = "std")]
+        self.publish_snapshot();
+    }
+
+    /// Get a reference to the quad tree for visualization purposes
+    pub fn get_tree(&self) -> QuadTree {
+        self.build_tree()
+    }
+
+    /// A tree over the current positions that stops subdividing at
+    /// `max_depth`, for drawing the wireframe.
+    ///
+    /// It has the same root bounds, and so gives the same view, as
+    /// `get_tree`, but is much cheaper to build when only the top levels are
+    /// shown. Forces are always evaluated with the full tree.
+    pub fn display_tree(&self, max_depth: usize) -> QuadTree {
+        let mut tree = QuadTree::with_max_depth(self.compute_bounds(), max_depth);
+        for body in &self.bodies {
+            let _ = tree.insert(body.clone());
+        }
+        tree
+    }
+}
+
+/// One-line-per-item summary of the simulation state.
+///
+/// Only O(n) quantities are shown by default; the alternate form (`{:#}`)
+/// adds the potential and total energy, which need a force pass.
+impl fmt::Display for Simulation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let com = self.center_of_mass();
+        writeln!(f, "Simulation: {} bodies, total mass {:e}", self.bodies.len(), self.total_mass())?;
+        writeln!(f, "  time {} (step {}), dt {}", self.time, self.step_count, self.timestep)?;
+        writeln!(
+            f,
+            "  G {:e}, softening {}, tree ratio {}",
+            self.g, self.softening, self.tree_threshold
+        )?;
+        writeln!(f, "  center of mass ({:e}, {:e})", com[0], com[1])?;
+        write!(f, "  kinetic energy {:e}", self.kinetic_energy())?;
+        if f.alternate() {
+            let potential = self.potential_energy();
+            write!(
+                f,
+                "\n  potential energy {:e}\n  total energy {:e}\n  bound fraction {}",
+                potential,
+                self.kinetic_energy() + potential,
+                self.bound_fraction()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+type Extents = ([Scalar; 2], [Scalar; 2]);
+
+const EMPTY_EXTENTS: Extents = ([Scalar::INFINITY; 2], [Scalar::NEG_INFINITY; 2]);
+
+/// Grow the extents to include a point, skipping non-finite coordinates
+fn extend_extents((mut min, mut max): Extents, point: [Scalar; 2]) -> Extents {
+    for axis in 0..2 {
+        if point[axis].is_finite() {
+            min[axis] = min[axis].min(point[axis]);
+            max[axis] = max[axis].max(point[axis]);
+        }
+    }
+    (min, max)
+}
+
+/// Combine two partial extents into one covering both
+#[cfg(feature = "parallel")]
+fn merge_extents(a: Extents, b: Extents) -> Extents {
+    (
+        [a.0[0].min(b.0[0]), a.0[1].min(b.0[1])],
+        [a.1[0].max(b.1[0]), a.1[1].max(b.1[1])],
+    )
+}
+
+/// Extents of the finite coordinates of `bodies`, on one thread
+pub(crate) fn sequential_extents(bodies: &[Body]) -> Extents {
+    bodies
+        .iter()
+        .fold(EMPTY_EXTENTS, |acc, body| extend_extents(acc, body.position))
+}
+
+#[cfg(feature = "parallel")]
+fn parallel_extents(bodies: &[Body]) -> Extents {
+    bodies
+        .par_iter()
+        .fold(|| EMPTY_EXTENTS, |acc, body| extend_extents(acc, body.position))
+        .reduce(|| EMPTY_EXTENTS, merge_extents)
+}
+
+/// Softening for each body scaled by the square of its distance to its
+/// `ADAPTIVE_SOFTENING_NEIGHBORS`th neighbour relative to the mean such
+/// distance. Too few bodies for that many neighbours all get `softening`.
+fn adaptive_softenings(tree: &QuadTree, bodies: &[Body], softening: Scalar) -> Vec<Scalar> {
+    // The nearest "neighbour" is the body itself
+    let spacing = |body: &Body| tree.kth_nearest_distance(body.position, ADAPTIVE_SOFTENING_NEIGHBORS + 1);
+
+    #[cfg(feature = "parallel")]
+    let spacings: Vec<Scalar> = bodies.par_iter().map(spacing).collect();
+
+    #[cfg(not(feature = "parallel"))]
+    let spacings: Vec<Scalar> = bodies.iter().map(spacing).collect();
+
+    let mean = spacings.iter().sum::<Scalar>() / spacings.len() as Scalar;
+    if !(mean.is_finite() && mean > 0.0) {
+        return vec![softening; bodies.len()];
+    }
+    spacings.iter().map(|&d| softening * (d / mean) * (d / mean)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_simulation_creation() {
+        let bodies = vec![
+            Body::new(1.0, 0.0, 0.0, 0.0, 0.0),
+            Body::new(1.0, 1.0, 0.0, 0.0, 0.0),
+        ];
+        let sim = Simulation::new(bodies, 0.1, 1.0, 0.001, 0.5);
+        assert_eq!(sim.bodies.len(), 2);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_from_soa() {
+        let masses = [1.0, 2.0, 3.0];
+        let positions = [[0.0, 0.0], [1.0, 0.0], [0.0, -2.0]];
+        let velocities = [[0.0, 0.5], [-0.5, 0.0], [0.25, 0.25]];
+        let sim = Simulation::from_soa(&masses, &positions, &velocities, 0.01, 1.0, 0.001, 0.5).unwrap();
+
+        assert_eq!(sim.bodies().len(), 3);
+        for (i, body) in sim.bodies().iter().enumerate() {
+            assert_eq!(body.mass, masses[i]);
+            assert_eq!(body.position, positions[i]);
+            assert_eq!(body.velocity, velocities[i]);
+        }
+        assert_eq!(sim.timestep(), 0.01);
+        assert_eq!(sim.softening(), 0.001);
+
+        let error = Simulation::from_soa(&masses, &positions[..2], &velocities, 0.01, 1.0, 0.001, 0.5).err().unwrap();
+        assert_eq!(error.to_string(), "Invalid body data: 3 masses, 2 positions and 3 velocities");
+    }
+
+    #[test]
+    fn test_potential_grid_matches_direct_sum() {
+        use rand::{Rng, SeedableRng};
+        let mut rng = rand::rngs::StdRng::seed_from_u64(12);
+        let bodies: Vec<Body> = (0..500)
+            .map(|_| Body::new(rng.gen_range(0.5..2.0), rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), 0.0, 0.0))
+            .collect();
+        let g = 2.0;
+        let bounds = Bounds::new([-2.0, -1.0], [2.0, 3.0]);
+        let resolution = 8;
+
+        let direct = |x: Scalar, y: Scalar| -> Scalar {
+            bodies
+                .iter()
+                .map(|b| -g * b.mass / ((b.position[0] - x).powi(2) + (b.position[1] - y).powi(2)).sqrt())
+                .sum()
+        };
+
+        let mut sim = Simulation::new(bodies.clone(), 0.01, g, 0.0, 0.0);
+        let exact = sim.potential_grid(resolution, bounds.clone());
+        sim.set_tree_ratio(0.5);
+        let approx = sim.potential_grid(resolution, bounds);
+        assert_eq!(exact.len(), resolution * resolution);
+
+        // Cell centres 0.5 apart, x fastest
+        for (index, x, y) in [(0, -1.75, -0.75), (9, -1.25, -0.25), (27, -0.25, 0.75), (63, 1.75, 2.75)] {
+            let expected = direct(x, y);
+            assert!(((exact[index] - expected) / expected).abs() < 1e3 * Scalar::EPSILON);
+            assert!(((approx[index] - expected) / expected).abs() < 1e-2);
+        }
+    }
+
+    #[test]
+    fn test_display_summary() {
+        let bodies = vec![
+            Body::new(1.5, 0.0, 0.0, 0.0, 0.0),
+            Body::new(2.5, 1.0, 0.0, 0.0, 1.0),
+            Body::new(4.0, 0.0, 1.0, 0.0, 0.0),
+        ];
+        let sim = Simulation::new(bodies, 0.1, 1.0, 0.001, 0.5);
+        assert_eq!(sim.center_of_mass(), [2.5 / 8.0, 0.5]);
+
+        let summary = sim.to_string();
+        assert!(summary.contains("3 bodies"), "{}", summary);
+        assert!(summary.contains("total mass 8e0"), "{}", summary);
+        assert!(!summary.contains("potential"));
+        assert!(format!("{:#}", sim).contains("potential energy"));
+        assert!(format!("{:#}", sim).contains("bound fraction 1"));
+    }
+
+    #[test]
+    fn test_lagrangian_radii() {
+        use rand::{Rng, SeedableRng};
+
+        // Uniform disk of radius 2 about (3, -1): the mass within r grows
+        // as r², so a fraction f lies within 2 √f
+        let mut rng = rand::rngs::StdRng::seed_from_u64(12);
+        let bodies: Vec<Body> = (0..20_000)
+            .map(|_| {
+                let r = 2.0 * rng.gen::<Scalar>().sqrt();
+                let theta = 2.0 * core::f64::consts::PI as Scalar * rng.gen::<Scalar>();
+                Body::new(1.0, 3.0 + r * theta.cos(), -1.0 + r * theta.sin(), 0.0, 0.0)
+            })
+            .collect();
+        let sim = Simulation::new(bodies.clone(), 0.01, 1.0, 0.01, 0.5);
+
+        let fractions = [0.1, 0.5, 0.9];
+        for (radius, fraction) in sim.lagrangian_radii(&fractions).into_iter().zip(fractions) {
+            let expected = 2.0 * Scalar::sqrt(fraction);
+            assert!((radius - expected).abs() < 0.02 * expected, "{} of the mass within {}", fraction, radius);
+        }
+        assert_eq!(sim.lagrangian_radii(&[]), Vec::<Scalar>::new());
+
+        // Radii weigh bodies by mass: a central body holding half of it
+        let mut weighted = bodies;
+        weighted.push(Body::new(20_000.0, 3.0, -1.0, 0.0, 0.0));
+        let sim = Simulation::new(weighted, 0.01, 1.0, 0.01, 0.5);
+        assert!(sim.lagrangian_radii(&[0.5])[0] < 0.01);
+
+        assert_eq!(Simulation::new(Vec::new(), 0.01, 1.0, 0.01, 0.5).lagrangian_radii(&[0.5]), [0.0]);
+    }
+
+    #[test]
+    fn test_bound_fraction() {
+        // A cold grid of bodies is bound whatever its bulk motion
+        let grid: Vec<Body> = (0..25)
+            .map(|i| Body::new(1.0, (i % 5) as Scalar * 0.1, (i / 5) as Scalar * 0.1, 100.0, -50.0))
+            .collect();
+        let sim = Simulation::new(grid.clone(), 0.01, 1.0, 0.001, 0.5);
+        assert_eq!(sim.bound_fraction(), 1.0);
+
+        // One light body flung out at far above the escape speed, without
+        // carrying the centre of mass with it
+        let mut escaping = grid.clone();
+        escaping[0] = Body::new(1e-6, 0.0, 0.0, 1e4, 0.0);
+        let sim = Simulation::new(escaping, 0.01, 1.0, 0.001, 0.5);
+        assert_eq!(sim.bound_fraction(), 24.0 / 25.0);
+
+        // Exploding outwards from the centre
+        let exploding = grid
+            .into_iter()
+            .map(|mut body| {
+                let [x, y] = body.position;
+                body.velocity = [1e3 * (x - 0.2), 1e3 * (y - 0.2)];
+                body
+            })
+            .collect();
+        let mut sim = Simulation::new(exploding, 0.01, 1.0, 0.001, 0.5);
+        assert!(sim.bound_fraction() <= 1.0 / 25.0, "{}", sim.bound_fraction());
+        sim.set_self_gravity(false);
+        assert_eq!(sim.bound_fraction(), 0.0);
+        assert_eq!(Simulation::new(Vec::new(), 0.01, 1.0, 0.001, 0.5).bound_fraction(), 0.0);
+    }
+
+    #[test]
+    fn test_bounds_growth() {
+        // Create two bodies moving outward
+        let bodies = vec![
+            Body::new(1.0, -1.0, -1.0, -1.0, -1.0),  // Moving left and down
+            Body::new(1.0, 1.0, 1.0, 1.0, 1.0),      // Moving right and up
+        ];
+        let mut sim = Simulation::new(bodies, 0.1, 0.0, 0.001, 0.5);  // Set g=0 to prevent attraction
+
+        // Get initial bounds
+        let initial_bounds = sim.get_tree().get_bounds().clone();
+
+        // Step simulation several times
+        for _ in 0..10 {
+            sim.step();
+        }
+
+        // Get new bounds
+        let binding = sim.get_tree();
+        let new_bounds = binding.get_bounds();
+
+        // Verify bounds have grown
+        assert!(new_bounds.min[0] < initial_bounds.min[0]);
+        assert!(new_bounds.min[1] < initial_bounds.min[1]);
+        assert!(new_bounds.max[0] > initial_bounds.max[0]);
+        assert!(new_bounds.max[1] > initial_bounds.max[1]);
+    }
+
+    #[test]
+    fn test_simulation_step() {
+        // Create two bodies that should attract each other
+        let bodies = vec![
+            Body::new(1.0, -0.5, 0.0, 0.0, 0.0),
+            Body::new(1.0, 0.5, 0.0, 0.0, 0.0),
+        ];
+
+        let mut sim = Simulation::new(bodies, 0.1, 1.0, 0.001, 0.5);
+
+        // Store initial positions
+        let initial_x1 = sim.bodies[0].position[0];
+        let initial_x2 = sim.bodies[1].position[0];
+
+        // Run one step
+        sim.step();
+
+        // Bodies should move towards each other
+        assert!(sim.bodies[0].position[0] > initial_x1);
+        assert!(sim.bodies[1].position[0] < initial_x2);
+    }
+
+    #[test]
+    fn test_add_body_between_steps() {
+        let mut sim = Simulation::new(vec![Body::new(1.0, 0.0, 0.0, 0.0, 0.0)], 0.1, 1.0, 0.001, 0.5);
+        sim.set_incremental_tree(true);
+        sim.set_energy_guard(0.01);
+        sim.step();
+        assert_eq!(sim.bodies()[0].position, [0.0, 0.0]);
+
+        // The new body pulls the first one towards it without tripping the guard
+        sim.add_body(Body::new(1.0, 1.0, 0.0, 0.0, 0.1));
+        sim.step();
+        assert_eq!(sim.bodies().len(), 2);
+        assert!(sim.bodies()[0].position[0] > 0.0);
+        assert!(sim.bodies()[1].position[1] > 0.0);
+        assert_eq!(sim.current_timestep(), 0.1);
+    }
+
+    #[test]
+    fn test_adaptive_softening_follows_density() {
+        // A tight clump in the middle of a sparse field
+        let mut bodies: Vec<Body> = seeded_bodies(100, 5)
+            .into_iter()
+            .map(|mut body| {
+                body.position = [0.01 * body.position[0], 0.01 * body.position[1]];
+                body
+            })
+            .collect();
+        bodies.extend(seeded_bodies(100, 6).into_iter().map(|mut body| {
+            body.position = [10.0 * body.position[0], 10.0 * body.position[1]];
+            body
+        }));
+        let mut sim = Simulation::new(bodies, 1e-4, 1.0, 0.01, 0.5);
+        sim.step();
+        assert!(sim.body_softenings().is_empty());
+
+        sim.set_adaptive_softening(true);
+        sim.step();
+        let softenings = sim.body_softenings();
+        assert_eq!(softenings.len(), 200);
+        let clump = softenings[..100].iter().cloned().fold(0.0, Scalar::max);
+        let field = softenings[100..].iter().cloned().fold(Scalar::INFINITY, Scalar::min);
+        assert!(clump < 1e-3 * field, "clump {} vs field {}", clump, field);
+        assert!(clump < 0.01);
+
+        // The clump's own forces feel its smaller softening
+        let mut fixed = Simulation::new(sim.bodies().to_vec(), 1e-4, 1.0, 0.01, 0.5);
+        let before = sim.bodies()[0].velocity;
+        let fixed_before = fixed.bodies()[0].velocity;
+        sim.step();
+        fixed.step();
+        let kick = |a: [Scalar; 2], b: [Scalar; 2]| (b[0] - a[0]).hypot(b[1] - a[1]);
+        assert!(kick(before, sim.bodies()[0].velocity) > kick(fixed_before, fixed.bodies()[0].velocity));
+
+        sim.set_adaptive_softening(false);
+        assert!(sim.body_softenings().is_empty());
+    }
+
+    #[test]
+    fn test_adaptive_softening_found_once_per_block_step() {
+        let mut sim = Simulation::new(core_and_halo(40), 0.05, 1.0, 1e-4, 0.5);
+        sim.set_adaptive_softening(true);
+        sim.set_integrator(Integrator::BlockLeapfrog { levels: 6, accuracy: 1e-4 });
+
+        // The step's first force pass is at the starting positions; the
+        // substeps after it must not replace the softenings
+        let expected = adaptive_softenings(&sim.get_tree(), sim.bodies(), 1e-4);
+        sim.step();
+        assert_eq!(sim.body_softenings(), expected.as_slice());
+    }
+
+    #[test]
+    fn test_body_accessors() {
+        let bodies = vec![Body::new(1.0, 0.0, 0.0, 0.0, 0.0), Body::new(1.0, 1.0, 0.0, 0.0, 0.0)];
+        let mut sim = Simulation::new(bodies, 0.1, 1.0, 0.001, 0.5);
+        // The block integrator reuses the last step's accelerations
+        sim.set_incremental_tree(true);
+        sim.set_integrator(Integrator::BlockLeapfrog { levels: 1, accuracy: 1.0 });
+        sim.step();
+
+        assert_eq!(sim.body(1).map(|b| b.position), Some(sim.bodies()[1].position));
+        assert!(sim.body(2).is_none());
+        assert!(sim.body_mut(2).is_none());
+
+        // Moving a body far away is seen by the next step's forces
+        sim.body_mut(1).unwrap().position = [1000.0, 0.0];
+        let before = sim.bodies()[0].velocity;
+        sim.step();
+        let after = sim.bodies()[0].velocity;
+        assert!((after[0] - before[0]).abs() < 1e-5, "{:?} -> {:?}", before, after);
+    }
+
+    #[test]
+    fn test_kick_region() {
+        let bodies = vec![
+            Body::new(1.0, 0.0, 0.0, 0.0, 0.0),
+            Body::new(1.0, 0.5, 0.5, 0.1, 0.0),
+            Body::new(1.0, 1.0, 0.0, 0.0, 0.0), // On the edge
+            Body::new(1.0, 1.0, 1.0, 0.0, 0.0),
+            Body::new(1.0, -3.0, 0.0, 0.0, 0.0),
+        ];
+        let mut sim = Simulation::new(bodies, 0.1, 1.0, 0.001, 0.5);
+
+        assert_eq!(sim.kick_region([0.0, 0.0], 1.0, [0.0, 2.0]), 3);
+        let velocities: Vec<[Scalar; 2]> = sim.bodies().iter().map(|b| b.velocity).collect();
+        assert_eq!(velocities, [[0.0, 2.0], [0.1, 2.0], [0.0, 2.0], [0.0, 0.0], [0.0, 0.0]]);
+
+        // Positions are untouched and an empty region kicks nothing
+        assert_eq!(sim.bodies()[1].position, [0.5, 0.5]);
+        assert_eq!(sim.kick_region([10.0, 10.0], 1.0, [1.0, 1.0]), 0);
+
+        // Velocity-dependent force laws need the block integrator's cached
+        // accelerations recomputed after a kick
+        sim.set_force_law(ForceLaw::PostNewtonian { c: 100.0 });
+        sim.set_integrator(Integrator::BlockLeapfrog { levels: 1, accuracy: 1.0 });
+        sim.step();
+        assert!(sim.accelerations_current);
+        sim.kick_region([0.0, 0.0], 1.0, [0.0, 1.0]);
+        assert!(!sim.accelerations_current);
+    }
+
+    #[test]
+    fn test_reflecting_boundary() {
+        // A free body heading for the right wall of the unit box
+        let bodies = vec![Body::new(1.0, 0.9, 0.0, 1.0, 0.5)];
+        let mut sim = Simulation::new(bodies, 0.25, 1.0, 0.0, 0.5);
+        sim.set_self_gravity(false);
+        sim.set_boundary(BoundaryCondition::Reflecting {
+            bounds: Bounds::new([-1.0, -1.0], [1.0, 1.0]),
+        });
+
+        sim.step();
+        let body = &sim.bodies()[0];
+        assert_eq!(body.position[0], 1.0);
+        assert_eq!(body.velocity, [-1.0, 0.5]);
+
+        for _ in 0..100 {
+            sim.step();
+            let [x, y] = sim.bodies()[0].position;
+            assert!((-1.0..=1.0).contains(&x) && (-1.0..=1.0).contains(&y));
+        }
+    }
+
+    #[test]
+    fn test_without_self_gravity_bodies_move_in_straight_lines() {
+        let integrators = [
+            Integrator::SymplecticEuler,
+            Integrator::Leapfrog,
+            Integrator::Yoshida4,
+            Integrator::BlockLeapfrog { levels: 4, accuracy: 0.01 },
+        ];
+        for integrator in integrators {
+            // Close enough together that gravity would bend their paths sharply
+            let bodies = seeded_bodies(50, 3);
+            let mut sim = Simulation::new(bodies.clone(), 0.01, 1.0, 1e-4, 0.5);
+            sim.set_self_gravity(false);
+            sim.set_integrator(integrator);
+            for _ in 0..1000 {
+                sim.step();
+            }
+
+            let t = sim.time();
+            for (body, start) in sim.bodies().iter().zip(&bodies) {
+                assert_eq!(body.velocity, start.velocity, "{:?}", integrator);
+                for axis in 0..2 {
+                    let expected = start.position[axis] + start.velocity[axis] * t;
+                    assert!((body.position[axis] - expected).abs() < 1e-3 * (1.0 + expected.abs()), "{:?}", integrator);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_uniform_acceleration() {
+        // Two bodies dropped from rest onto the floor of the unit box
+        let bodies = vec![Body::new(1.0, -0.5, 0.9, 0.0, 0.0), Body::new(1.0, 0.5, 0.9, 0.0, 0.0)];
+        let mut sim = Simulation::new(bodies, 0.01, 1.0, 0.0, 0.5);
+        sim.set_self_gravity(false);
+        sim.set_uniform_acceleration([0.0, -9.8]);
+        sim.set_boundary(BoundaryCondition::Reflecting {
+            bounds: Bounds::new([-1.0, -1.0], [1.0, 1.0]),
+        });
+
+        // The fall takes about 0.61, so the first 50 steps are in free fall
+        for step in 1..=50 {
+            sim.step();
+            for body in sim.bodies() {
+                let expected = -9.8 * 0.01 * step as Scalar;
+                assert!((body.velocity[1] - expected).abs() < 1e-4, "step {}: {:?}", step, body.velocity);
+                assert_eq!(body.velocity[0], 0.0);
+            }
+        }
+
+        // Then they bounce off the floor and stay in the box
+        let mut bounced = false;
+        for _ in 0..200 {
+            sim.step();
+            let body = &sim.bodies()[0];
+            assert!(body.position[1] >= -1.0);
+            bounced |= body.velocity[1] > 0.0;
+        }
+        assert!(bounced);
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_parallel_bounds_match_sequential() {
+        use rand::{Rng, SeedableRng};
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(42);
+        let bodies: Vec<Body> = (0..10_000)
+            .map(|_| Body::new(
+                1.0,
+                rng.gen_range(-100.0..100.0),
+                rng.gen_range(-100.0..100.0),
+                0.0,
+                0.0,
+            ))
+            .collect();
+
+        let (seq_min, seq_max) = sequential_extents(&bodies);
+        let (par_min, par_max) = parallel_extents(&bodies);
+
+        assert_eq!(seq_min, par_min);
+        assert_eq!(seq_max, par_max);
+    }
+
+    /// Bodies in a seeded random disk with small random velocities
+    fn seeded_bodies(n: usize, seed: u64) -> Vec<Body> {
+        use rand::{Rng, SeedableRng};
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+        (0..n)
+            .map(|_| Body::new(
+                rng.gen_range(0.5..2.0),
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-0.1..0.1),
+                rng.gen_range(-0.1..0.1),
+            ))
+            .collect()
+    }
+
+    #[test]
+    fn test_step_matches_sequential_reference() {
+        // Run in every build, this checks the parallel and sequential builds
+        // against the same plain loop, and so against each other
+        let dt = 0.001;
+        let mut sim = Simulation::new(seeded_bodies(500, 7), dt, 1.0, 0.01, 0.5);
+        let params = ForceParams::new(1.0, 0.01, 0.5);
+
+        for _ in 0..5 {
+            let tree = sim.get_tree();
+            let mut expected = sim.bodies().to_vec();
+            for body in &mut expected {
+                let force = tree.calculate_force_with(body, &params);
+                body.acceleration = [force[0] / body.mass, force[1] / body.mass];
+                body.update_velocity(dt);
+                body.update_position(dt);
+            }
+
+            sim.step();
+            for (body, expected) in sim.bodies().iter().zip(&expected) {
+                assert_eq!(body.position, expected.position);
+                assert_eq!(body.velocity, expected.velocity);
+            }
+        }
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_results_independent_of_thread_count() {
+        let run = |threads: usize| {
+            let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+            pool.install(|| {
+                let mut sim = Simulation::new(seeded_bodies(1000, 11), 0.001, 1.0, 0.01, 0.5);
+                sim.set_integrator(Integrator::Leapfrog);
+                sim.set_energy_guard(0.01);
+                for _ in 0..10 {
+                    sim.step();
+                }
+                let state: Vec<_> = sim.bodies().iter().map(|b| (b.position, b.velocity)).collect();
+                (state, sim.potential_energy(), sim.current_timestep())
+            })
+        };
+
+        let single = run(1);
+        for threads in [2, 4, 7] {
+            assert!(run(threads) == single, "{} threads differ from one", threads);
+        }
+    }
+
+    #[test]
+    fn test_snapshot_restore() {
+        let mut sim = Simulation::new(seeded_bodies(200, 7), 0.01, 1.0, 0.01, 0.5);
+        sim.set_integrator(Integrator::Leapfrog);
+        for _ in 0..5 {
+            sim.step();
+        }
+        let snapshot = sim.snapshot();
+        for _ in 0..5 {
+            sim.step();
+        }
+        let later = sim.snapshot();
+        assert_ne!(later, snapshot);
+
+        sim.set_timestep(0.5);
+        sim.restore(&snapshot);
+        assert_eq!(sim.snapshot(), snapshot);
+        assert_eq!(sim.step_count(), 5);
+        assert_eq!(sim.timestep(), 0.01);
+
+        // Stepping again from the restored state retraces the same path
+        for _ in 0..5 {
+            sim.step();
+        }
+        assert_eq!(sim.snapshot(), later);
+        assert_eq!(sim.bodies(), later.bodies());
+    }
+
+    #[test]
+    fn test_mean_nodes_opened_grows_as_threshold_shrinks() {
+        let mut sim = Simulation::new(seeded_bodies(1000, 11), 0.01, 1.0, 0.01, 1.0);
+        let mut last = 0.0;
+        for ratio in [1.0, 0.5, 0.25] {
+            sim.set_tree_ratio(ratio);
+            let opened = sim.mean_nodes_opened();
+            assert!(opened > last, "ratio {}: {} nodes opened vs {}", ratio, opened, last);
+            last = opened;
+        }
+
+        // With a zero ratio every internal node is opened
+        sim.set_tree_ratio(0.0);
+        assert!(sim.mean_nodes_opened() > 1.5 * last);
+        assert_eq!(Simulation::new(Vec::new(), 0.01, 1.0, 0.01, 0.5).mean_nodes_opened(), 0.0);
+    }
+
+    /// Depth of the deepest node below `tree`
+    fn tree_depth(tree: &QuadTree) -> usize {
+        tree.get_children().iter().flatten().map(|child| 1 + tree_depth(child)).max().unwrap_or(0)
+    }
+
+    #[test]
+    fn test_display_tree_is_shallow() {
+        let sim = Simulation::new(seeded_bodies(2000, 5), 0.01, 1.0, 0.01, 0.5);
+        let full = sim.get_tree();
+        for max_depth in [0, 1, 4] {
+            let shallow = sim.display_tree(max_depth);
+            assert_eq!(shallow.get_bounds(), full.get_bounds());
+            assert_eq!(shallow.body_count(), full.body_count());
+            assert!((shallow.total_mass() - full.total_mass()).abs() <= 1e-5 * full.total_mass());
+            for axis in 0..2 {
+                let com = shallow.center_of_mass()[axis] - full.center_of_mass()[axis];
+                assert!(com.abs() < 1e-4, "max depth {}: centre of mass off by {}", max_depth, com);
+            }
+            assert_eq!(tree_depth(&shallow), max_depth);
+        }
+        assert!(tree_depth(&full) > 4);
+    }
+
+    #[test]
+    fn test_acceleration_at_matches_direct_sum() {
+        let bodies = seeded_bodies(500, 13);
+        let point = [0.123, -0.456];
+        let softening = 0.01;
+        let mut direct = [0.0, 0.0];
+        for body in &bodies {
+            let dx = body.position[0] - point[0];
+            let dy = body.position[1] - point[1];
+            let r_sq = dx * dx + dy * dy;
+            let a = body.mass / (r_sq + softening) / r_sq.sqrt();
+            direct = [direct[0] + a * dx, direct[1] + a * dy];
+        }
+        let magnitude = direct[0].hypot(direct[1]);
+
+        // Opening every node gives the direct sum
+        let mut sim = Simulation::new(bodies, 0.01, 1.0, softening, 0.0);
+        let exact = sim.acceleration_at(point);
+        assert!((exact[0] - direct[0]).hypot(exact[1] - direct[1]) < 1e-4 * magnitude, "{:?} vs {:?}", exact, direct);
+
+        sim.set_tree_ratio(0.5);
+        let approximate = sim.accelerations_at(&[point, [0.0, 0.0]]);
+        assert_eq!(approximate.len(), 2);
+        assert!((approximate[0][0] - direct[0]).hypot(approximate[0][1] - direct[1]) < 5e-2 * magnitude, "{:?} vs {:?}", approximate[0], direct);
+
+        // External fields are felt by the probe too, even without self-gravity
+        sim.set_self_gravity(false);
+        sim.set_external_field(crate::external::uniform([0.0, -2.0]));
+        assert_eq!(sim.acceleration_at(point), [0.0, -2.0]);
+    }
+
+    #[test]
+    fn test_max_tree_depth_caps_clustered_bodies() {
+        // A tight clump far from the rest would need a very deep tree
+        let mut bodies = seeded_bodies(100, 17);
+        bodies.extend(seeded_bodies(200, 19).into_iter().map(|mut body| {
+            body.position = [0.5 + 1e-4 * body.position[0], 0.5 + 1e-4 * body.position[1]];
+            body
+        }));
+        let mut sim = Simulation::new(bodies, 1e-6, 1.0, 0.01, 0.5);
+        assert!(tree_depth(&sim.get_tree()) > 12);
+        assert_eq!(sim.capped_tree_leaves(), 0);
+
+        sim.set_max_tree_depth(Some(8));
+        let tree = sim.get_tree();
+        assert!(tree_depth(&tree) <= 8);
+        assert_eq!(tree.body_count(), 300);
+        assert!(sim.capped_tree_leaves() > 0);
+
+        sim.step();
+        assert!(sim.bodies().iter().all(|body| body.acceleration.iter().all(|a| a.is_finite())));
+        assert_eq!(sim.snapshot().max_tree_depth, Some(8));
+    }
+
+    #[test]
+    fn test_speed_histogram() {
+        let bodies = [0.0, 0.5, 1.5, 1.9, 2.5, 4.0]
+            .iter()
+            .map(|&speed| Body::new(1.0, speed, 0.0, 0.0, speed))
+            .collect();
+        let sim = Simulation::new(bodies, 0.01, 1.0, 0.01, 0.5);
+
+        assert_eq!(sim.speed_histogram(4), vec![(0.0, 2), (1.0, 2), (2.0, 1), (3.0, 1)]);
+        assert_eq!(sim.speed_histogram(1), vec![(0.0, 6)]);
+        assert!(sim.speed_histogram(0).is_empty());
+
+        let resting = Simulation::new(vec![Body::new(1.0, 0.0, 0.0, 0.0, 0.0); 3], 0.01, 1.0, 0.01, 0.5);
+        assert_eq!(resting.speed_histogram(2), vec![(0.0, 3), (0.0, 0)]);
+    }
+
+    #[test]
+    fn test_bounds_coincident_bodies() {
+        let bodies = vec![
+            Body::new(1.0, 2.0, 3.0, 0.0, 0.0),
+            Body::new(1.0, 2.0, 3.0, 0.0, 0.0),
+        ];
+        let sim = Simulation::new(bodies, 0.1, 1.0, 0.001, 0.5);
+        let bounds = sim.compute_bounds();
+
+        assert!(bounds.max[0] > bounds.min[0]);
+        assert!(bounds.max[1] > bounds.min[1]);
+        assert!(bounds.contains([2.0, 3.0]));
+    }
+
+    #[test]
+    fn test_bounds_skip_infinite_positions() {
+        let bodies = vec![
+            Body::new(1.0, Scalar::INFINITY, 0.0, 0.0, 0.0),
+            Body::new(2.0, 5.0, 5.0, 0.0, 0.0),
+        ];
+        let sim = Simulation::new(bodies, 0.1, 1.0, 0.001, 0.5);
+        assert!(sim.compute_bounds().contains([5.0, 5.0]));
+
+        // The finite body is in the tree; only the infinite one is left out
+        let tree = sim.get_tree();
+        assert_eq!(tree.total_mass(), 2.0);
+        assert_eq!(tree.center_of_mass(), [5.0, 5.0]);
+    }
+
+    #[test]
+    fn test_parameter_setters() {
+        let bodies = vec![
+            Body::new(1.0, -0.5, 0.0, 0.0, 0.0),
+            Body::new(1.0, 0.5, 0.0, 0.0, 0.0),
+        ];
+        let mut sim = Simulation::new(bodies, 0.1, 1.0, 0.001, 0.5);
+
+        sim.set_timestep(0.01);
+        sim.set_g(2.0);
+        sim.set_softening(0.1);
+        sim.set_tree_ratio(1.5);
+
+        assert_eq!(sim.timestep(), 0.01);
+        assert_eq!(sim.g(), 2.0);
+        assert_eq!(sim.softening(), 0.1);
+        assert_eq!(sim.tree_ratio(), 1.5);
+
+        // With G switched off mid-run the bodies no longer attract
+        sim.set_g(0.0);
+        sim.step();
+        assert_eq!(sim.bodies()[0].position[0], -0.5);
+        assert_eq!(sim.bodies()[1].position[0], 0.5);
+    }
+
+    #[test]
+    fn test_time_accumulates() {
+        let bodies = vec![
+            Body::new(1.0, -0.5, 0.0, 0.0, 0.0),
+            Body::new(1.0, 0.5, 0.0, 0.0, 0.0),
+        ];
+        let mut sim = Simulation::new(bodies, 0.25, 1.0, 0.001, 0.5);
+
+        for _ in 0..8 {
+            sim.step();
+        }
+        assert_eq!(sim.step_count(), 8);
+        assert_eq!(sim.time(), 8.0 * 0.25);
+
+        // Time keeps following the timestep in use at each step
+        sim.set_timestep(0.5);
+        sim.step();
+        assert_eq!(sim.step_count(), 9);
+        assert_eq!(sim.time(), 2.5);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_snapshot_channel() {
+        let bodies: Vec<Body> = (0..10)
+            .map(|i| Body::new(1.0, i as Scalar, 0.0, 0.0, 0.0))
+            .collect();
+        let mut sim = Simulation::new(bodies, 0.1, 0.0, 0.001, 0.5);
+
+        let (tx, rx) = std::sync::mpsc::sync_channel(1);
+        sim.attach_snapshot_sender(tx, 3);
+
+        // The second snapshot is dropped because the channel is full
+        sim.step();
+        sim.step();
+
+        let snapshot = rx.try_recv().unwrap();
+        assert_eq!(snapshot, vec![0.0, 0.0, 3.0, 0.0, 6.0, 0.0, 9.0, 0.0]);
+        assert!(rx.try_recv().is_err());
+
+        // A disconnected receiver detaches the sender instead of failing
+        drop(rx);
+        sim.step();
+        assert!(sim.snapshot_sender.is_none());
+    }
+
+    /// Direct O(n²) sum of the softened pair potential
+    fn direct_potential_energy(sim: &Simulation) -> Scalar {
+        let bodies = sim.bodies();
+        let mut total = 0.0;
+        for i in 0..bodies.len() {
+            for j in (i + 1)..bodies.len() {
+                let dx = bodies[j].position[0] - bodies[i].position[0];
+                let dy = bodies[j].position[1] - bodies[i].position[1];
+                let distance = (dx * dx + dy * dy).sqrt();
+                let g_mm = sim.g() * bodies[i].mass * bodies[j].mass;
+                total += crate::force::softened_potential(g_mm, distance, sim.softening());
+            }
+        }
+        total
+    }
+
+    #[test]
+    fn test_tree_potential_converges_to_direct() {
+        use rand::{Rng, SeedableRng};
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+        let bodies: Vec<Body> = (0..300)
+            .map(|_| Body::new(
+                rng.gen_range(0.5..2.0),
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+                0.0,
+                0.0,
+            ))
+            .collect();
+        let mut sim = Simulation::new(bodies, 0.1, 1.0, 0.01, 0.0);
+        let direct = direct_potential_energy(&sim);
+
+        let mut last_error = Scalar::INFINITY;
+        for threshold in [2.0, 1.0, 0.5, 0.25] {
+            sim.set_tree_ratio(threshold);
+            let error = ((sim.potential_energy() - direct) / direct).abs();
+            assert!(error <= last_error, "error grew to {} at threshold {}", error, threshold);
+            last_error = error;
+        }
+
+        // With a zero threshold every node is opened, giving the direct sum
+        sim.set_tree_ratio(0.0);
+        let error = ((sim.potential_energy() - direct) / direct).abs();
+        assert!(error < 1e4 * Scalar::EPSILON, "relative error {}", error);
+    }
+
+    #[test]
+    fn test_potential_matches_force() {
+        // The potential gradient should equal the force for the softened law
+        let softening = 0.05;
+        let h = Scalar::EPSILON.cbrt();
+        let pair = |x: Scalar| Simulation::new(
+            vec![Body::new(1.0, 0.0, 0.0, 0.0, 0.0), Body::new(2.0, x, 0.0, 0.0, 0.0)],
+            0.1, 1.0, softening, 0.0,
+        );
+
+        let numeric = -(pair(0.5 + h).potential_energy() - pair(0.5 - h).potential_energy()) / (2.0 * h);
+        let analytic = -2.0 / (0.25 + softening); // attractive, towards the origin
+        assert!((numeric - analytic).abs() < 1e2 * Scalar::EPSILON.sqrt(), "{} vs {}", numeric, analytic);
+    }
+
+    #[test]
+    fn test_energy_guard_reduces_timestep() {
+        // A nearly head-on close pair with a coarse timestep gains energy
+        // violently at closest approach
+        let bodies = vec![
+            Body::new(1.0, -1.0, 0.0, 0.5, 0.0),
+            Body::new(1.0, 1.0, 0.001, -0.5, 0.0),
+        ];
+        let mut sim = Simulation::new(bodies.clone(), 0.05, 1.0, 1e-8, 0.5);
+        sim.set_energy_guard(0.01);
+
+        let mut min_dt = Scalar::INFINITY;
+        for _ in 0..100 {
+            sim.step();
+            min_dt = min_dt.min(sim.current_timestep());
+        }
+        assert!(min_dt < 0.05, "guard never braked");
+        assert!(sim.time() < 100.0 * 0.05);
+
+        // Without the guard the timestep never changes
+        let mut unguarded = Simulation::new(bodies, 0.05, 1.0, 1e-8, 0.5);
+        for _ in 0..100 {
+            unguarded.step();
+            assert_eq!(unguarded.current_timestep(), 0.05);
+        }
+    }
+
+    #[test]
+    fn test_incremental_tree_matches_rebuild() {
+        use rand::{Rng, SeedableRng};
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(8);
+        let bodies: Vec<Body> = (0..300)
+            .map(|_| Body::new(
+                1.0,
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-0.1..0.1),
+                rng.gen_range(-0.1..0.1),
+            ))
+            .collect();
+
+        // An exact direct sum, so only the summation order can differ
+        let mut full = Simulation::new(bodies.clone(), 1e-4, 1.0, 0.01, 0.0);
+        let mut incremental = Simulation::new(bodies, 1e-4, 1.0, 0.01, 0.0);
+        incremental.set_incremental_tree(true);
+
+        for _ in 0..50 {
+            full.step();
+            incremental.step();
+        }
+        assert!(incremental.cached_tree.is_some());
+
+        for (a, b) in full.bodies().iter().zip(incremental.bodies()) {
+            for k in 0..2 {
+                assert!((a.position[k] - b.position[k]).abs() < Scalar::EPSILON.sqrt());
+                assert!((a.acceleration[k] - b.acceleration[k]).abs() < Scalar::EPSILON.sqrt() * a.acceleration[k].abs().max(1.0));
+            }
+        }
+    }
+
+    #[test]
+    fn test_tree_rebuild_interval_force_error() {
+        use rand::{Rng, SeedableRng};
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(12);
+        let bodies: Vec<Body> = (0..500)
+            .map(|_| Body::new(
+                1.0,
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-0.5..0.5),
+                rng.gen_range(-0.5..0.5),
+            ))
+            .collect();
+
+        let mut sim = Simulation::new(bodies, 1e-3, 1.0, 0.01, 1.0);
+        sim.set_tree_rebuild_interval(4);
+        for _ in 0..3 {
+            sim.step();
+        }
+        assert_eq!(sim.tree_age, 3);
+
+        // The fourth evaluation, on a tree built three steps ago, against a
+        // freshly built tree with the same opening threshold
+        let mut frozen = sim.cached_tree.take().unwrap();
+        assert!(frozen.refit(sim.bodies()));
+        assert!((frozen.total_mass() - sim.total_mass()).abs() < 1e3 * Scalar::EPSILON);
+        for (a, b) in frozen.center_of_mass().iter().zip(sim.center_of_mass()) {
+            assert!((a - b).abs() < 1e3 * Scalar::EPSILON);
+        }
+
+        // The frozen structure changes the forces by much less than the
+        // error Barnes-Hut makes anyway against the exact direct sum
+        let fresh = sim.build_tree();
+        let params = sim.force_params();
+        let exact = ForceParams { threshold: 0.0, ..params };
+        let rms_difference = |a: &QuadTree, a_params: &ForceParams, b: &QuadTree, b_params: &ForceParams| {
+            let (mut difference_sq, mut force_sq) = (0.0, 0.0);
+            for body in sim.bodies() {
+                let fa = a.calculate_force_with(body, a_params);
+                let fb = b.calculate_force_with(body, b_params);
+                difference_sq += (fa[0] - fb[0]).powi(2) + (fa[1] - fb[1]).powi(2);
+                force_sq += fb[0].powi(2) + fb[1].powi(2);
+            }
+            (difference_sq / force_sq).sqrt()
+        };
+        let frozen_error = rms_difference(&frozen, &params, &fresh, &params);
+        let tree_error = rms_difference(&fresh, &params, &fresh, &exact);
+        assert!(frozen_error < 0.5 * tree_error, "frozen {} vs tree {}", frozen_error, tree_error);
+
+        // The next evaluation rebuilds
+        sim.cached_tree = Some(frozen);
+        sim.step();
+        sim.step();
+        assert_eq!(sim.tree_age, 1);
+    }
+
+    #[test]
+    fn test_momentum_and_angular_momentum() {
+        let bodies = vec![
+            Body::new(2.0, 1.0, 0.0, 0.0, 0.5),
+            Body::new(1.0, -2.0, 0.0, 0.0, -1.0),
+            Body::new(3.0, 0.0, 1.5, 0.2, 0.0),
+        ];
+        // Tree ratio 0 so every force is exact pairwise
+        let mut sim = Simulation::new(bodies, 0.001, 1.0, 0.01, 0.0);
+        sim.set_integrator(crate::Integrator::Leapfrog);
+        assert!((sim.momentum()[0] - 0.6).abs() < 1e-6 && sim.momentum()[1] == 0.0);
+        let angular = 2.0 * 0.5 + 2.0 - 3.0 * 1.5 * 0.2;
+        assert!((sim.angular_momentum() - angular).abs() < 1e-6);
+
+        // Equal and opposite central forces conserve both
+        for _ in 0..1000 {
+            sim.step();
+        }
+        assert!((sim.momentum()[0] - 0.6).abs() < 1e-4 && sim.momentum()[1].abs() < 1e-4);
+        assert!((sim.angular_momentum() - angular).abs() < 1e-4, "{}", sim.angular_momentum());
+    }
+
+    #[test]
+    fn test_drag_removes_kinetic_energy() {
+        // A hot cluster of total mass 1 with G = 1, roughly in virial balance
+        let cluster = || {
+            let bodies = seeded_bodies(100, 3)
+                .into_iter()
+                .map(|body| Body::new(0.01, body.position[0], body.position[1], 10.0 * body.velocity[0], 10.0 * body.velocity[1]))
+                .collect();
+            let mut sim = Simulation::new(bodies, 0.001, 1.0, 0.01, 0.5);
+            sim.set_integrator(Integrator::Leapfrog);
+            sim
+        };
+        let run = |drag: Scalar| {
+            let mut sim = cluster();
+            sim.set_drag(drag);
+            let mut energies = vec![sim.total_energy()];
+            for _ in 0..10 {
+                for _ in 0..100 {
+                    sim.step();
+                }
+                energies.push(sim.total_energy());
+            }
+            (sim.kinetic_energy(), energies)
+        };
+
+        let initial = cluster();
+        assert!(initial.total_energy() < 0.0, "not bound");
+        let (free_kinetic, free_energies) = run(0.0);
+        let (damped_kinetic, damped_energies) = run(2.0);
+
+        // Without drag the energy is conserved; with it, it keeps falling
+        let e0 = free_energies[0];
+        assert!(free_energies.iter().all(|e| ((e - e0) / e0).abs() < 1e-2), "{:?}", free_energies);
+        assert!(damped_energies.windows(2).all(|w| w[1] < w[0]), "{:?}", damped_energies);
+        assert!(damped_kinetic < 0.5 * initial.kinetic_energy());
+        assert!(damped_kinetic < 0.5 * free_kinetic);
+    }
+
+    #[test]
+    fn test_to_nbody_units() {
+        let bodies = vec![
+            Body::new(5.0, 0.0, 0.0, 0.0, -0.02),
+            Body::new(3.0, 40.0, 0.0, 0.0, 0.1),
+            Body::new(2.0, -25.0, 10.0, 0.05, -0.1),
+        ];
+        let mut sim = Simulation::new(bodies, 0.5, 0.5, 0.01, 0.5);
+        assert!(sim.total_energy() < 0.0);
+        let virial = sim.virial_ratio();
+
+        assert!(sim.to_nbody_units());
+        assert_eq!(sim.g(), 1.0);
+        assert!((sim.total_mass() - 1.0).abs() < 1e-6);
+        assert!((sim.total_energy() + 0.25).abs() < 1e-5, "energy {}", sim.total_energy());
+        // A dimensionless quantity is unchanged
+        assert!((sim.virial_ratio() / virial - 1.0).abs() < 1e-4);
+
+        let mut unbound = Simulation::new(
+            vec![Body::new(1.0, -1.0, 0.0, -5.0, 0.0), Body::new(1.0, 1.0, 0.0, 5.0, 0.0)],
+            0.01, 1.0, 0.0, 0.5,
+        );
+        assert!(!unbound.to_nbody_units());
+        assert_eq!(unbound.g(), 1.0);
+        assert_eq!(unbound.bodies()[1].velocity, [5.0, 0.0]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_relaxation_time_scales_as_n_over_log_n() {
+        use rand::SeedableRng;
+        use crate::initial_conditions::plummer_with_rng;
+
+        let relaxation = |n: usize| {
+            let mut rng = rand::rngs::StdRng::seed_from_u64(n as u64);
+            let sim = Simulation::new(plummer_with_rng(&mut rng, n, 1.0, 1.0, 1.0), 0.01, 1.0, 1e-4, 1.0);
+            let (relaxation, crossing) = (sim.relaxation_time(), sim.crossing_time());
+            let expected = n as Scalar / (8.0 * (n as Scalar).ln()) * crossing;
+            assert!((relaxation - expected).abs() < 1e-4 * expected);
+            (relaxation, crossing)
+        };
+
+        // The same cluster sampled with more bodies: about the same crossing
+        // time, and a relaxation time longer by the ratio of N / ln N
+        let (t_small, cross_small) = relaxation(1000);
+        let (t_large, cross_large) = relaxation(4000);
+        assert!((cross_large / cross_small - 1.0).abs() < 0.1, "{} vs {}", cross_large, cross_small);
+        let expected_ratio = (4000.0 / (4000.0 as Scalar).ln()) / (1000.0 / (1000.0 as Scalar).ln());
+        let ratio = t_large / t_small;
+        assert!((ratio / expected_ratio - 1.0).abs() < 0.1, "ratio {} vs {}", ratio, expected_ratio);
+
+        // Virial radius 2a over rms speed √(GM / 4a) for this model
+        let expected_crossing = 4.0;
+        assert!((cross_large / expected_crossing - 1.0).abs() < 0.05, "crossing time {}", cross_large);
+
+        let one = Simulation::new(vec![Body::new(1.0, 0.0, 0.0, 0.0, 0.0)], 0.01, 1.0, 0.0, 0.5);
+        assert_eq!(one.relaxation_time(), Scalar::INFINITY);
+    }
+
+    #[test]
+    fn test_external_point_mass_kepler_orbit() {
+        // Starting at apoapsis r = 1 with v = 0.8 < circular speed
+        let start = Body::new(1.0, 1.0, 0.0, 0.0, 0.8);
+        let semi_major = 1.0 / (2.0 - 0.8 * 0.8);
+        let period = 2.0 * std::f64::consts::PI as Scalar * semi_major * Scalar::sqrt(semi_major);
+        let dt = 1e-4;
+
+        let mut sim = Simulation::new(vec![start.clone()], dt, 1.0, 0.0, 0.5);
+        sim.set_self_gravity(false);
+        sim.set_external_field(crate::external::point_mass(1.0));
+
+        let mut min_r = Scalar::INFINITY;
+        for _ in 0..(period / dt).round() as usize {
+            sim.step();
+            let p = sim.bodies()[0].position;
+            min_r = min_r.min((p[0] * p[0] + p[1] * p[1]).sqrt());
+        }
+
+        assert!((min_r - (2.0 * semi_major - 1.0)).abs() < 1e-3, "periapsis {}", min_r);
+        let end = sim.bodies()[0].position;
+        assert!((end[0] - 1.0).abs() < 1e-2 && end[1].abs() < 1e-2, "ended at {:?}", end);
+        assert_eq!(sim.potential_energy(), 0.0);
+    }
+
+    /// Largest relative energy error of a test particle on an e = 0.5 Kepler
+    /// orbit around a unit point mass, over `periods` orbits of `steps` steps
+    fn kepler_energy_error(integrator: Integrator, steps: usize, periods: usize) -> Scalar {
+        // Released at apoapsis r = 1.5 of an orbit with semi-major axis 1
+        let energy = |body: &Body| {
+            let [x, y] = body.position;
+            0.5 * (body.velocity[0].powi(2) + body.velocity[1].powi(2)) - 1.0 / (x * x + y * y).sqrt()
+        };
+        let start = Body::new(1.0, 1.5, 0.0, 0.0, Scalar::sqrt(1.0 / 3.0));
+        let e0 = energy(&start);
+        let period = 2.0 * std::f64::consts::PI as Scalar;
+
+        let mut sim = Simulation::new(vec![start], period / steps as Scalar, 1.0, 0.0, 0.5);
+        sim.set_self_gravity(false);
+        sim.set_external_field(crate::external::point_mass(1.0));
+        sim.set_integrator(integrator);
+
+        let mut max_error: Scalar = 0.0;
+        for _ in 0..steps * periods {
+            sim.step();
+            max_error = max_error.max(((energy(&sim.bodies()[0]) - e0) / e0).abs());
+        }
+        max_error
+    }
+
+    #[test]
+    fn test_yoshida_energy_error_converges_at_fourth_order() {
+        let errors: Vec<Scalar> = [50, 100, 200]
+            .iter()
+            .map(|&steps| kepler_energy_error(Integrator::Yoshida4, steps, 1))
+            .collect();
+
+        // Halving dt cuts the error by 2⁴
+        for pair in errors.windows(2) {
+            let ratio = pair[0] / pair[1];
+            assert!((10.0..25.0).contains(&ratio), "errors {:?}", errors);
+        }
+
+        // Far below leapfrog's error at the same dt, and with no drift over
+        // many orbits
+        let leapfrog = kepler_energy_error(Integrator::Leapfrog, 100, 1);
+        assert!(errors[1] < 0.1 * leapfrog, "Yoshida {} vs leapfrog {}", errors[1], leapfrog);
+        let long_run = kepler_energy_error(Integrator::Yoshida4, 100, 100);
+        assert!(long_run < 1.5 * errors[1], "error grew to {} over 100 orbits", long_run);
+    }
+
+    /// A tight equal-mass binary (period about 0.0063) at the centre of a
+    /// halo of light bodies on circular orbits between radii 1 and 3
+    fn core_and_halo(halo: usize) -> Vec<Body> {
+        let mut bodies = vec![Body::new(1.0, -0.01, 0.0, 0.0, -5.0), Body::new(1.0, 0.01, 0.0, 0.0, 5.0)];
+        for i in 0..halo {
+            let r = 1.0 + 2.0 * i as Scalar / halo as Scalar;
+            let theta = 2.399_963 * i as Scalar;
+            let (sin, cos) = theta.sin_cos();
+            let v = (2.0 / r).sqrt();
+            bodies.push(Body::new(1e-4, r * cos, r * sin, -v * sin, v * cos));
+        }
+        bodies
+    }
+
+    /// Largest relative energy error of `core_and_halo` over 0.2 time units
+    fn core_and_halo_energy_error(integrator: Integrator, dt: Scalar) -> Scalar {
+        let mut sim = Simulation::new(core_and_halo(40), dt, 1.0, 1e-8, 0.0);
+        sim.set_integrator(integrator);
+        let e0 = sim.total_energy();
+
+        let mut max_error: Scalar = 0.0;
+        for _ in 0..(0.2 / dt).round() as usize {
+            sim.step();
+            max_error = max_error.max(((sim.total_energy() - e0) / e0).abs());
+        }
+        max_error
+    }
+
+    #[test]
+    fn test_block_timesteps_resolve_dense_core() {
+        // The binary goes on rung 7 (128 substeps) and the halo on rungs 0
+        // to 2, about 350 force evaluations per step, or eight of the whole
+        // system: the cost of a uniform leapfrog step of dt / 8
+        let block = core_and_halo_energy_error(Integrator::BlockLeapfrog { levels: 8, accuracy: 2.5e-5 }, 0.01);
+        let uniform = core_and_halo_energy_error(Integrator::Leapfrog, 0.01 / 8.0);
+
+        assert!(block < 1e-5, "block timestep energy error {}", block);
+        assert!(block < 1e-2 * uniform, "block {} vs uniform {}", block, uniform);
+
+        // One level is an ordinary leapfrog step
+        let single = core_and_halo_energy_error(Integrator::BlockLeapfrog { levels: 1, accuracy: 2.5e-5 }, 0.01);
+        assert!(single > 0.1, "single level energy error {}", single);
+    }
+
+    #[test]
+    fn test_grid_cutoff_matches_direct_sum_when_cutoff_covers_system() {
+        use rand::{Rng, SeedableRng};
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(9);
+        let bodies: Vec<Body> = (0..200)
+            .map(|_| Body::new(rng.gen_range(0.5..1.5), rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), 0.0, 0.0))
+            .collect();
+
+        let mut direct = Simulation::new(bodies.clone(), 0.01, 1.0, 0.01, 0.0);
+        let mut grid = Simulation::new(bodies, 0.01, 1.0, 0.01, 0.0);
+        grid.set_force_method(ForceMethod::GridCutoff { cutoff: 5.0 });
+        direct.step();
+        grid.step();
+
+        for (a, b) in direct.bodies().iter().zip(grid.bodies()) {
+            for k in 0..2 {
+                let tolerance = 1e3 * Scalar::EPSILON * a.acceleration[k].abs().max(1.0);
+                assert!((a.acceleration[k] - b.acceleration[k]).abs() < tolerance);
+            }
+        }
+        let (pd, pg) = (direct.potential_energy(), grid.potential_energy());
+        assert!((pd - pg).abs() < 1e3 * Scalar::EPSILON * pd.abs());
+
+        // A short cutoff drops the long-range part of the force
+        grid.set_force_method(ForceMethod::GridCutoff { cutoff: 0.1 });
+        assert!(grid.potential_energy() > 0.5 * pd);
+    }
+}
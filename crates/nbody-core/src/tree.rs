@@ -1,31 +1,68 @@
-use std::cmp::Ordering;
+use core::cmp::Ordering;
+use core::fmt::Write;
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+#[cfg(not(any(feature = "std", test)))]
+use crate::math::Float;
 use crate::body::Body;
+use crate::force::{CompensatedSum, ForceParams, ForceSum, NaiveSum, OpeningCriterion};
+use crate::Scalar;
 
-#[derive(Debug, Clone)]
+/// Fraction of bodies that may change cell before `QuadTree::update_positions`
+/// gives up in favour of a full rebuild
+const UPDATE_MAX_MOVED_FRACTION: Scalar = 0.1;
+
+/// Depth below which cells are no longer subdivided. Halving the root cell
+/// this often exhausts the mantissa, so deeper cells could not separate
+/// bodies anyway.
+const MAX_DEPTH: usize = Scalar::MANTISSA_DIGITS as usize;
+
+/// Nodes per body in a tree of randomly placed bodies, in hundredths, as
+/// counted by `cargo bench -p nbody-core --bench tree_build` (172109 nodes
+/// for 100k uniform bodies)
+const NODES_PER_HUNDRED_BODIES: usize = 172;
+
+/// Bookkeeping a typical allocator adds to each heap allocation
+const ALLOCATION_OVERHEAD: usize = 16;
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Bounds {
-    pub min: [f64; 2],
-    pub max: [f64; 2],
+    pub min: [Scalar; 2],
+    pub max: [Scalar; 2],
 }
 
 impl Bounds {
-    pub fn new(min: [f64; 2], max: [f64; 2]) -> Self {
+    pub fn new(min: [Scalar; 2], max: [Scalar; 2]) -> Self {
         Bounds { min, max }
     }
 
-    pub fn center(&self) -> [f64; 2] {
+    pub fn center(&self) -> [Scalar; 2] {
         [
             (self.min[0] + self.max[0]) * 0.5,
             (self.min[1] + self.max[1]) * 0.5,
         ]
     }
 
-    pub fn diagonal(&self) -> f64 {
+    /// Length of the longer side
+    pub fn width(&self) -> Scalar {
+        (self.max[0] - self.min[0]).max(self.max[1] - self.min[1])
+    }
+
+    /// Distance from `point` to the nearest point of the bounds (0 inside)
+    pub fn distance_to(&self, point: [Scalar; 2]) -> Scalar {
+        let dx = (self.min[0] - point[0]).max(point[0] - self.max[0]).max(0.0);
+        let dy = (self.min[1] - point[1]).max(point[1] - self.max[1]).max(0.0);
+        (dx * dx + dy * dy).sqrt()
+    }
+
+    pub fn diagonal(&self) -> Scalar {
         let dx = self.max[0] - self.min[0];
         let dy = self.max[1] - self.min[1];
         (dx * dx + dy * dy).sqrt()
     }
 
-    pub fn contains(&self, point: [f64; 2]) -> bool {
+    pub fn contains(&self, point: [Scalar; 2]) -> bool {
         point[0] >= self.min[0] && point[0] <= self.max[0] &&
         point[1] >= self.min[1] && point[1] <= self.max[1]
     }
@@ -48,76 +85,255 @@ impl Bounds {
 #[derive(Debug)]
 pub struct QuadTree {
     bounds: Bounds,
-    total_mass: f64,
-    center_of_mass: [f64; 2],
-    body: Option<Box<Body>>,
+    total_mass: Scalar,
+    center_of_mass: [Scalar; 2],
+    // Bodies stored in a leaf with their insertion index, used to match them
+    // up in `update_positions`. Only leaves at `max_depth` hold more than one.
+    bodies: Vec<(usize, Body)>,
+    body_count: usize,
+    depth: usize,
+    max_depth: usize,
     children: [Option<Box<QuadTree>>; 4],
 }
 
 impl QuadTree {
     pub fn new(bounds: Bounds) -> Self {
+        Self::with_depth(bounds, 0, MAX_DEPTH)
+    }
+
+    /// An empty tree whose cells are not subdivided below `max_depth` (the
+    /// root is depth 0). Leaves at that depth keep all the bodies that fall
+    /// in them, so the tree is cheap to build but its forces are coarse;
+    /// it is meant for display.
+    pub fn with_max_depth(bounds: Bounds, max_depth: usize) -> Self {
+        Self::with_depth(bounds, 0, max_depth.min(MAX_DEPTH))
+    }
+
+    /// Estimated memory in bytes of a tree holding `n_bodies`, without
+    /// building one. Every node is a separate allocation, and so is each
+    /// leaf's body list, which `Vec` sizes for four entries on first push.
+    pub fn estimate_memory(n_bodies: usize) -> usize {
+        let nodes = n_bodies * NODES_PER_HUNDRED_BODIES / 100;
+        let leaf_bodies = 4 * core::mem::size_of::<(usize, Body)>();
+        nodes * (core::mem::size_of::<QuadTree>() + ALLOCATION_OVERHEAD)
+            + n_bodies * (leaf_bodies + ALLOCATION_OVERHEAD)
+    }
+
+    fn with_depth(bounds: Bounds, depth: usize, max_depth: usize) -> Self {
         QuadTree {
             bounds,
             total_mass: 0.0,
             center_of_mass: [0.0, 0.0],
-            body: None,
+            bodies: Vec::new(),
+            body_count: 0,
+            depth,
+            max_depth,
             children: [None, None, None, None],
         }
     }
 
-    pub fn insert(&mut self, body: Body) {
+    /// Insert a body. Bodies are identified by their insertion order.
+    ///
+    /// Returns whether the body was stored. A body outside the tree's bounds,
+    /// or with a NaN position, has no cell to go in: it is rejected, leaving
+    /// the tree unchanged, and `false` is returned. The tree then holds fewer
+    /// bodies than were offered, so `refit` and `update_positions` refuse it
+    /// and it gets rebuilt.
+    #[must_use = "a body outside the tree's bounds is rejected, not stored"]
+    pub fn insert(&mut self, body: Body) -> bool {
+        if !self.bounds.contains(body.position) {
+            return false;
+        }
+        let index = self.body_count;
+        self.insert_indexed(index, body);
+        true
+    }
+
+    fn insert_indexed(&mut self, index: usize, body: Body) {
         // If this node is empty, store the body here
-        if self.total_mass == 0.0 {
+        if self.body_count == 0 {
             self.total_mass = body.mass;
             self.center_of_mass = body.position;
-            self.body = Some(Box::new(body));
+            self.bodies.push((index, body));
+            self.body_count = 1;
+            return;
+        }
+
+        // Past the maximum depth cells are too small to separate (nearly)
+        // coincident bodies, or a shallow tree was asked for, so the leaf
+        // keeps all of them. The centre of mass is moved towards the new body
+        // rather than recomputed, keeping big leaves linear in their size.
+        if self.is_leaf() && self.depth >= self.max_depth {
+            let total_mass = self.total_mass + body.mass;
+            if total_mass > 0.0 {
+                let weight = body.mass / total_mass;
+                for axis in 0..2 {
+                    self.center_of_mass[axis] += (body.position[axis] - self.center_of_mass[axis]) * weight;
+                }
+            }
+            self.total_mass = total_mass;
+            self.bodies.push((index, body));
+            self.body_count += 1;
             return;
         }
 
         // If this node already contains a body, split it
-        if let Some(existing_body) = self.body.take() {
-            self.subdivide_and_insert(*existing_body);
+        for (existing_index, existing_body) in core::mem::take(&mut self.bodies) {
+            self.subdivide_and_insert(existing_index, existing_body);
         }
 
         // Insert the new body into the appropriate quadrant
-        self.subdivide_and_insert(body);
+        self.subdivide_and_insert(index, body);
+        self.body_count += 1;
 
         // Update center of mass and total mass
         self.update_mass_distribution();
     }
 
-    fn subdivide_and_insert(&mut self, body: Body) {
+    fn subdivide_and_insert(&mut self, index: usize, body: Body) {
         let quadrant = self.get_quadrant(body.position);
         let child = &mut self.children[quadrant];
 
         if child.is_none() {
             let bounds = self.bounds.subdivide()[quadrant].clone();
-            *child = Some(Box::new(QuadTree::new(bounds)));
+            *child = Some(Box::new(QuadTree::with_depth(bounds, self.depth + 1, self.max_depth)));
         }
 
         if let Some(ref mut child) = child {
-            child.insert(body);
+            child.insert_indexed(index, body);
         }
     }
 
-    fn get_quadrant(&self, position: [f64; 2]) -> usize {
+    /// Refresh the tree for new states of the bodies it was built from.
+    ///
+    /// `bodies` must hold the bodies in the order they were inserted. Bodies
+    /// still inside their leaf cell are updated in place, the ones that
+    /// crossed a cell boundary are re-inserted, and the masses and centres of
+    /// mass are recomputed, giving the same tree as a full rebuild with the
+    /// same bounds. This is much cheaper than a rebuild when the timestep is
+    /// small and few bodies change cell.
+    ///
+    /// Returns `false`, leaving the tree untouched, if the body count differs,
+    /// a body left the root bounds or too many bodies changed cell; the caller
+    /// should then rebuild the tree.
+    pub fn update_positions(&mut self, bodies: &[Body]) -> bool {
+        if bodies.len() != self.body_count || !bodies.iter().all(|b| self.bounds.contains(b.position)) {
+            return false;
+        }
+
+        let max_moved = (bodies.len() as Scalar * UPDATE_MAX_MOVED_FRACTION) as usize;
+        if self.count_moved(bodies) > max_moved {
+            return false;
+        }
+
+        let mut moved = Vec::new();
+        self.refresh(bodies, &mut moved);
+        for index in moved {
+            self.insert_indexed(index, bodies[index].clone());
+        }
+        true
+    }
+
+    /// Copy new states of the bodies the tree was built from into their
+    /// leaves and recompute the masses and centres of mass, keeping the
+    /// structure as it is.
+    ///
+    /// Unlike `update_positions` no body is moved to another cell, so a body
+    /// may end up outside the bounds of the node holding it and the opening
+    /// criterion sees nodes as smaller than they are. This is only a good
+    /// approximation while bodies have moved a small fraction of their cell.
+    ///
+    /// Returns `false`, leaving the tree untouched, if the body count differs.
+    pub fn refit(&mut self, bodies: &[Body]) -> bool {
+        if bodies.len() != self.body_count {
+            return false;
+        }
+        self.refit_node(bodies);
+        true
+    }
+
+    fn refit_node(&mut self, bodies: &[Body]) {
+        for (index, body) in self.bodies.iter_mut() {
+            *body = bodies[*index].clone();
+        }
+        for child in self.children.iter_mut().flatten() {
+            child.refit_node(bodies);
+        }
+        self.update_mass_distribution();
+    }
+
+    /// Number of stored bodies whose new position is outside their leaf cell
+    fn count_moved(&self, bodies: &[Body]) -> usize {
+        let own = self.bodies
+            .iter()
+            .filter(|(index, _)| !self.bounds.contains(bodies[*index].position))
+            .count();
+        own + self.children.iter().flatten().map(|child| child.count_moved(bodies)).sum::<usize>()
+    }
+
+    /// Copy the new state of every stored body, detaching the ones that left
+    /// their cell into `moved` and collapsing nodes left with a single body
+    fn refresh(&mut self, bodies: &[Body], moved: &mut Vec<usize>) {
+        if self.is_leaf() {
+            let bounds = &self.bounds;
+            self.bodies.retain_mut(|(index, body)| {
+                let current = &bodies[*index];
+                if bounds.contains(current.position) {
+                    *body = current.clone();
+                    true
+                } else {
+                    moved.push(*index);
+                    false
+                }
+            });
+            self.body_count = self.bodies.len();
+        } else {
+            for slot in self.children.iter_mut() {
+                if let Some(child) = slot {
+                    child.refresh(bodies, moved);
+                    if child.body_count == 0 {
+                        *slot = None;
+                    }
+                }
+            }
+            self.body_count = self.children.iter().flatten().map(|child| child.body_count).sum();
+
+            // A lone remaining body is always in a (collapsed) leaf child
+            if self.body_count == 1 {
+                if let Some(child) = self.children.iter_mut().find_map(|child| child.take()) {
+                    self.bodies = child.bodies;
+                }
+            }
+        }
+
+        self.update_mass_distribution();
+    }
+
+    fn get_quadrant(&self, position: [Scalar; 2]) -> usize {
         let center = self.bounds.center();
         match (position[0].partial_cmp(&center[0]), position[1].partial_cmp(&center[1])) {
             (Some(Ordering::Greater), Some(Ordering::Greater)) => 0, // Quadrant 1
             (Some(Ordering::Less | Ordering::Equal), Some(Ordering::Greater)) => 1, // Quadrant 2
             (Some(Ordering::Less | Ordering::Equal), Some(Ordering::Less | Ordering::Equal)) => 2, // Quadrant 3
             (Some(Ordering::Greater), Some(Ordering::Less | Ordering::Equal)) => 3, // Quadrant 4
-            _ => 0, // Handle NaN cases by defaulting to quadrant 1
+            _ => 0, // NaN, which `insert` keeps out of the tree
         }
     }
 
     fn update_mass_distribution(&mut self) {
+        // A single body is taken exactly, as when it was inserted
+        if let [(_, body)] = self.bodies.as_slice() {
+            self.total_mass = body.mass;
+            self.center_of_mass = body.position;
+            return;
+        }
+
         let mut total_mass = 0.0;
         let mut com_x = 0.0;
         let mut com_y = 0.0;
 
-        // Add contribution from direct body if present
-        if let Some(ref body) = self.body {
+        // Add contributions from bodies stored in a leaf
+        for (_, body) in &self.bodies {
             total_mass += body.mass;
             com_x += body.mass * body.position[0];
             com_y += body.mass * body.position[1];
@@ -136,44 +352,192 @@ impl QuadTree {
         self.total_mass = total_mass;
     }
 
-    pub fn calculate_force(&self, body: &Body, g: f64, softening: f64, threshold: f64) -> [f64; 2] {
-        // Don't calculate force with self
-        if let Some(ref node_body) = self.body {
-            if std::ptr::eq(body, &**node_body) {
-                return [0.0, 0.0];
-            }
-        }
+    pub fn calculate_force(&self, body: &Body, g: Scalar, softening: Scalar, threshold: Scalar) -> [Scalar; 2] {
+        self.calculate_force_with(body, &ForceParams::new(g, softening, threshold))
+    }
 
-        let dx = self.center_of_mass[0] - body.position[0];
-        let dy = self.center_of_mass[1] - body.position[1];
-        let distance_sq = dx * dx + dy * dy;
-        let distance = distance_sq.sqrt();
+    /// Calculate the force on `body` under the given force law and parameters
+    pub fn calculate_force_with(&self, body: &Body, params: &ForceParams) -> [Scalar; 2] {
+        let force = if params.compensated {
+            self.accumulate_force::<CompensatedSum<2>>(body, params).total()
+        } else {
+            self.accumulate_force::<NaiveSum<2>>(body, params).total()
+        };
+        params.law.finish(force, body.mass)
+    }
 
-        // If this is a leaf node or the node is sufficiently far away
-        if self.is_leaf() || (self.bounds.diagonal() / distance) < threshold {
-            if distance_sq == 0.0 {
-                return [0.0, 0.0];
-            }
+    fn accumulate_force<S: ForceSum<2>>(&self, body: &Body, params: &ForceParams) -> S {
+        let mut total_force = S::default();
 
-            // Calculate gravitational force
-            let force = (g * body.mass * self.total_mass) / (distance_sq + softening);
-            let force_x = force * dx / distance;
-            let force_y = force * dy / distance;
+        // Leaves interact body by body
+        if self.is_leaf() {
+            for (_, other) in &self.bodies {
+                // Don't calculate force with self
+                if core::ptr::eq(body, other) {
+                    continue;
+                }
+                let force = point_force(body, other.mass, other.position, params);
+                let (push, _) = repulsion(body, other.position, params);
+                let drag = radiation_reaction(body, other, params);
+                total_force.add([force[0] + push[0] + drag[0], force[1] + push[1] + drag[1]]);
+            }
+            return total_force;
+        }
 
-            return [force_x, force_y];
+        // If the node is sufficiently far away, treat it as a point mass
+        if self.is_far(body, params) {
+            total_force.add(point_force(body, self.total_mass, self.center_of_mass, params));
+            return total_force;
         }
 
         // Otherwise, recursively calculate forces from children
-        let mut total_force = [0.0, 0.0];
         for child in self.children.iter().flatten() {
-            let force = child.calculate_force(body, g, softening, threshold);
-            total_force[0] += force[0];
-            total_force[1] += force[1];
+            total_force.merge(child.accumulate_force(body, params));
         }
 
         total_force
     }
 
+    /// Calculate the force on `body` together with its potential energy.
+    ///
+    /// Uses the same traversal and opening criterion as `calculate_force`. The
+    /// potential is the exact antiderivative of the softened force law
+    /// `G m M / (r² + softening)`, so energy is consistent with the dynamics.
+    pub fn calculate_force_and_potential(
+        &self,
+        body: &Body,
+        g: Scalar,
+        softening: Scalar,
+        threshold: Scalar,
+    ) -> ([Scalar; 2], Scalar) {
+        self.calculate_force_and_potential_with(body, &ForceParams::new(g, softening, threshold))
+    }
+
+    /// `calculate_force_and_potential` under the given force law and parameters
+    pub fn calculate_force_and_potential_with(&self, body: &Body, params: &ForceParams) -> ([Scalar; 2], Scalar) {
+        let [fx, fy, potential] = if params.compensated {
+            self.accumulate_force_and_potential::<CompensatedSum<3>>(body, params).total()
+        } else {
+            self.accumulate_force_and_potential::<NaiveSum<3>>(body, params).total()
+        };
+        (params.law.finish([fx, fy], body.mass), potential)
+    }
+
+    fn accumulate_force_and_potential<S: ForceSum<3>>(&self, body: &Body, params: &ForceParams) -> S {
+        let mut total = S::default();
+        let mut add = |(force, potential): ([Scalar; 2], Scalar)| total.add([force[0], force[1], potential]);
+
+        if self.is_leaf() {
+            for (_, other) in &self.bodies {
+                add(point_force_and_potential(body, other.mass, other.position, params));
+                add(repulsion(body, other.position, params));
+                add((radiation_reaction(body, other, params), 0.0));
+            }
+        } else if self.is_far(body, params) {
+            add(point_force_and_potential(body, self.total_mass, self.center_of_mass, params));
+        } else {
+            for child in self.children.iter().flatten() {
+                total.merge(child.accumulate_force_and_potential(body, params));
+            }
+        }
+
+        total
+    }
+
+    /// Barnes-Hut opening criterion: whether this node is far enough from
+    /// `body` to be approximated by its centre of mass
+    fn is_far(&self, body: &Body, params: &ForceParams) -> bool {
+        let com_distance = || {
+            let dx = self.center_of_mass[0] - body.position[0];
+            let dy = self.center_of_mass[1] - body.position[1];
+            (dx * dx + dy * dy).sqrt()
+        };
+        let (size, distance) = match params.opening {
+            OpeningCriterion::Diagonal => (self.bounds.diagonal(), com_distance()),
+            OpeningCriterion::Width => (self.bounds.width(), com_distance()),
+            OpeningCriterion::MinDistance => (self.bounds.width(), self.bounds.distance_to(body.position)),
+        };
+        (size / distance) < params.threshold
+    }
+
+    /// Number of internal nodes the force traversal for `body` opens.
+    ///
+    /// This is the work the opening criterion and threshold in `params`
+    /// cause: a smaller threshold or a more conservative criterion opens
+    /// more nodes, giving more accurate but slower forces.
+    pub fn nodes_opened(&self, body: &Body, params: &ForceParams) -> usize {
+        if self.is_leaf() || self.is_far(body, params) {
+            return 0;
+        }
+        1 + self.children.iter().flatten().map(|child| child.nodes_opened(body, params)).sum::<usize>()
+    }
+
+    /// Find the body stored in the leaf whose cell contains `point` (the
+    /// first one, if the leaf holds several coincident bodies).
+    ///
+    /// Returns `None` if the point lies outside the tree bounds or falls in
+    /// an empty cell.
+    pub fn locate(&self, point: [Scalar; 2]) -> Option<&Body> {
+        if !self.bounds.contains(point) {
+            return None;
+        }
+
+        let mut node = self;
+        loop {
+            if node.is_leaf() {
+                return node.bodies.first().map(|(_, body)| body);
+            }
+            node = node.children[node.get_quadrant(point)].as_deref()?;
+        }
+    }
+
+    /// Distance from `point` to the `k`th nearest body in the tree, counting
+    /// any body at `point` itself, or infinity if the tree holds fewer than
+    /// `k` bodies.
+    ///
+    /// Cells are searched nearest first and skipped once they are farther
+    /// than the `k`th nearest body found so far.
+    pub fn kth_nearest_distance(&self, point: [Scalar; 2], k: usize) -> Scalar {
+        if k == 0 {
+            return 0.0;
+        }
+        // Squared distances of the nearest bodies found so far, in order
+        let mut nearest = Vec::with_capacity(k + 1);
+        self.collect_nearest(point, k, &mut nearest);
+        if nearest.len() < k { Scalar::INFINITY } else { nearest[k - 1].sqrt() }
+    }
+
+    fn collect_nearest(&self, point: [Scalar; 2], k: usize, nearest: &mut Vec<Scalar>) {
+        if self.body_count == 0 {
+            return;
+        }
+        if nearest.len() == k {
+            let distance = self.bounds.distance_to(point);
+            if distance * distance >= nearest[k - 1] {
+                return;
+            }
+        }
+
+        for (_, body) in &self.bodies {
+            let dx = body.position[0] - point[0];
+            let dy = body.position[1] - point[1];
+            let distance_sq = dx * dx + dy * dy;
+            if nearest.len() < k || distance_sq < nearest[k - 1] {
+                nearest.insert(nearest.partition_point(|&d| d <= distance_sq), distance_sq);
+                nearest.truncate(k);
+            }
+        }
+
+        let mut order = [0, 1, 2, 3];
+        let distance = |i: usize| self.children[i].as_ref().map_or(Scalar::INFINITY, |child| child.bounds.distance_to(point));
+        order.sort_unstable_by(|&a, &b| distance(a).total_cmp(&distance(b)));
+        for i in order {
+            if let Some(child) = self.children[i].as_deref() {
+                child.collect_nearest(point, k, nearest);
+            }
+        }
+    }
+
     fn is_leaf(&self) -> bool {
         self.children.iter().all(|child| child.is_none())
     }
@@ -183,7 +547,405 @@ impl QuadTree {
         &self.bounds
     }
 
+    pub fn total_mass(&self) -> Scalar {
+        self.total_mass
+    }
+
+    pub fn center_of_mass(&self) -> [Scalar; 2] {
+        self.center_of_mass
+    }
+
+    /// Number of bodies stored in this node and its descendants
+    pub fn body_count(&self) -> usize {
+        self.body_count
+    }
+
     pub fn get_children(&self) -> &[Option<Box<QuadTree>>; 4] {
         &self.children
     }
-}
\ No newline at end of file
+
+    /// Number of leaves holding more than one body because they are at the
+    /// maximum depth and could not be subdivided further
+    pub fn capped_leaves(&self) -> usize {
+        if self.is_leaf() {
+            return usize::from(self.bodies.len() > 1);
+        }
+        self.children.iter().flatten().map(|child| child.capped_leaves()).sum()
+    }
+
+    /// Graphviz DOT representation of the tree for debugging.
+    ///
+    /// Every node is labelled with its total mass and body count; leaves
+    /// holding a body are drawn as ellipses, internal nodes as boxes.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph quadtree {\n    node [shape=box];\n");
+        let mut next_id = 0;
+        self.write_dot_node(&mut dot, &mut next_id);
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Append this node and its subtree, returning the node's id
+    fn write_dot_node(&self, dot: &mut String, next_id: &mut usize) -> usize {
+        let id = *next_id;
+        *next_id += 1;
+
+        let shape = if !self.bodies.is_empty() { ", shape=ellipse" } else { "" };
+        // Writing to a String cannot fail
+        let _ = writeln!(
+            dot,
+            "    n{} [label=\"mass {:.4e}\\nbodies {}\"{}];",
+            id, self.total_mass, self.body_count, shape
+        );
+
+        for (quadrant, child) in self.children.iter().enumerate() {
+            if let Some(child) = child {
+                let child_id = child.write_dot_node(dot, next_id);
+                let _ = writeln!(dot, "    n{} -> n{} [label=\"{}\"];", id, child_id, quadrant + 1);
+            }
+        }
+
+        id
+    }
+}
+
+/// Force on `body` from a point mass at `position`
+fn point_force(body: &Body, mass: Scalar, position: [Scalar; 2], params: &ForceParams) -> [Scalar; 2] {
+    let dx = position[0] - body.position[0];
+    let dy = position[1] - body.position[1];
+    let distance_sq = dx * dx + dy * dy;
+    if distance_sq == 0.0 {
+        return [0.0, 0.0];
+    }
+
+    params.pair_force(params.g * body.mass * mass, [dx, dy], distance_sq)
+}
+
+/// Force on `body` from a point mass at `position` together with their
+/// potential energy
+fn point_force_and_potential(
+    body: &Body,
+    mass: Scalar,
+    position: [Scalar; 2],
+    params: &ForceParams,
+) -> ([Scalar; 2], Scalar) {
+    let dx = position[0] - body.position[0];
+    let dy = position[1] - body.position[1];
+    let distance_sq = dx * dx + dy * dy;
+    if distance_sq == 0.0 {
+        return ([0.0, 0.0], 0.0);
+    }
+
+    let g_mm = params.g * body.mass * mass;
+    let force = params.pair_force(g_mm, [dx, dy], distance_sq);
+    let potential = params.law.pair_potential(g_mm, distance_sq.sqrt(), params.potential_softening());
+    (force, potential)
+}
+
+/// Short-range repulsion on `body` from another body at `position`, with
+/// its potential energy; zero unless the force law has a repulsive core.
+/// Bodies only repel bodies, so this is never applied to tree nodes.
+fn repulsion(body: &Body, position: [Scalar; 2], params: &ForceParams) -> ([Scalar; 2], Scalar) {
+    let dx = position[0] - body.position[0];
+    let dy = position[1] - body.position[1];
+    let distance_sq = dx * dx + dy * dy;
+    if distance_sq == 0.0 {
+        return ([0.0, 0.0], 0.0);
+    }
+
+    match params.law.repulsion(distance_sq) {
+        Some((force, potential)) => {
+            let distance = distance_sq.sqrt();
+            ([-force * dx / distance, -force * dy / distance], potential)
+        }
+        None => ([0.0, 0.0], 0.0),
+    }
+}
+
+/// Post-Newtonian radiation reaction on `body` from `other`; zero unless
+/// the force law has one. Like the repulsion it needs the other body's
+/// velocity, so it is never applied to tree nodes.
+fn radiation_reaction(body: &Body, other: &Body, params: &ForceParams) -> [Scalar; 2] {
+    if body.position == other.position {
+        return [0.0, 0.0];
+    }
+    params.law
+        .radiation_reaction(
+            params.g,
+            params.softening,
+            [body.mass, other.mass],
+            [body.position[0] - other.position[0], body.position[1] - other.position[1]],
+            [body.velocity[0] - other.velocity[0], body.velocity[1] - other.velocity[1]],
+        )
+        .unwrap_or([0.0, 0.0])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+
+    fn square_tree(bodies: &[Body]) -> QuadTree {
+        let mut tree = QuadTree::new(Bounds::new([-1.0, -1.0], [1.0, 1.0]));
+        for body in bodies {
+            assert!(tree.insert(body.clone()));
+        }
+        tree
+    }
+
+    #[test]
+    fn test_insert_rejects_bodies_outside_bounds() {
+        let inside = Body::new(1.0, 0.5, 0.5, 0.0, 0.0);
+        let mut tree = square_tree(std::slice::from_ref(&inside));
+
+        for position in [[2.0, 0.0], [-0.5, -1.5], [Scalar::NAN, 0.0], [0.0, Scalar::INFINITY]] {
+            assert!(!tree.insert(Body::new(10.0, position[0], position[1], 0.0, 0.0)), "{:?}", position);
+        }
+        assert_eq!(tree.body_count, 1);
+        assert_eq!(tree.total_mass(), 1.0);
+        assert_eq!(tree.center_of_mass, [0.5, 0.5]);
+
+        // On the boundary counts as inside
+        assert!(tree.insert(Body::new(1.0, -1.0, 1.0, 0.0, 0.0)));
+        assert_eq!(tree.center_of_mass, [-0.25, 0.75]);
+
+        // Fewer bodies in the tree than in the system: refit refuses
+        let bodies = [inside.clone(), Body::new(1.0, -1.0, 1.0, 0.0, 0.0), Body::new(1.0, 5.0, 0.0, 0.0, 0.0)];
+        assert!(!tree.refit(&bodies));
+    }
+
+    #[test]
+    fn test_locate_bodies_in_quadrants() {
+        let bodies = [
+            Body::new(1.0, 0.5, 0.5, 0.0, 0.0),
+            Body::new(2.0, -0.5, 0.5, 0.0, 0.0),
+            Body::new(3.0, -0.5, -0.5, 0.0, 0.0),
+            Body::new(4.0, 0.5, -0.5, 0.0, 0.0),
+        ];
+        let tree = square_tree(&bodies);
+
+        for body in &bodies {
+            // Any point in the same quadrant leads to the body stored there
+            let probe = [body.position[0] * 1.5, body.position[1] * 0.5];
+            let found = tree.locate(probe).expect("body not found");
+            assert_eq!(found.mass, body.mass);
+            assert_eq!(found.position, body.position);
+        }
+    }
+
+    #[test]
+    fn test_kth_nearest_distance_matches_brute_force() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(11);
+        let bodies: Vec<Body> = (0..300)
+            .map(|_| Body::new(1.0, rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), 0.0, 0.0))
+            .collect();
+        let tree = square_tree(&bodies);
+
+        for probe in bodies.iter().take(20).map(|b| b.position).chain([[0.0, 0.0], [0.99, -0.99]]) {
+            let mut distances: Vec<Scalar> = bodies
+                .iter()
+                .map(|b| ((b.position[0] - probe[0]).powi(2) + (b.position[1] - probe[1]).powi(2)).sqrt())
+                .collect();
+            distances.sort_by(Scalar::total_cmp);
+            for k in [1, 2, 9, 300] {
+                assert_eq!(tree.kth_nearest_distance(probe, k), distances[k - 1], "k = {} at {:?}", k, probe);
+            }
+        }
+        assert_eq!(tree.kth_nearest_distance([0.0, 0.0], 301), Scalar::INFINITY);
+        assert_eq!(tree.kth_nearest_distance([0.0, 0.0], 0), 0.0);
+    }
+
+    #[test]
+    fn test_locate_outside_or_empty() {
+        let tree = square_tree(&[
+            Body::new(1.0, 0.5, 0.5, 0.0, 0.0),
+            Body::new(1.0, 0.6, 0.6, 0.0, 0.0),
+        ]);
+
+        assert!(tree.locate([2.0, 0.0]).is_none());
+        assert!(tree.locate([-0.5, -0.5]).is_none());
+    }
+
+    fn random_bodies(rng: &mut impl Rng, n: usize) -> Vec<Body> {
+        (0..n)
+            .map(|_| Body::new(rng.gen_range(0.5..1.5), rng.gen_range(-0.9..0.9), rng.gen_range(-0.9..0.9), 0.0, 0.0))
+            .collect()
+    }
+
+    #[test]
+    fn test_update_positions_matches_rebuild() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(3);
+        let mut bodies = random_bodies(&mut rng, 500);
+        let mut tree = square_tree(&bodies);
+
+        // A small jitter moves a few bodies across cell boundaries
+        for body in bodies.iter_mut() {
+            body.position[0] += rng.gen_range(-1e-3..1e-3);
+            body.position[1] += rng.gen_range(-1e-3..1e-3);
+        }
+        assert!(tree.count_moved(&bodies) > 0);
+        assert!(tree.update_positions(&bodies));
+
+        let rebuilt = square_tree(&bodies);
+        assert_eq!(tree.body_count(), rebuilt.body_count());
+        assert!((tree.total_mass() - rebuilt.total_mass()).abs() < 1e3 * Scalar::EPSILON);
+        for body in &bodies {
+            let a = tree.calculate_force(body, 1.0, 1e-4, 0.5);
+            let b = rebuilt.calculate_force(body, 1.0, 1e-4, 0.5);
+            let scale = b[0].abs() + b[1].abs();
+            assert!((a[0] - b[0]).abs() + (a[1] - b[1]).abs() < 1e3 * Scalar::EPSILON * scale);
+        }
+    }
+
+    #[test]
+    fn test_update_positions_falls_back() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(4);
+        let bodies = random_bodies(&mut rng, 200);
+        let mut tree = square_tree(&bodies);
+
+        // Everything reshuffled: too many bodies change cell
+        let shuffled = random_bodies(&mut rng, 200);
+        assert!(!tree.update_positions(&shuffled));
+
+        // A body leaving the root bounds
+        let mut escaped = bodies.clone();
+        escaped[0].position = [5.0, 0.0];
+        assert!(!tree.update_positions(&escaped));
+
+        // A different body count
+        assert!(!tree.update_positions(&bodies[1..]));
+
+        // The tree is untouched by a failed update
+        let probe = Body::new(1.0, 0.0, 0.0, 0.0, 0.0);
+        let rebuilt = square_tree(&bodies);
+        assert_eq!(tree.calculate_force(&probe, 1.0, 0.0, 0.5), rebuilt.calculate_force(&probe, 1.0, 0.0, 0.5));
+    }
+
+    #[test]
+    fn test_to_dot_declares_every_node() {
+        // Two bodies in one quadrant force an extra level: root, two
+        // children and the two leaves under the crowded child
+        let tree = square_tree(&[
+            Body::new(1.0, 0.7, 0.7, 0.0, 0.0),
+            Body::new(1.0, 0.2, 0.2, 0.0, 0.0),
+            Body::new(2.0, -0.5, -0.5, 0.0, 0.0),
+        ]);
+        let dot = tree.to_dot();
+
+        assert!(dot.starts_with("digraph quadtree {"));
+        assert!(dot.trim_end().ends_with('}'));
+        let declarations = dot.lines().filter(|line| line.contains("[label=\"mass")).count();
+        let edges = dot.lines().filter(|line| line.contains("->")).count();
+        assert_eq!(declarations, 5);
+        assert_eq!(edges, 4);
+        assert!(dot.contains("n0 [label=\"mass 4.0000e0\\nbodies 3\"];"));
+    }
+
+    #[test]
+    fn test_estimate_memory_node_count() {
+        fn count_nodes(tree: &QuadTree) -> usize {
+            1 + tree.get_children().iter().flatten().map(|child| count_nodes(child)).sum::<usize>()
+        }
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(6);
+        let n = 20_000;
+        let tree = square_tree(&random_bodies(&mut rng, n));
+        let per_body = count_nodes(&tree) as Scalar / n as Scalar;
+        assert!((per_body - NODES_PER_HUNDRED_BODIES as Scalar / 100.0).abs() < 0.05, "{} nodes per body", per_body);
+
+        assert_eq!(QuadTree::estimate_memory(0), 0);
+        assert_eq!(QuadTree::estimate_memory(2 * n), 2 * QuadTree::estimate_memory(n));
+    }
+
+    #[test]
+    fn test_compensated_summation_reduces_cancellation_error() {
+        // Mirror-image pairs of very different masses around the probe cancel
+        // exactly, leaving only the pull of one light body
+        let mut rng = rand::rngs::StdRng::seed_from_u64(11);
+        let mut bodies = Vec::new();
+        for _ in 0..500 {
+            let mass = (10.0 as Scalar).powi(rng.gen_range(0..8));
+            let (x, y) = (rng.gen_range(-0.9..0.9), rng.gen_range(-0.9..0.9));
+            bodies.push(Body::new(mass, x, y, 0.0, 0.0));
+            bodies.push(Body::new(mass, -x, -y, 0.0, 0.0));
+        }
+        let light = Body::new(1e-3, 0.6, 0.2, 0.0, 0.0);
+        bodies.push(light.clone());
+        let tree = square_tree(&bodies);
+
+        let probe = Body::new(1.0, 0.0, 0.0, 0.0, 0.0);
+        let params = ForceParams::new(1.0, 0.0, 0.0);
+        let exact = point_force(&probe, light.mass, light.position, &params);
+        let error = |compensated| {
+            let force = tree.calculate_force_with(&probe, &ForceParams { compensated, ..params });
+            ((force[0] - exact[0]).powi(2) + (force[1] - exact[1]).powi(2)).sqrt()
+                / (exact[0].powi(2) + exact[1].powi(2)).sqrt()
+        };
+
+        let naive = error(false);
+        let compensated = error(true);
+        assert!(compensated < 0.01 * naive, "naive {} vs compensated {}", naive, compensated);
+    }
+
+    #[test]
+    fn test_zero_mass_bodies_are_kept() {
+        // Emptiness is judged by body count, not mass, so a massless body
+        // occupies its cell like any other
+        let massless = Body::new(0.0, 0.5, 0.5, 0.0, 0.0);
+        let massive = Body::new(2.0, 0.6, 0.4, 0.0, 0.0);
+        let tree = square_tree(&[massless.clone(), massive.clone()]);
+
+        assert_eq!(tree.body_count(), 2);
+        assert_eq!(tree.total_mass(), 2.0);
+        assert_eq!(tree.center_of_mass(), massive.position);
+        assert_eq!(tree.locate(massless.position).map(|b| b.position), Some(massless.position));
+        assert_eq!(tree.locate(massive.position).map(|b| b.position), Some(massive.position));
+
+        // The massless body feels the other and exerts nothing on it
+        let probe = Body::new(1.0, 0.5, 0.5, 0.0, 0.0);
+        assert!(tree.calculate_force(&probe, 1.0, 0.0, 0.0)[0] > 0.0);
+        let force = tree.calculate_force(&Body::new(1.0, 0.6, 0.4, 0.0, 0.0), 1.0, 0.0, 0.0);
+        assert_eq!(force, [0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_coincident_bodies_share_a_leaf() {
+        let bodies: Vec<Body> = (0..1000).map(|_| Body::new(1.0, 0.25, -0.25, 0.0, 0.0)).collect();
+        let tree = square_tree(&bodies);
+
+        assert_eq!(tree.body_count(), 1000);
+        assert_eq!(tree.total_mass(), 1000.0);
+        assert_eq!(tree.center_of_mass(), [0.25, -0.25]);
+
+        // Forces from the pile, and within it, stay finite
+        let probe = Body::new(1.0, -0.5, 0.5, 0.0, 0.0);
+        let force = tree.calculate_force(&probe, 1.0, 0.0, 0.5);
+        assert!(force[0].is_finite() && force[1].is_finite());
+        assert!(force[0] > 0.0 && force[1] < 0.0);
+        for body in &bodies {
+            let force = tree.calculate_force(body, 1.0, 0.0, 0.5);
+            assert_eq!(force, [0.0, 0.0]);
+        }
+    }
+
+    #[test]
+    fn test_width_criterion_opens_fewer_nodes() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(8);
+        let bodies: Vec<Body> = (0..2000)
+            .map(|_| Body::new(1.0, rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), 0.0, 0.0))
+            .collect();
+        let tree = square_tree(&bodies);
+
+        let opened = |opening| {
+            let params = ForceParams { opening, ..ForceParams::new(1.0, 0.0, 0.5) };
+            bodies.iter().take(100).map(|body| tree.nodes_opened(body, &params)).sum::<usize>()
+        };
+        let diagonal = opened(OpeningCriterion::Diagonal);
+        let width = opened(OpeningCriterion::Width);
+        let min_distance = opened(OpeningCriterion::MinDistance);
+
+        assert!(width < diagonal, "width {} vs diagonal {}", width, diagonal);
+        // The nearest point of a node is never farther than its centre of mass
+        assert!(min_distance >= width, "min distance {} vs width {}", min_distance, width);
+    }
+}
@@ -1,125 +1,226 @@
 use std::fs::File;
 use std::io::{BufReader, BufWriter, Write, BufRead};
 use std::path::Path;
-use nbody_core::Body;
+use nbody_core::{Body, Bounds, NBodyError, QuadTree, Scalar, Simulation};
+use serde::Serialize;
 
-/// Read simulation state from a file
+/// Snapshot format version understood by `read_bodies`
+const SNAPSHOT_VERSION: u32 = 1;
+
+/// Size of the header of a Tipsy file
+const TIPSY_HEADER_BYTES: usize = 32;
+
+/// Path that `read_bodies` and `write_bodies` map to stdin and stdout
+pub const STDIO_PATH: &str = "-";
+
+/// Read simulation state from a file, or from stdin if `path` is `-`.
+///
+/// See `read_bodies_from` for the format.
 pub fn read_bodies<P: AsRef<Path>>(
     path: P
-) -> Result<Vec<Body>, String> {
+) -> Result<Vec<Body>, NBodyError> {
+    if is_stdio(path.as_ref()) {
+        return read_bodies_from(std::io::stdin().lock());
+    }
     let file = File::open(path)
-        .map_err(|e| format!("Failed to open file: {}", e))?;
-    let reader = BufReader::new(file);
-    let mut lines = reader.lines();
+        .map_err(|e| NBodyError::io("Failed to open file", e))?;
+    read_bodies_from(BufReader::new(file))
+}
+
+/// Read simulation state from `reader`.
+///
+/// The input may start with an optional `# nbody <version> <dimensions>d`
+/// line (e.g. `# nbody 1 2d`); files without one are read as version 1, 2D.
+/// Every body line must have the same number of columns: 5, 6 with a
+/// species id, or 9 with a species id and an `r g b` color. Reading stops
+/// after the last body, so anything following it (such as further snapshots
+/// in a stream) is left unread.
+pub fn read_bodies_from<R: BufRead>(reader: R) -> Result<Vec<Body>, NBodyError> {
+    let mut lines = reader.lines().peekable();
+
+    if let Some(Ok(first)) = lines.peek() {
+        if first.trim_start().starts_with('#') {
+            check_version_line(first)?;
+            lines.next();
+        }
+    }
 
     // Read header information
-    let timestep: f64 = lines.next()
-        .ok_or("Missing timestep")?
-        .map_err(|e| format!("Failed to read timestep: {}", e))?
+    let _timestep: Scalar = lines.next()
+        .ok_or_else(|| NBodyError::Parse("Missing timestep".to_string()))?
+        .map_err(|e| NBodyError::io("Failed to read timestep", e))?
         .trim()
         .parse()
-        .map_err(|e| format!("Invalid timestep format: {}", e))?;
+        .map_err(|e| NBodyError::Parse(format!("Invalid timestep format: {}", e)))?;
 
-    let g: f64 = lines.next()
-        .ok_or("Missing G value")?
-        .map_err(|e| format!("Failed to read G value: {}", e))?
+    let _g: Scalar = lines.next()
+        .ok_or_else(|| NBodyError::Parse("Missing G value".to_string()))?
+        .map_err(|e| NBodyError::io("Failed to read G value", e))?
         .trim()
         .parse()
-        .map_err(|e| format!("Invalid G value format: {}", e))?;
+        .map_err(|e| NBodyError::Parse(format!("Invalid G value format: {}", e)))?;
 
-    let softening: f64 = lines.next()
-        .ok_or("Missing softening factor")?
-        .map_err(|e| format!("Failed to read softening factor: {}", e))?
+    let _softening: Scalar = lines.next()
+        .ok_or_else(|| NBodyError::Parse("Missing softening factor".to_string()))?
+        .map_err(|e| NBodyError::io("Failed to read softening factor", e))?
         .trim()
         .parse()
-        .map_err(|e| format!("Invalid softening factor format: {}", e))?;
+        .map_err(|e| NBodyError::Parse(format!("Invalid softening factor format: {}", e)))?;
 
-    let tree_ratio: f64 = lines.next()
-        .ok_or("Missing tree ratio")?
-        .map_err(|e| format!("Failed to read tree ratio: {}", e))?
+    let _tree_ratio: Scalar = lines.next()
+        .ok_or_else(|| NBodyError::Parse("Missing tree ratio".to_string()))?
+        .map_err(|e| NBodyError::io("Failed to read tree ratio", e))?
         .trim()
         .parse()
-        .map_err(|e| format!("Invalid tree ratio format: {}", e))?;
+        .map_err(|e| NBodyError::Parse(format!("Invalid tree ratio format: {}", e)))?;
 
     let n_bodies: usize = lines.next()
-        .ok_or("Missing number of bodies")?
-        .map_err(|e| format!("Failed to read number of bodies: {}", e))?
+        .ok_or_else(|| NBodyError::Parse("Missing number of bodies".to_string()))?
+        .map_err(|e| NBodyError::io("Failed to read number of bodies", e))?
         .trim()
         .parse()
-        .map_err(|e| format!("Invalid number of bodies format: {}", e))?;
+        .map_err(|e| NBodyError::Parse(format!("Invalid number of bodies format: {}", e)))?;
 
     // Read body data
     let mut bodies = Vec::with_capacity(n_bodies);
-    for (i, line) in lines.enumerate() {
-        if i >= n_bodies {
-            break;
-        }
-
-        let line = line.map_err(|e| format!("Failed to read body data: {}", e))?;
-        let parts: Vec<f64> = line.split_whitespace()
-            .map(|s| s.parse::<f64>())
-            .collect::<Result<Vec<f64>, _>>()
-            .map_err(|e| format!("Invalid body data format: {}", e))?;
+    let mut column_count = None;
+    for (i, line) in lines.take(n_bodies).enumerate() {
+        let line = line.map_err(|e| NBodyError::io("Failed to read body data", e))?;
+        let columns: Vec<&str> = line.split_whitespace().collect();
 
-        if parts.len() != 5 {
-            return Err(format!("Invalid body data: expected 5 values, got {}", parts.len()));
+        // An optional sixth column holds the species id, and the three after it the color
+        match (columns.len(), column_count) {
+            (7, None) => {
+                return Err(NBodyError::InvalidBody(
+                    "got 7 values, which looks like a 3D snapshot; only 2D snapshots are supported".to_string(),
+                ));
+            }
+            (5 | 6 | 9, None) => column_count = Some(columns.len()),
+            (n, Some(expected)) if n != expected => {
+                return Err(NBodyError::InvalidBody(format!(
+                    "body {} has {} values but the first body has {}",
+                    i + 1,
+                    n,
+                    expected
+                )));
+            }
+            (5 | 6 | 9, Some(_)) => {}
+            (n, _) => {
+                return Err(NBodyError::InvalidBody(format!("expected 5, 6 or 9 values, got {}", n)));
+            }
         }
 
+        let parts: Vec<Scalar> = columns[..5].iter()
+            .map(|s| s.parse::<Scalar>())
+            .collect::<Result<Vec<Scalar>, _>>()
+            .map_err(|e| NBodyError::Parse(format!("Invalid body data format: {}", e)))?;
+        let species: u8 = match columns.get(5) {
+            Some(s) => s.parse().map_err(|e| NBodyError::Parse(format!("Invalid species format: {}", e)))?,
+            None => 0,
+        };
+        let color = match columns.get(6..9) {
+            Some(rgb) => {
+                let mut color = [0.0; 3];
+                for (channel, s) in color.iter_mut().zip(rgb) {
+                    *channel = s.parse().map_err(|e| NBodyError::Parse(format!("Invalid color format: {}", e)))?;
+                }
+                color
+            }
+            None => [1.0, 1.0, 1.0],
+        };
+
         bodies.push(Body::new(
             parts[0], // mass
             parts[1], // x
             parts[2], // y
             parts[3], // vx
             parts[4], // vy
-        ));
+        ).with_species(species).with_color(color));
     }
 
     if bodies.len() != n_bodies {
-        return Err(format!(
+        return Err(NBodyError::Parse(format!(
             "Mismatch in body count: expected {}, got {}",
             n_bodies,
             bodies.len()
-        ));
+        )));
     }
 
     Ok(bodies)
 }
 
-/// Write simulation state to a file
+/// Check a `# nbody <version> <dimensions>d` header line
+fn check_version_line(line: &str) -> Result<(), NBodyError> {
+    let fields: Vec<&str> = line.trim_start_matches('#').split_whitespace().collect();
+    let [magic, version, dimensions] = fields[..] else {
+        return Err(NBodyError::Parse(format!("Invalid header line: {}", line.trim())));
+    };
+    if magic != "nbody" {
+        return Err(NBodyError::Parse(format!("Invalid header line: {}", line.trim())));
+    }
+
+    let version: u32 = version
+        .parse()
+        .map_err(|e| NBodyError::Parse(format!("Invalid snapshot version format: {}", e)))?;
+    if version > SNAPSHOT_VERSION {
+        return Err(NBodyError::Parse(format!(
+            "Unsupported snapshot version {} (newest supported is {})",
+            version, SNAPSHOT_VERSION
+        )));
+    }
+
+    match dimensions {
+        "2d" | "2D" => Ok(()),
+        "3d" | "3D" => Err(NBodyError::Parse("3D snapshots are not supported".to_string())),
+        other => Err(NBodyError::Parse(format!("Invalid snapshot dimensions: {}", other))),
+    }
+}
+
+/// Write simulation state to a file, or to stdout if `path` is `-`
 pub fn write_bodies<P: AsRef<Path>>(
     path: P,
     bodies: &[Body],
-    timestep: f64,
-    g: f64,
-    softening: f64,
-    tree_ratio: f64,
-) -> Result<(), String> {
-    // Create parent directories if they don't exist
-    if let Some(parent) = path.as_ref().parent() {
-        std::fs::create_dir_all(parent)
-            .map_err(|e| format!("Failed to create directory structure: {}", e))?;
+    timestep: Scalar,
+    g: Scalar,
+    softening: Scalar,
+    tree_ratio: Scalar,
+) -> Result<(), NBodyError> {
+    if is_stdio(path.as_ref()) {
+        let stdout = std::io::stdout().lock();
+        return write_bodies_to(BufWriter::new(stdout), bodies, timestep, g, softening, tree_ratio);
     }
+    let writer = create_writer(path)?;
+    write_bodies_to(writer, bodies, timestep, g, softening, tree_ratio)
+}
 
-    // Open file with proper error handling
-    let file = File::create(path)
-        .map_err(|e| format!("Failed to create file: {}", e))?;
-    let mut writer = BufWriter::new(file);
+/// Write simulation state to `writer`, flushing it afterwards
+pub fn write_bodies_to<W: Write>(
+    mut writer: W,
+    bodies: &[Body],
+    timestep: Scalar,
+    g: Scalar,
+    softening: Scalar,
+    tree_ratio: Scalar,
+) -> Result<(), NBodyError> {
 
     // Write header information
     writeln!(writer, "{:.16e}", timestep)
-        .map_err(|e| format!("Failed to write timestep: {}", e))?;
+        .map_err(|e| NBodyError::io("Failed to write timestep", e))?;
     writeln!(writer, "{:.16e}", g)
-        .map_err(|e| format!("Failed to write G value: {}", e))?;
+        .map_err(|e| NBodyError::io("Failed to write G value", e))?;
     writeln!(writer, "{:.16e}", softening)
-        .map_err(|e| format!("Failed to write softening factor: {}", e))?;
+        .map_err(|e| NBodyError::io("Failed to write softening factor", e))?;
     writeln!(writer, "{:.16e}", tree_ratio)
-        .map_err(|e| format!("Failed to write tree ratio: {}", e))?;
+        .map_err(|e| NBodyError::io("Failed to write tree ratio", e))?;
     writeln!(writer, "{}", bodies.len())
-        .map_err(|e| format!("Failed to write body count: {}", e))?;
+        .map_err(|e| NBodyError::io("Failed to write body count", e))?;
 
-    // Write body data
+    // Write body data, with a species column only if any body has one and
+    // color columns (after the species) only if any body is not white
+    let with_color = bodies.iter().any(|body| body.color != [1.0, 1.0, 1.0]);
+    let with_species = with_color || bodies.iter().any(|body| body.species != 0);
     for body in bodies {
-        writeln!(
+        write!(
             writer,
             "{:.16e} {:.16e} {:.16e} {:.16e} {:.16e}",
             body.mass,
@@ -127,24 +228,446 @@ pub fn write_bodies<P: AsRef<Path>>(
             body.position[1],
             body.velocity[0],
             body.velocity[1]
-        ).map_err(|e| format!("Failed to write body data: {}", e))?;
+        ).map_err(|e| NBodyError::io("Failed to write body data", e))?;
+        if with_species {
+            write!(writer, " {}", body.species)
+                .map_err(|e| NBodyError::io("Failed to write body data", e))?;
+        }
+        if with_color {
+            let [r, g, b] = body.color;
+            write!(writer, " {} {} {}", r, g, b)
+                .map_err(|e| NBodyError::io("Failed to write body data", e))?;
+        }
+        writeln!(writer).map_err(|e| NBodyError::io("Failed to write body data", e))?;
     }
 
     // Ensure all data is written
     writer.flush()
-        .map_err(|e| format!("Failed to flush file buffer: {}", e))?;
+        .map_err(|e| NBodyError::io("Failed to flush file buffer", e))?;
 
     Ok(())
 }
 
+/// Write body positions in XYZ format for VMD/OVITO-style viewers.
+///
+/// The file holds the body count, a comment line, then one `X x y z` line per
+/// body using a dummy element name. Bodies lie in the z = 0 plane.
+pub fn write_xyz<P: AsRef<Path>>(
+    path: P,
+    bodies: &[Body],
+    comment: &str,
+) -> Result<(), NBodyError> {
+    let mut writer = create_writer(path)?;
+
+    writeln!(writer, "{}", bodies.len())
+        .map_err(|e| NBodyError::io("Failed to write body count", e))?;
+    writeln!(writer, "{}", comment.replace('\n', " "))
+        .map_err(|e| NBodyError::io("Failed to write comment", e))?;
+
+    for body in bodies {
+        writeln!(
+            writer,
+            "X {:.16e} {:.16e} {:.16e}",
+            body.position[0],
+            body.position[1],
+            0.0
+        ).map_err(|e| NBodyError::io("Failed to write body data", e))?;
+    }
+
+    writer.flush()
+        .map_err(|e| NBodyError::io("Failed to flush file buffer", e))?;
+
+    Ok(())
+}
+
+/// Read the positions from an XYZ snapshot written by `write_xyz`.
+///
+/// XYZ files carry no masses or velocities, so every body gets unit mass
+/// and zero velocity; this is meant for replaying, not resuming.
+pub fn read_xyz<P: AsRef<Path>>(path: P) -> Result<Vec<Body>, NBodyError> {
+    let file = File::open(path)
+        .map_err(|e| NBodyError::io("Failed to open file", e))?;
+    let mut lines = BufReader::new(file).lines();
+
+    let n_bodies: usize = lines.next()
+        .ok_or_else(|| NBodyError::Parse("Missing number of bodies".to_string()))?
+        .map_err(|e| NBodyError::io("Failed to read number of bodies", e))?
+        .trim()
+        .parse()
+        .map_err(|e| NBodyError::Parse(format!("Invalid number of bodies format: {}", e)))?;
+    lines.next()
+        .ok_or_else(|| NBodyError::Parse("Missing comment line".to_string()))?
+        .map_err(|e| NBodyError::io("Failed to read comment", e))?;
+
+    let mut bodies = Vec::with_capacity(n_bodies);
+    for line in lines.take(n_bodies) {
+        let line = line.map_err(|e| NBodyError::io("Failed to read body data", e))?;
+        let columns: Vec<&str> = line.split_whitespace().collect();
+        if columns.len() != 4 {
+            return Err(NBodyError::InvalidBody(format!("expected an element and 3 coordinates, got {} values", columns.len())));
+        }
+
+        let coords: Vec<Scalar> = columns[1..3].iter()
+            .map(|s| s.parse::<Scalar>())
+            .collect::<Result<_, _>>()
+            .map_err(|e| NBodyError::Parse(format!("Invalid body data format: {}", e)))?;
+        bodies.push(Body::new(1.0, coords[0], coords[1], 0.0, 0.0));
+    }
+
+    if bodies.len() != n_bodies {
+        return Err(NBodyError::Parse(format!(
+            "Mismatch in body count: expected {}, got {}",
+            n_bodies,
+            bodies.len()
+        )));
+    }
+
+    Ok(bodies)
+}
+
+/// Write bodies as a legacy ASCII VTK polydata file for ParaView.
+///
+/// Each body is a vertex in the z = 0 plane carrying `mass` and `velocity`
+/// point data.
+pub fn write_vtk<P: AsRef<Path>>(
+    path: P,
+    bodies: &[Body],
+) -> Result<(), NBodyError> {
+    let mut writer = create_writer(path)?;
+    let n = bodies.len();
+
+    let write_err = |e| NBodyError::io("Failed to write VTK data", e);
+
+    writeln!(writer, "# vtk DataFile Version 3.0").map_err(write_err)?;
+    writeln!(writer, "nbody snapshot").map_err(write_err)?;
+    writeln!(writer, "ASCII").map_err(write_err)?;
+    writeln!(writer, "DATASET POLYDATA").map_err(write_err)?;
+
+    writeln!(writer, "POINTS {} double", n).map_err(write_err)?;
+    for body in bodies {
+        writeln!(writer, "{:.16e} {:.16e} 0", body.position[0], body.position[1])
+            .map_err(write_err)?;
+    }
+
+    writeln!(writer, "VERTICES {} {}", n, 2 * n).map_err(write_err)?;
+    for i in 0..n {
+        writeln!(writer, "1 {}", i).map_err(write_err)?;
+    }
+
+    writeln!(writer, "POINT_DATA {}", n).map_err(write_err)?;
+    writeln!(writer, "SCALARS mass double 1").map_err(write_err)?;
+    writeln!(writer, "LOOKUP_TABLE default").map_err(write_err)?;
+    for body in bodies {
+        writeln!(writer, "{:.16e}", body.mass).map_err(write_err)?;
+    }
+
+    writeln!(writer, "VECTORS velocity double").map_err(write_err)?;
+    for body in bodies {
+        writeln!(writer, "{:.16e} {:.16e} 0", body.velocity[0], body.velocity[1])
+            .map_err(write_err)?;
+    }
+
+    writer.flush()
+        .map_err(|e| NBodyError::io("Failed to flush file buffer", e))?;
+
+    Ok(())
+}
+
+/// Write bodies as a Tipsy binary in the big-endian "standard" layout read
+/// by pynbody, tipsy and other astrophysics tools.
+///
+/// The file is a 32-byte header followed by one 36-byte record per body,
+/// all of them as dark matter particles:
+///
+/// | Offset | Type       | Field                                  |
+/// |--------|------------|----------------------------------------|
+/// | 0      | `f64`      | time                                   |
+/// | 8      | `i32`      | total particles                        |
+/// | 12     | `i32`      | dimensions (always 3)                  |
+/// | 16     | `i32`      | gas particles (0)                      |
+/// | 20     | `i32`      | dark matter particles                  |
+/// | 24     | `i32`      | star particles (0)                     |
+/// | 28     | `i32`      | padding (0)                            |
+/// | 32     | `f32`      | mass of the first body                 |
+/// | 36     | `[f32; 3]` | position, with z = 0                   |
+/// | 48     | `[f32; 3]` | velocity, with vz = 0                  |
+/// | 60     | `f32`      | softening length `√softening`          |
+/// | 64     | `f32`      | potential (0, not computed)            |
+///
+/// and so on every 36 bytes. The format stores particle data in single
+/// precision, whatever `Scalar` is.
+// The time is cast to f64 and the particle data to f32, one of which is a
+// no-op cast for either `Scalar`
+#[allow(clippy::unnecessary_cast)]
+pub fn write_tipsy<P: AsRef<Path>>(
+    path: P,
+    bodies: &[Body],
+    time: Scalar,
+    softening: Scalar,
+) -> Result<(), NBodyError> {
+    let n = i32::try_from(bodies.len())
+        .map_err(|_| NBodyError::InvalidBody(format!("{} bodies is too many for a Tipsy file", bodies.len())))?;
+    let mut writer = create_writer(path)?;
+
+    let mut header = Vec::with_capacity(TIPSY_HEADER_BYTES);
+    header.extend_from_slice(&(time as f64).to_be_bytes());
+    for count in [n, 3, 0, n, 0, 0] {
+        header.extend_from_slice(&count.to_be_bytes());
+    }
+    writer.write_all(&header)
+        .map_err(|e| NBodyError::io("Failed to write Tipsy header", e))?;
+
+    let eps = softening.max(0.0).sqrt() as f32;
+    for body in bodies {
+        let fields = [
+            body.mass as f32,
+            body.position[0] as f32,
+            body.position[1] as f32,
+            0.0,
+            body.velocity[0] as f32,
+            body.velocity[1] as f32,
+            0.0,
+            eps,
+            0.0,
+        ];
+        let record: Vec<u8> = fields.iter().flat_map(|field| field.to_be_bytes()).collect();
+        writer.write_all(&record)
+            .map_err(|e| NBodyError::io("Failed to write Tipsy particle", e))?;
+    }
+
+    writer.flush()
+        .map_err(|e| NBodyError::io("Failed to flush file buffer", e))?;
+
+    Ok(())
+}
+
+/// Write the Graphviz DOT dump of a tree (see `QuadTree::to_dot`)
+pub fn write_dot<P: AsRef<Path>>(path: P, tree: &QuadTree) -> Result<(), NBodyError> {
+    let mut writer = create_writer(path)?;
+    writer.write_all(tree.to_dot().as_bytes())
+        .map_err(|e| NBodyError::io("Failed to write DOT data", e))?;
+    writer.flush()
+        .map_err(|e| NBodyError::io("Failed to flush file buffer", e))?;
+
+    Ok(())
+}
+
+/// Write a `resolution` x `resolution` grid of samples over `bounds` (see
+/// `Simulation::potential_grid`) as whitespace separated text.
+///
+/// Comment lines starting with `#` give the resolution and bounds, followed by
+/// one line per row of cell-centre samples, starting at `bounds.min[1]`. This
+/// loads directly with e.g. `numpy.loadtxt`.
+pub fn write_grid<P: AsRef<Path>>(
+    path: P,
+    values: &[Scalar],
+    resolution: usize,
+    bounds: &Bounds,
+) -> Result<(), NBodyError> {
+    let mut writer = create_writer(path)?;
+    let write_err = |e| NBodyError::io("Failed to write grid data", e);
+
+    writeln!(writer, "# resolution {}", resolution).map_err(write_err)?;
+    writeln!(
+        writer,
+        "# bounds {:.16e} {:.16e} {:.16e} {:.16e}",
+        bounds.min[0], bounds.min[1], bounds.max[0], bounds.max[1]
+    ).map_err(write_err)?;
+    for row in values.chunks(resolution.max(1)) {
+        let line: Vec<String> = row.iter().map(|v| format!("{:.16e}", v)).collect();
+        writeln!(writer, "{}", line.join(" ")).map_err(write_err)?;
+    }
+
+    writer.flush()
+        .map_err(|e| NBodyError::io("Failed to flush file buffer", e))?;
+
+    Ok(())
+}
+
+/// Write a speed histogram as text: a `#` comment header, then one
+/// `lower-edge count` line per bin
+pub fn write_speed_histogram<P: AsRef<Path>>(
+    path: P,
+    histogram: &[(Scalar, usize)],
+) -> Result<(), NBodyError> {
+    let mut writer = create_writer(path)?;
+    let write_err = |e| NBodyError::io("Failed to write speed histogram", e);
+
+    writeln!(writer, "# speed count").map_err(write_err)?;
+    for (speed, count) in histogram {
+        writeln!(writer, "{:.16e} {}", speed, count).map_err(write_err)?;
+    }
+
+    writer.flush()
+        .map_err(|e| NBodyError::io("Failed to flush file buffer", e))?;
+
+    Ok(())
+}
+
+/// A text table of quantities tracked over a run, written a row at a time:
+/// a `#` comment line naming the columns, then one whitespace-separated row
+/// per call to `write_row`
+pub struct TableWriter {
+    writer: BufWriter<File>,
+}
+
+impl TableWriter {
+    /// Create the file at `path` and write the column names
+    pub fn create<P: AsRef<Path>>(path: P, columns: &[String]) -> Result<Self, NBodyError> {
+        let mut writer = create_writer(path)?;
+        writeln!(writer, "# {}", columns.join(" "))
+            .map_err(|e| NBodyError::io("Failed to write table header", e))?;
+        Ok(TableWriter { writer })
+    }
+
+    /// Append a row, flushed straight away so the table can be followed
+    /// while the run goes on
+    pub fn write_row(&mut self, values: &[Scalar]) -> Result<(), NBodyError> {
+        let row: Vec<String> = values.iter().map(|v| format!("{:.16e}", v)).collect();
+        writeln!(self.writer, "{}", row.join(" "))
+            .map_err(|e| NBodyError::io("Failed to write table row", e))?;
+        self.writer.flush()
+            .map_err(|e| NBodyError::io("Failed to flush file buffer", e))
+    }
+}
+
+/// Write summary statistics of a run as a pretty-printed JSON object:
+/// the body count, step and time, total mass, centre of mass, kinetic,
+/// potential and total energy, the bound fraction, momentum, angular momentum
+/// about the origin, the extents of the bodies and the wall-clock seconds the
+/// run took.
+///
+/// Vectors are `[x, y]` arrays and values that are not finite are `null`.
+pub fn write_stats<P: AsRef<Path>>(
+    path: P,
+    simulation: &Simulation,
+    wall_clock_seconds: f64,
+) -> Result<(), NBodyError> {
+    let (bounds_min, bounds_max) = simulation.extents();
+    let kinetic_energy = simulation.kinetic_energy();
+    let potential_energy = simulation.potential_energy();
+    let stats = Stats {
+        n_bodies: simulation.bodies().len(),
+        step: simulation.step_count(),
+        time: simulation.time(),
+        total_mass: simulation.total_mass(),
+        center_of_mass: simulation.center_of_mass(),
+        kinetic_energy,
+        potential_energy,
+        total_energy: kinetic_energy + potential_energy,
+        bound_fraction: simulation.bound_fraction(),
+        momentum: simulation.momentum(),
+        angular_momentum: simulation.angular_momentum(),
+        bounds_min,
+        bounds_max,
+        wall_clock_seconds,
+    };
+
+    let mut writer = create_writer(path)?;
+    serde_json::to_writer_pretty(&mut writer, &stats)
+        .map_err(|e| NBodyError::io("Failed to write statistics", e.into()))?;
+    writeln!(writer).map_err(|e| NBodyError::io("Failed to write statistics", e))?;
+
+    writer.flush()
+        .map_err(|e| NBodyError::io("Failed to flush file buffer", e))?;
+
+    Ok(())
+}
+
+/// The summary `write_stats` writes
+#[derive(Serialize)]
+struct Stats {
+    n_bodies: usize,
+    step: usize,
+    time: Scalar,
+    total_mass: Scalar,
+    center_of_mass: [Scalar; 2],
+    kinetic_energy: Scalar,
+    potential_energy: Scalar,
+    total_energy: Scalar,
+    bound_fraction: Scalar,
+    momentum: [Scalar; 2],
+    angular_momentum: Scalar,
+    bounds_min: [Scalar; 2],
+    bounds_max: [Scalar; 2],
+    wall_clock_seconds: f64,
+}
+
+/// Write an RGBA image, rows top to bottom, as an 8-bit PNG
+pub fn write_png<P: AsRef<Path>>(
+    path: P,
+    width: u32,
+    height: u32,
+    rgba: &[u8],
+) -> Result<(), NBodyError> {
+    let writer = create_writer(path)?;
+    let mut encoder = png::Encoder::new(writer, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    encoder.write_header()
+        .and_then(|mut png_writer| {
+            png_writer.write_image_data(rgba)?;
+            png_writer.finish()
+        })
+        .map_err(|e| NBodyError::io("Failed to write PNG data", std::io::Error::other(e)))
+}
+
+/// Read a PNG image as `(width, height, rgba)`, rows top to bottom, with
+/// grayscale, RGB, palette and 16-bit images converted to 8-bit RGBA
+pub fn read_png<P: AsRef<Path>>(path: P) -> Result<(u32, u32, Vec<u8>), NBodyError> {
+    let file = File::open(path)
+        .map_err(|e| NBodyError::io("Failed to open file", e))?;
+    let png_err = |e| NBodyError::io("Failed to read PNG data", std::io::Error::other(e));
+
+    let mut decoder = png::Decoder::new(BufReader::new(file));
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(png_err)?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).map_err(png_err)?;
+    let pixels = &buffer[..info.buffer_size()];
+
+    let rgba = match info.color_type {
+        png::ColorType::Rgba => pixels.to_vec(),
+        png::ColorType::Rgb => pixels.chunks(3).flat_map(|p| [p[0], p[1], p[2], 255]).collect(),
+        png::ColorType::GrayscaleAlpha => pixels.chunks(2).flat_map(|p| [p[0], p[0], p[0], p[1]]).collect(),
+        png::ColorType::Grayscale => pixels.iter().flat_map(|&v| [v, v, v, 255]).collect(),
+        png::ColorType::Indexed => {
+            return Err(NBodyError::Parse("Unsupported PNG color type".to_string()));
+        }
+    };
+
+    Ok((info.width, info.height, rgba))
+}
+
+/// Whether `path` is the `-` that stands for stdin or stdout
+pub fn is_stdio(path: &Path) -> bool {
+    path.as_os_str() == STDIO_PATH
+}
+
+/// Create a buffered writer for `path`, creating parent directories as needed
+fn create_writer<P: AsRef<Path>>(path: P) -> Result<BufWriter<File>, NBodyError> {
+    // Create parent directories if they don't exist
+    if let Some(parent) = path.as_ref().parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| NBodyError::io("Failed to create directory structure", e))?;
+    }
+
+    // Open file with proper error handling
+    let file = File::create(path)
+        .map_err(|e| NBodyError::io("Failed to create file", e))?;
+    Ok(BufWriter::new(file))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::fs;
+    use std::io::Cursor;
     use tempfile::tempdir;
 
     #[test]
-    fn test_write_and_read_bodies() -> Result<(), String> {
+    fn test_write_and_read_bodies() -> Result<(), Box<dyn std::error::Error>> {
         // Create a temporary directory for the test
         let dir = tempdir().map_err(|e| format!("Failed to create temp dir: {}", e))?;
         let file_path = dir.path().join("test_bodies.dat");
@@ -188,14 +711,96 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_species_round_trip() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempdir().map_err(|e| format!("Failed to create temp dir: {}", e))?;
+        let file_path = dir.path().join("species.dat");
+
+        let original_bodies = vec![
+            Body::new(1.0, 0.0, 0.0, 0.0, 0.0),
+            Body::new(2.0, 1.0, 1.0, -0.1, 0.1).with_species(1),
+            Body::new(3.0, -1.0, 2.0, 0.2, 0.0).with_species(255),
+        ];
+        write_bodies(&file_path, &original_bodies, 0.1, 1.0, 0.001, 0.5)?;
+
+        let contents = fs::read_to_string(&file_path)
+            .map_err(|e| format!("Failed to read test file: {}", e))?;
+        assert!(contents.lines().nth(6).unwrap().ends_with(" 1"));
+
+        let species: Vec<u8> = read_bodies(&file_path)?.iter().map(|body| body.species).collect();
+        assert_eq!(species, vec![0, 1, 255]);
+
+        // Files without the column read as species 0
+        write_bodies(&file_path, &original_bodies[..1], 0.1, 1.0, 0.001, 0.5)?;
+        let contents = fs::read_to_string(&file_path)
+            .map_err(|e| format!("Failed to read test file: {}", e))?;
+        assert_eq!(contents.lines().nth(5).unwrap().split_whitespace().count(), 5);
+        assert_eq!(read_bodies(&file_path)?[0].species, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_color_round_trip() -> Result<(), Box<dyn std::error::Error>> {
+        let bodies = vec![
+            Body::new(1.0, 0.0, 0.0, 0.0, 0.0),
+            Body::new(2.0, 1.0, 1.0, -0.1, 0.1).with_color([0.1, 0.25, 1.0 / 3.0]).with_species(2),
+        ];
+        let mut buffer = Vec::new();
+        write_bodies_to(&mut buffer, &bodies, 0.1, 1.0, 0.001, 0.5)?;
+
+        let read = read_bodies_from(Cursor::new(buffer))?;
+        assert_eq!(read[0].color, [1.0, 1.0, 1.0]);
+        assert_eq!(read[1].color, [0.1, 0.25, 1.0 / 3.0]);
+        assert_eq!(read[1].species, 2);
+
+        // Through a file, as `--output` writes it and `--resume` reads it
+        let dir = tempdir()?;
+        let path = dir.path().join("colors.dat");
+        write_bodies(&path, &bodies, 0.1, 1.0, 0.001, 0.5)?;
+        assert_eq!(read_bodies(&path)?, bodies);
+
+        // All-white bodies keep the shorter format
+        let mut buffer = Vec::new();
+        write_bodies_to(&mut buffer, &bodies[..1], 0.1, 1.0, 0.001, 0.5)?;
+        assert_eq!(String::from_utf8(buffer)?.lines().last().unwrap().split_whitespace().count(), 5);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_and_write_in_memory() -> Result<(), Box<dyn std::error::Error>> {
+        let bodies = vec![
+            Body::new(1.0, 0.0, 0.0, 0.0, 0.0),
+            Body::new(2.0, 1.0, -1.0, 0.5, 0.1),
+        ];
+
+        // Two snapshots back to back, as `--output -` streams them
+        let mut buffer = Vec::new();
+        write_bodies_to(&mut buffer, &bodies, 0.1, 1.0, 0.001, 0.5)?;
+        write_bodies_to(&mut buffer, &bodies[..1], 0.1, 1.0, 0.001, 0.5)?;
+
+        let mut reader = Cursor::new(buffer);
+        let first = read_bodies_from(&mut reader)?;
+        assert_eq!(first.len(), 2);
+        assert_eq!(first[1].position, [1.0, -1.0]);
+        assert_eq!(first[1].velocity, [0.5, 0.1]);
+        assert_eq!(read_bodies_from(&mut reader)?.len(), 1);
+
+        assert!(is_stdio(Path::new("-")));
+        assert!(!is_stdio(Path::new("./-")));
+
+        Ok(())
+    }
+
     #[test]
     fn test_invalid_file() {
         let result = read_bodies("nonexistent_file.dat");
-        assert!(result.is_err());
+        assert!(matches!(result, Err(NBodyError::Io { .. })));
     }
 
     #[test]
-    fn test_malformed_file() -> Result<(), String> {
+    fn test_malformed_file() -> Result<(), Box<dyn std::error::Error>> {
         let dir = tempdir().map_err(|e| format!("Failed to create temp dir: {}", e))?;
         let file_path = dir.path().join("malformed.dat");
 
@@ -204,10 +809,261 @@ mod tests {
             .map_err(|e| format!("Failed to write test file: {}", e))?;
 
         let result = read_bodies(&file_path);
-        assert!(result.is_err());
+        assert!(matches!(result, Err(NBodyError::Parse(_))));
 
         dir.close().map_err(|e| format!("Failed to clean up temp dir: {}", e))?;
         
         Ok(())
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_write_xyz() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempdir().map_err(|e| format!("Failed to create temp dir: {}", e))?;
+        let file_path = dir.path().join("snapshots").join("step.xyz");
+
+        let bodies = vec![
+            Body::new(1.0, 0.25, -1.5, 0.0, 0.0),
+            Body::new(2.0, 3.0, 4.0e-3, 0.0, 0.0),
+        ];
+        write_xyz(&file_path, &bodies, "t=1.5")?;
+
+        let contents = fs::read_to_string(&file_path)
+            .map_err(|e| format!("Failed to read test file: {}", e))?;
+        let mut lines = contents.lines();
+        assert_eq!(lines.next(), Some("2"));
+        assert_eq!(lines.next(), Some("t=1.5"));
+
+        for body in &bodies {
+            let line = lines.next().ok_or("Missing body line")?;
+            let mut fields = line.split_whitespace();
+            assert_eq!(fields.next(), Some("X"));
+            let coords: Vec<Scalar> = fields
+                .map(|v| v.parse::<Scalar>().map_err(|e| e.to_string()))
+                .collect::<Result<_, _>>()?;
+            assert_eq!(coords, vec![body.position[0], body.position[1], 0.0]);
+        }
+        assert!(lines.next().is_none());
+
+        let positions: Vec<[Scalar; 2]> = read_xyz(&file_path)?.iter().map(|b| b.position).collect();
+        assert_eq!(positions, vec![bodies[0].position, bodies[1].position]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_vtk() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempdir().map_err(|e| format!("Failed to create temp dir: {}", e))?;
+        let file_path = dir.path().join("step.vtk");
+
+        let bodies = vec![
+            Body::new(1.0, 0.5, 0.5, 1.0, 0.0),
+            Body::new(2.0, -0.5, 0.5, 0.0, -1.0),
+            Body::new(3.0, 0.0, -0.5, 0.0, 0.0),
+        ];
+        write_vtk(&file_path, &bodies)?;
+
+        let contents = fs::read_to_string(&file_path)
+            .map_err(|e| format!("Failed to read test file: {}", e))?;
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines[0], "# vtk DataFile Version 3.0");
+        assert_eq!(lines[4], "POINTS 3 double");
+
+        let point: Vec<Scalar> = lines[6]
+            .split_whitespace()
+            .map(|v| v.parse::<Scalar>().map_err(|e| e.to_string()))
+            .collect::<Result<_, _>>()?;
+        assert_eq!(point, vec![-0.5, 0.5, 0.0]);
+        assert!(lines.contains(&"VERTICES 3 6"));
+        assert!(lines.contains(&"POINT_DATA 3"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_tipsy() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempdir().map_err(|e| format!("Failed to create temp dir: {}", e))?;
+        let file_path = dir.path().join("snapshot.tipsy");
+
+        let bodies = vec![
+            Body::new(1.5, 0.5, -0.25, 1.0, -2.0),
+            Body::new(2.0, -0.5, 0.5, 0.0, 0.0),
+        ];
+        write_tipsy(&file_path, &bodies, 3.5, 0.25)?;
+
+        let bytes = fs::read(&file_path)?;
+        assert_eq!(bytes.len(), TIPSY_HEADER_BYTES + 2 * 36);
+
+        let int = |offset: usize| i32::from_be_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        let float = |offset: usize| f32::from_be_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        assert_eq!(f64::from_be_bytes(bytes[..8].try_into()?), 3.5);
+        assert_eq!([int(8), int(12), int(16), int(20), int(24), int(28)], [2, 3, 0, 2, 0, 0]);
+
+        let first: Vec<f32> = (0..9).map(|i| float(TIPSY_HEADER_BYTES + 4 * i)).collect();
+        assert_eq!(first, [1.5, 0.5, -0.25, 0.0, 1.0, -2.0, 0.0, 0.5, 0.0]);
+        assert_eq!(float(TIPSY_HEADER_BYTES + 36), 2.0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_png() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempdir().map_err(|e| format!("Failed to create temp dir: {}", e))?;
+        let file_path = dir.path().join("frames").join("frame_00001.png");
+
+        let rgba: Vec<u8> = (0..3 * 2 * 4).map(|i| i as u8).collect();
+        write_png(&file_path, 3, 2, &rgba)?;
+
+        let file = File::open(&file_path).map_err(|e| format!("Failed to open PNG: {}", e))?;
+        let mut reader = png::Decoder::new(file).read_info().map_err(|e| e.to_string())?;
+        let mut decoded = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut decoded).map_err(|e| e.to_string())?;
+        assert_eq!((info.width, info.height), (3, 2));
+        assert_eq!(info.color_type, png::ColorType::Rgba);
+        assert_eq!(&decoded[..info.buffer_size()], &rgba[..]);
+        assert_eq!(read_png(&file_path)?, (3, 2, rgba));
+
+        // RGB images gain an opaque alpha channel
+        let rgb_path = dir.path().join("rgb.png");
+        let mut encoder = png::Encoder::new(File::create(&rgb_path)?, 2, 1);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.write_header()?.write_image_data(&[1, 2, 3, 4, 5, 6])?;
+        assert_eq!(read_png(&rgb_path)?, (2, 1, vec![1, 2, 3, 255, 4, 5, 6, 255]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_version_header_and_column_checks() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempdir()?;
+        let file_path = dir.path().join("snapshot.dat");
+        let header = "0.1\n1.0\n0.001\n0.5\n2\n";
+        let read = |contents: &str| {
+            fs::write(&file_path, contents).unwrap();
+            read_bodies(&file_path)
+        };
+
+        // With and without the version line
+        let bodies = read(&format!("# nbody 1 2d\n{}1 0 0 0 0\n2 1 1 0 0\n", header))?;
+        assert_eq!(bodies.len(), 2);
+        assert_eq!(bodies[1].mass, 2.0);
+        assert_eq!(read(&format!("{}1 0 0 0 0 3\n2 1 1 0 0 4\n", header))?[1].species, 4);
+
+        assert!(matches!(read(&format!("# nbody 1 3d\n{}", header)), Err(NBodyError::Parse(_))));
+        assert!(matches!(read(&format!("# nbody 9 2d\n{}", header)), Err(NBodyError::Parse(_))));
+
+        // 3D bodies and mixed column counts
+        let err = read(&format!("{}1 0 0 0 0 0 0\n2 1 1 0 0 0 0\n", header)).unwrap_err();
+        assert!(err.to_string().contains("3D"), "{}", err);
+        let err = read(&format!("{}1 0 0 0 0\n2 1 1 0 0 1\n", header)).unwrap_err();
+        assert!(matches!(err, NBodyError::InvalidBody(_)));
+        assert_eq!(err.to_string(), "Invalid body data: body 2 has 6 values but the first body has 5");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_stats() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempdir()?;
+        let file_path = dir.path().join("stats.json");
+
+        let bodies = vec![
+            Body::new(2.0, -1.0, 0.5, 0.0, 0.5),
+            Body::new(1.0, 2.0, -0.5, 0.0, -1.0),
+        ];
+        let simulation = Simulation::new(bodies, 0.1, 1.0, 0.01, 0.5);
+        write_stats(&file_path, &simulation, 1.25)?;
+
+        let contents = fs::read_to_string(&file_path)?;
+        let stats: serde_json::Value = serde_json::from_str(&contents)?;
+        let fields = stats.as_object().unwrap();
+
+        let expected_keys = [
+            "n_bodies", "step", "time", "total_mass", "center_of_mass", "kinetic_energy",
+            "potential_energy", "total_energy", "bound_fraction", "momentum", "angular_momentum",
+            "bounds_min", "bounds_max", "wall_clock_seconds",
+        ];
+        assert_eq!(fields.len(), expected_keys.len());
+        for key in expected_keys {
+            assert!(fields.contains_key(key), "missing {}", key);
+        }
+        let number = |key: &str| fields[key].as_f64().unwrap();
+        let vector = |key: &str| [fields[key][0].as_f64().unwrap(), fields[key][1].as_f64().unwrap()];
+        assert_eq!(fields["n_bodies"], 2);
+        assert_eq!(fields["step"], 0);
+        assert_eq!(number("total_mass"), 3.0);
+        assert_eq!(vector("center_of_mass")[0], 0.0);
+        assert!((vector("center_of_mass")[1] - 1.0 / 6.0).abs() < 1e-6);
+        assert_eq!(vector("momentum"), [0.0, 0.0]);
+        assert_eq!(vector("bounds_min"), [-1.0, -0.5]);
+        assert_eq!(vector("bounds_max"), [2.0, 0.5]);
+        assert_eq!(number("wall_clock_seconds"), 1.25);
+        assert_eq!(number("bound_fraction"), 1.0);
+        let total = number("kinetic_energy") + number("potential_energy");
+        assert!((number("total_energy") - total).abs() < 1e-6 * total.abs());
+
+        // Without bodies the extents are infinite, which JSON writes as null
+        let empty = Simulation::new(Vec::new(), 0.1, 1.0, 0.01, 0.5);
+        write_stats(&file_path, &empty, 0.0)?;
+        let stats: serde_json::Value = serde_json::from_str(&fs::read_to_string(&file_path)?)?;
+        assert!(stats["bounds_min"][0].is_null());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_grid() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempdir()?;
+        let file_path = dir.path().join("potential.txt");
+
+        let values: Vec<Scalar> = (0..4).map(|i| -(i as Scalar)).collect();
+        write_grid(&file_path, &values, 2, &Bounds::new([-1.0, -2.0], [1.0, 2.0]))?;
+
+        let contents = fs::read_to_string(&file_path)?;
+        let rows: Vec<Vec<Scalar>> = contents
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .map(|line| line.split_whitespace().map(|v| v.parse().unwrap()).collect())
+            .collect();
+        assert_eq!(rows, vec![vec![0.0, -1.0], vec![-2.0, -3.0]]);
+        assert!(contents.starts_with("# resolution 2\n# bounds -1."));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_writer() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempdir()?;
+        let file_path = dir.path().join("radii.txt");
+
+        let mut table = TableWriter::create(&file_path, &["time".to_string(), "r50".to_string()])?;
+        table.write_row(&[0.0, 1.5])?;
+        // Rows are on disk before the writer is dropped
+        table.write_row(&[0.25, 1.0])?;
+        let contents = fs::read_to_string(&file_path)?;
+        drop(table);
+
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines[0], "# time r50");
+        let rows: Vec<Vec<Scalar>> = lines[1..]
+            .iter()
+            .map(|line| line.split_whitespace().map(|v| v.parse().unwrap()).collect())
+            .collect();
+        assert_eq!(rows, vec![vec![0.0, 1.5], vec![0.25, 1.0]]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_speed_histogram() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempdir()?;
+        let file_path = dir.path().join("speeds.txt");
+
+        write_speed_histogram(&file_path, &[(0.0, 3), (0.5, 1)])?;
+
+        let contents = fs::read_to_string(&file_path)?;
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines, ["# speed count", "0.0000000000000000e0 3", "5.0000000000000000e-1 1"]);
+
+        Ok(())
+    }
+}
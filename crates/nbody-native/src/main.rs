@@ -1,11 +1,16 @@
 // crates/nbody-native/src/main.rs
-use clap::Parser;
+// Conversions and constants written for f64 become no-op casts or are rounded
+// when Scalar is f32
+#![cfg_attr(feature = "single-precision", allow(clippy::unnecessary_cast, clippy::excessive_precision))]
+
+use clap::{Parser, ValueEnum};
+use glow::HasContext;
 use rand::prelude::*;
 use std::path::PathBuf;
 use std::time::{Duration, Instant};
 use std::num::NonZeroU32;
 use winit::{
-    event::{Event, WindowEvent},
+    event::{ElementState, Event, KeyboardInput, MouseButton, VirtualKeyCode, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
     window::{WindowBuilder, Window},
     dpi::LogicalSize,
@@ -22,20 +27,40 @@ use raw_window_handle::HasRawWindowHandle;
 use std::sync::Arc;
 
 mod fileio;
+mod replay;
 
-use nbody_core::{Body, Simulation, Renderer};
+use replay::Replay;
+use nbody_core::initial_conditions::{MassFunction, Shape};
+use nbody_core::{initial_conditions, presets, Body, ColorMode, ForceParams, Integrator, NBodyError, OpeningCriterion, QuadTree, Simulation, Renderer, Scalar, Sprite};
 
 const DEFAULT_BODIES: usize = 1000;
-const DEFAULT_MASS: f64 = 2000.0;
-const DEFAULT_G: f64 = 6.67384e-11;
-const DEFAULT_TIMESTEP: f64 = 0.1;
-const DEFAULT_SOFTENING: f64 = 0.005;
-const DEFAULT_SPIN: f64 = 0.05;
-const DEFAULT_MZERO: f64 = 1.0e7;
-const DEFAULT_TREE_RATIO: f64 = 3.0;
+const DEFAULT_MASS: Scalar = 2000.0;
+const DEFAULT_G: Scalar = 6.67384e-11;
+const DEFAULT_TIMESTEP: Scalar = 0.1;
+const DEFAULT_SOFTENING: Scalar = 0.005;
+const DEFAULT_SPIN: Scalar = 0.05;
+const DEFAULT_MZERO: Scalar = 1.0e7;
+/// Radius of the ring that multiple central bodies orbit on
+const CENTRAL_RING_RADIUS: Scalar = 0.05;
+const DEFAULT_TREE_RATIO: Scalar = 3.0;
 const DEFAULT_WRITE_INTERVAL: usize = 100;
-const FRAME_TIME: Duration = Duration::from_micros(66666); // Approximately 30 FPS
-const PI: f64 = std::f32::consts::PI as f64;
+const DEFAULT_FPS: u32 = 15;
+
+/// Mass fractions whose Lagrangian radii --lagrangian tracks
+const LAGRANGIAN_FRACTIONS: [Scalar; 3] = [0.1, 0.5, 0.9];
+
+/// Width and height in pixels of the generated --sprites glow texture
+const SPRITE_SIZE: u32 = 64;
+
+/// Factor the + and - keys scale the point size by, and the size - stops at
+const POINT_SIZE_STEP: f32 = 1.25;
+const MIN_POINT_SIZE: f32 = 0.5;
+
+/// Body counts, tree ratios and sample size of --accuracy-sweep. The largest
+/// N keeps the direct sums over the sample to a few seconds.
+const SWEEP_BODIES: [usize; 3] = [1_000, 10_000, 100_000];
+const SWEEP_TREE_RATIOS: [Scalar; 6] = [0.25, 0.5, 1.0, 1.5, 2.0, 3.0];
+const SWEEP_SAMPLE: usize = 256;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -46,37 +71,97 @@ struct Config {
 
     /// Mass for randomly distributed bodies
     #[arg(short = 'm', long, default_value_t = DEFAULT_MASS)]
-    mass: f64,
+    mass: Scalar,
 
     /// Gravitational constant
     #[arg(short = 'g', long, default_value_t = DEFAULT_G)]
-    g: f64,
+    g: Scalar,
 
     /// Simulation timestep
     #[arg(short = 'd', long = "dt", default_value_t = DEFAULT_TIMESTEP)]
-    timestep: f64,
+    timestep: Scalar,
 
     /// Softening factor to prevent singularities
     #[arg(short = 'f', long = "sf", default_value_t = DEFAULT_SOFTENING)]
-    softening: f64,
+    softening: Scalar,
+
+    /// Scale each body's softening by the square of its 8th-neighbour distance over the mean, so dense regions get less
+    #[arg(long)]
+    adaptive_softening: bool,
 
     /// Initial spin factor for random distribution
     #[arg(short = 's', long, default_value_t = DEFAULT_SPIN)]
-    spin: f64,
+    spin: Scalar,
 
     /// Mass of central body
     #[arg(long = "mz", default_value_t = DEFAULT_MZERO)]
-    mzero: f64,
+    mzero: Scalar,
+
+    /// Number of central bodies sharing the central mass (2 = binary)
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    central_bodies: u32,
 
     /// Tree ratio threshold for Barnes-Hut approximation
     #[arg(short = 't', long = "tr", default_value_t = DEFAULT_TREE_RATIO)]
-    tree_ratio: f64,
+    tree_ratio: Scalar,
+
+    /// Node size and distance the tree ratio is compared against
+    #[arg(long, value_enum, default_value_t = Opening::Diagonal)]
+    opening: Opening,
+
+    /// Print the mean number of tree nodes opened per force evaluation when the run stops
+    #[arg(long)]
+    report_opens: bool,
+
+    /// Update the tree in place between steps instead of rebuilding it (experimental)
+    #[arg(long)]
+    incremental_tree: bool,
 
-    /// Input file to resume simulation from
+    /// Rebuild the tree only every K force evaluations, refitting its masses in between (approximate)
+    #[arg(long, value_name = "K", default_value_t = 1)]
+    tree_rebuild_interval: usize,
+
+    /// Stop subdividing the tree below this depth, bounding its memory on clustered inputs (leaves at the cap keep all their bodies)
+    #[arg(long, value_name = "DEPTH")]
+    max_tree_depth: Option<usize>,
+
+    /// Sum forces with compensated (Kahan) summation: more accurate where large forces cancel, but slower
+    #[arg(long)]
+    compensated_sum: bool,
+
+    /// Integration scheme (yoshida4 evaluates forces three times per step)
+    #[arg(long, value_enum, default_value_t = IntegratorArg::Euler)]
+    integrator: IntegratorArg,
+
+    /// Number of timestep rungs of the block integrator, each halving the one before
+    #[arg(long, value_name = "LEVELS", default_value_t = 4, value_parser = clap::value_parser!(u32).range(1..=16))]
+    block_levels: u32,
+
+    /// Bodies go on the coarsest block rung with |a| h² at most this length [default: 0.05 √sf]
+    #[arg(long, value_name = "LENGTH")]
+    block_accuracy: Option<Scalar>,
+
+    /// Damp velocities by v -> v (1 - D dt) each step to dissipate energy (non-conservative, 0 = off)
+    #[arg(long, value_name = "D", default_value_t = 0.0)]
+    drag: Scalar,
+
+    /// Turn off the gravity between bodies, leaving them as test particles that skip the force calculation and coast
+    #[arg(long)]
+    no_self_gravity: bool,
+
+    /// Input file to resume simulation from (- for stdin)
     #[arg(short = 'r', long = "resume")]
     input_file: Option<PathBuf>,
 
-    /// Output file to save simulation state
+    /// Play back the snapshot files in DIR (XYZ or --output state files) instead of simulating
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["no_graphics", "input_file", "preset"])]
+    replay: Option<PathBuf>,
+
+    /// Add N test particles (of --mass each) on circular orbits over the resumed system
+    #[arg(long, value_name = "N", requires = "input_file")]
+    add_bodies: Option<usize>,
+
+    /// Output file to save simulation state (- for stdout, which implies --quiet)
     #[arg(short = 'o', long = "output")]
     output_file: Option<PathBuf>,
 
@@ -84,6 +169,86 @@ struct Config {
     #[arg(long = "nsteps", default_value_t = DEFAULT_WRITE_INTERVAL)]
     write_interval: usize,
 
+    /// Write final statistics (energies, momenta, bounds, wall-clock time) as JSON to this file when the run stops
+    #[arg(long, value_name = "PATH")]
+    stats_out: Option<PathBuf>,
+
+    /// Append the radii enclosing 10%, 50% and 90% of the mass (about the centre of mass) to this file every output interval
+    #[arg(long, value_name = "PATH", conflicts_with = "replay")]
+    lagrangian: Option<PathBuf>,
+
+    /// Directory to write XYZ snapshots to, one file per output interval
+    #[arg(long = "xyz")]
+    xyz_dir: Option<PathBuf>,
+
+    /// Directory to write legacy VTK snapshots to, one file per output interval
+    #[arg(long = "vtk")]
+    vtk_dir: Option<PathBuf>,
+
+    /// Directory to write Tipsy binary snapshots to (standard big-endian layout), one file per output interval
+    #[arg(long = "tipsy")]
+    tipsy_dir: Option<PathBuf>,
+
+    /// Stop after this many steps
+    #[arg(long)]
+    steps: Option<usize>,
+
+    /// Stop after this many seconds of wall-clock time
+    #[arg(long, value_name = "SECONDS")]
+    max_time: Option<f64>,
+
+    /// Stop once the simulation time reaches this value
+    #[arg(long, value_name = "T")]
+    sim_time: Option<Scalar>,
+
+    /// Write the Barnes-Hut tree as a Graphviz DOT file at --dump-step
+    #[arg(long = "dump-tree")]
+    dump_tree: Option<PathBuf>,
+
+    /// Write the gravitational potential sampled on a grid over the bodies at --dump-step
+    #[arg(long, value_name = "PATH")]
+    potential_grid: Option<PathBuf>,
+
+    /// Number of --potential-grid samples along each axis
+    #[arg(long, default_value_t = 128)]
+    potential_resolution: usize,
+
+    /// Write a histogram of body speeds at --dump-step, one "lower-edge count" line per bin
+    #[arg(long, value_name = "PATH")]
+    speed_hist: Option<PathBuf>,
+
+    /// Number of --speed-hist bins
+    #[arg(long, default_value_t = 50, value_parser = clap::value_parser!(u32).range(1..))]
+    speed_bins: u32,
+
+    /// Step at which --dump-tree, --potential-grid and --speed-hist write (0 = initial state)
+    #[arg(long, default_value_t = 0)]
+    dump_step: usize,
+
+    /// Directory to write each rendered frame to as frame_00001.png, frame_00002.png, ...
+    #[arg(long = "record", value_name = "DIR")]
+    record_dir: Option<PathBuf>,
+
+    /// Record only every Nth rendered frame
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    record_every: u32,
+
+    /// Suppress the configuration summary and status line (warnings and errors are still logged)
+    #[arg(short = 'q', long)]
+    quiet: bool,
+
+    /// Print the estimated memory needed for --n-bodies bodies and exit without simulating
+    #[arg(long)]
+    estimate_memory: bool,
+
+    /// Print the OpenGL vendor, renderer and versions and the MSAA sample count, then exit
+    #[arg(long)]
+    gl_info: bool,
+
+    /// Print a CSV table of Barnes-Hut force error and time against direct summation for several N and tree ratios, then exit
+    #[arg(long, conflicts_with_all = ["input_file", "preset"])]
+    accuracy_sweep: bool,
+
     /// Disable graphics
     #[arg(long = "no-graphics")]
     no_graphics: bool,
@@ -96,6 +261,14 @@ struct Config {
     #[arg(long, default_value_t = 800)]
     height: u32,
 
+    /// Render frame rate cap (0 = uncapped)
+    #[arg(long, default_value_t = DEFAULT_FPS)]
+    fps: u32,
+
+    /// Show the body count, simulation time and frame rate in the window title, updated every second
+    #[arg(long, conflicts_with = "no_graphics")]
+    title_stats: bool,
+
     /// Point size for rendering bodies
     #[arg(short = 'p', long, default_value_t = 2.0)]
     point_size: f32,
@@ -103,6 +276,296 @@ struct Config {
     /// Use fixed scale view instead of following particles
     #[arg(long)]
     fixed_scale: bool,
+
+    /// Multisample antialiasing samples (power of two, 0 = off)
+    #[arg(long, default_value_t = 0, value_parser = parse_msaa)]
+    msaa: u8,
+
+    /// Add a glow (bloom) post-process to the rendered bodies
+    #[arg(long)]
+    bloom: bool,
+
+    /// Strength of the bloom glow
+    #[arg(long, default_value_t = 1.0)]
+    bloom_strength: f32,
+
+    /// Draw bodies as additively blended star glow sprites instead of flat points
+    #[arg(long)]
+    sprites: bool,
+
+    /// Square PNG image to draw bodies with in place of the generated glow (implies --sprites)
+    #[arg(long, value_name = "PNG")]
+    sprite_image: Option<PathBuf>,
+
+    /// Draw at most M bodies (a fixed evenly spaced subset); all bodies are still simulated
+    #[arg(long, value_name = "M")]
+    render_sample: Option<usize>,
+
+    /// Width in pixels of the tree wireframe lines
+    #[arg(long, default_value_t = 1.0)]
+    wire_width: f32,
+
+    /// Draw only the tree nodes holding at least this fraction of the total mass ([ and ] adjust it live)
+    #[arg(long, value_name = "FRACTION", default_value_t = 0.0)]
+    wire_min_mass: f32,
+
+    /// Draw the tree wireframe only down to this depth, from a shallow tree that is cheaper to build
+    #[arg(long, value_name = "DEPTH")]
+    wire_depth: Option<usize>,
+
+    /// Tint tree node boxes by their total mass
+    #[arg(long)]
+    tree_mass_color: bool,
+
+    /// Color bodies by their species id
+    #[arg(long)]
+    color_species: bool,
+
+    /// Color bodies by their own r g b columns in the --resume file
+    #[arg(long, conflicts_with = "color_species")]
+    color_body: bool,
+
+    /// Slowly cycle the background hue, once every PERIOD units of simulation time
+    #[arg(long, value_name = "PERIOD")]
+    time_tint: Option<f32>,
+
+    /// Draw each body's velocity as a line of length speed * SCALE (world units)
+    #[arg(long, value_name = "SCALE")]
+    velocity_arrows: Option<f32>,
+
+    /// Left-click spawns a body of MASS at the cursor instead of selecting one; drag back and release to fling it
+    #[arg(long, value_name = "MASS", conflicts_with_all = ["no_graphics", "replay"])]
+    spawn_mass: Option<Scalar>,
+
+    /// Launch velocity of a spawned body per world unit dragged
+    #[arg(long, default_value_t = 0.1)]
+    spawn_speed: Scalar,
+
+    /// Radius around the cursor within which the K key kicks bodies
+    #[arg(long, default_value_t = 1.0)]
+    kick_radius: Scalar,
+
+    /// Velocity the K key adds to each body within --kick-radius of the cursor
+    #[arg(long, value_name = "VX,VY", default_value = "0,0.5", value_parser = parse_vector, allow_hyphen_values = true)]
+    kick_velocity: [Scalar; 2],
+
+    /// Start from a classic test problem instead of a random distribution
+    #[arg(long, value_enum)]
+    preset: Option<Preset>,
+
+    /// Distance along x between the centres of the two-cluster preset's clusters
+    #[arg(long, default_value_t = 10.0)]
+    separation: Scalar,
+
+    /// Speed at which the two-cluster preset's clusters approach each other
+    #[arg(long, default_value_t = 0.5)]
+    velocity: Scalar,
+
+    /// Offset along y between the centres of the two-cluster preset's clusters (0 = head-on)
+    #[arg(long, default_value_t = 0.0)]
+    impact: Scalar,
+
+    /// Initial condition generator for random bodies
+    #[arg(long = "ic", value_enum, default_value_t = InitialCondition::Disk)]
+    initial_condition: InitialCondition,
+
+    /// Log the virial ratio 2T/|U| at every --nsteps interval
+    #[arg(long)]
+    virial: bool,
+
+    /// Rescale the starting bodies (and the timestep and softening) to N-body units: G = 1, total mass 1, energy -1/4
+    #[arg(long, conflicts_with = "replay")]
+    nbody_units: bool,
+
+    /// Region the disk initial condition spreads its bodies over
+    #[arg(long, value_enum, default_value_t = ShapeArg::Disk)]
+    shape: ShapeArg,
+
+    /// Inner radius of the ring shape
+    #[arg(long, default_value_t = 0.5)]
+    r_inner: Scalar,
+
+    /// Outer radius of the ring shape, and half the width of the uniform shape's square
+    #[arg(long, default_value_t = 1.0)]
+    r_outer: Scalar,
+
+    /// Standard deviation of the gaussian shape along each axis
+    #[arg(long, default_value_t = 0.5)]
+    sigma: Scalar,
+
+    /// Solid-body angular velocity for the collapse initial condition
+    #[arg(long, default_value_t = 0.0)]
+    rotation: Scalar,
+
+    /// Masses of the disk initial condition's bodies: equal (--mass each), salpeter, or powerlaw:ALPHA for dN/dm ∝ m^-ALPHA
+    #[arg(long, value_parser = parse_mass_function, default_value = "equal")]
+    mass_function: MassFunctionArg,
+
+    /// Lightest mass of a power-law mass function [default: --mass]
+    #[arg(long)]
+    mass_min: Option<Scalar>,
+
+    /// Heaviest mass of a power-law mass function [default: 100 times --mass-min]
+    #[arg(long)]
+    mass_max: Option<Scalar>,
+}
+
+fn parse_msaa(s: &str) -> Result<u8, String> {
+    let samples: u8 = s.parse().map_err(|e| format!("{}", e))?;
+    if samples == 0 || samples.is_power_of_two() {
+        Ok(samples)
+    } else {
+        Err(format!("{} is not a power of two", samples))
+    }
+}
+
+fn parse_vector(s: &str) -> Result<[Scalar; 2], String> {
+    let (x, y) = s.split_once(',').ok_or_else(|| format!("expected X,Y, got {}", s))?;
+    let component = |v: &str| v.trim().parse::<Scalar>().map_err(|e| format!("{}", e));
+    Ok([component(x)?, component(y)?])
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum IntegratorArg {
+    /// Semi-implicit Euler (first order)
+    Euler,
+    /// Drift-kick-drift leapfrog (second order)
+    Leapfrog,
+    /// Yoshida's fourth-order symplectic integrator
+    Yoshida4,
+    /// Kick-drift-kick leapfrog with per-body block timesteps
+    Block,
+}
+
+impl IntegratorArg {
+    fn integrator(self, config: &Config) -> Integrator {
+        match self {
+            IntegratorArg::Euler => Integrator::SymplecticEuler,
+            IntegratorArg::Leapfrog => Integrator::Leapfrog,
+            IntegratorArg::Yoshida4 => Integrator::Yoshida4,
+            IntegratorArg::Block => Integrator::BlockLeapfrog {
+                levels: config.block_levels,
+                // The usual criterion dt = √(2 η ε / |a|) with η = 0.025
+                accuracy: config.block_accuracy.unwrap_or(0.05 * config.softening.sqrt()),
+            },
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum ShapeArg {
+    /// Within radius 1, crowding towards the centre
+    Disk,
+    /// Uniform over the annulus from --r-inner to --r-outer
+    Ring,
+    /// Normal around the origin with standard deviation --sigma
+    Gaussian,
+    /// Uniform over the square of half-width --r-outer
+    Uniform,
+}
+
+impl ShapeArg {
+    fn shape(self, config: &Config) -> Shape {
+        match self {
+            ShapeArg::Disk => Shape::Disk,
+            ShapeArg::Ring => Shape::Ring { inner: config.r_inner, outer: config.r_outer },
+            ShapeArg::Gaussian => Shape::Gaussian { sigma: config.sigma },
+            ShapeArg::Uniform => Shape::Uniform { half_width: config.r_outer },
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum MassFunctionArg {
+    Equal,
+    PowerLaw(Scalar),
+}
+
+impl MassFunctionArg {
+    fn mass_function(self, config: &Config) -> MassFunction {
+        let min = config.mass_min.unwrap_or(config.mass);
+        let max = config.mass_max.unwrap_or(100.0 * min);
+        match self {
+            MassFunctionArg::Equal => MassFunction::Equal { mass: config.mass },
+            MassFunctionArg::PowerLaw(alpha) => MassFunction::PowerLaw { alpha, min, max },
+        }
+    }
+}
+
+fn parse_mass_function(s: &str) -> Result<MassFunctionArg, String> {
+    match s.split_once(':') {
+        None if s == "equal" => Ok(MassFunctionArg::Equal),
+        None if s == "salpeter" => Ok(MassFunctionArg::PowerLaw(initial_conditions::SALPETER_ALPHA)),
+        Some(("powerlaw", alpha)) => alpha
+            .parse()
+            .map(MassFunctionArg::PowerLaw)
+            .map_err(|e| format!("invalid power-law slope {}: {}", alpha, e)),
+        _ => Err("expected equal, salpeter or powerlaw:ALPHA".to_string()),
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum Opening {
+    /// Node diagonal over distance to its centre of mass
+    Diagonal,
+    /// Node width over distance to its centre of mass (classic s/d)
+    Width,
+    /// Node width over distance to the nearest point of the node
+    MinDistance,
+}
+
+impl From<Opening> for OpeningCriterion {
+    fn from(opening: Opening) -> Self {
+        match opening {
+            Opening::Diagonal => OpeningCriterion::Diagonal,
+            Opening::Width => OpeningCriterion::Width,
+            Opening::MinDistance => OpeningCriterion::MinDistance,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum InitialCondition {
+    /// Spinning disk around a heavy central body
+    Disk,
+    /// Cold uniform disk that collapses under its own gravity
+    Collapse,
+    /// Non-rotating cluster in virial equilibrium (2D Plummer model)
+    Plummer,
+    /// Square grid of bodies at rest filling the 2 x 2 square (the top row is partial unless -n is a square)
+    Lattice,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Preset {
+    /// Two equal masses on a circular orbit
+    TwoBody,
+    /// Figure-eight three-body choreography
+    Figure8,
+    /// Hierarchical Sun-Earth-Moon-like triple
+    SunEarthMoon,
+    /// Two Plummer clusters of half the bodies each, set to collide
+    TwoCluster,
+}
+
+impl Preset {
+    fn bodies(self, config: &Config) -> Vec<Body> {
+        let g = config.g;
+        match self {
+            Preset::TwoBody => presets::two_body_circular(g, 1.0, 1.0, 1.0),
+            Preset::Figure8 => presets::figure_eight(g),
+            Preset::SunEarthMoon => presets::sun_earth_moon(g),
+            Preset::TwoCluster => initial_conditions::colliding_clusters(
+                config.n_bodies,
+                1.0,
+                config.mass * config.n_bodies as Scalar,
+                g,
+                config.separation,
+                config.velocity,
+                config.impact,
+            ),
+        }
+    }
 }
 
 struct SimulationState {
@@ -110,27 +573,53 @@ struct SimulationState {
     renderer: Option<Renderer>,
     gl_context: Option<PossiblyCurrentContext>,
     gl_surface: Option<Surface<WindowSurface>>,
-    step_count: usize,
-    sim_time: f64,
     last_render: Instant,
-    last_save: usize,
-    frame_times: Vec<Duration>,  // Track recent frame times
+    last_save: Option<usize>,    // Step of the last snapshot written, if any
+    started: Instant,            // Wall-clock start, for --max-time
+    frame_interval: Duration,    // Minimum time between rendered frames
+    frames_rendered: u32,        // Frames rendered since the last FPS update
+    total_frames: u64,           // Frames rendered since the start, for --record-every
+    frames_recorded: u64,        // Frames written by --record
     fps_update_timer: Instant,   // Timer for FPS updates
+    title: Option<String>,       // Window title due with --title-stats, set at each FPS update
+    cursor_ndc: [f32; 2],        // Last cursor position in normalized device coordinates
+    selected: Option<usize>,     // Index of the body picked with the mouse
+    spawn_start: Option<[Scalar; 2]>, // World position of a body being dragged out with --spawn-mass
+    replay: Option<Replay>,      // Snapshots played back instead of stepping
+    lagrangian: Option<fileio::TableWriter>, // Lagrangian radii table for --lagrangian
+    paused: bool,
+    step_request: isize,         // Steps (or replay frames) to take while paused
 }
 
 impl SimulationState {
-    fn new(simulation: Simulation) -> Self {
+    fn new(simulation: Simulation, fps: u32) -> Self {
+        let frame_interval = if fps == 0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(1.0 / fps as f64)
+        };
+
         SimulationState {
             simulation,
             renderer: None,
             gl_context: None,
             gl_surface: None,
-            step_count: 0,
-            sim_time: 0.0,
             last_render: Instant::now(),
-            last_save: 0,
-            frame_times: Vec::with_capacity(60),
+            last_save: None,
+            started: Instant::now(),
+            frame_interval,
+            frames_rendered: 0,
+            total_frames: 0,
+            frames_recorded: 0,
             fps_update_timer: Instant::now(),
+            title: None,
+            cursor_ndc: [0.0, 0.0],
+            selected: None,
+            spawn_start: None,
+            replay: None,
+            lagrangian: None,
+            paused: false,
+            step_request: 0,
         }
     }
 
@@ -139,206 +628,624 @@ impl SimulationState {
         event_loop: &EventLoop<()>,
         config: &Config,
     ) -> Result<Window, Box<dyn std::error::Error>> {
-        let window_builder = WindowBuilder::new()
-            .with_title("N-body Simulation")
-            .with_inner_size(LogicalSize::new(
-                config.width as f64,
-                config.height as f64,
-            ));
-
-        let template = ConfigTemplateBuilder::new()
-            .with_alpha_size(8)
-            .with_transparency(true);
-
-        let display_builder = DisplayBuilder::new().with_window_builder(Some(window_builder));
-        let (window, gl_config) = display_builder
-            .build(event_loop, template, |configs| {
-                configs
-                    .reduce(|accum, config| {
-                        let transparency_check = config.supports_transparency().unwrap_or(false)
-                            & !accum.supports_transparency().unwrap_or(false);
-                        if transparency_check || config.num_samples() > accum.num_samples() {
-                            config
-                        } else {
-                            accum
-                        }
-                    })
-                    .unwrap()
-            })
-            .map_err(|e| format!("Failed to build window: {}", e))?;
+        let OpenGl { window, gl, context: gl_context, surface: gl_surface, samples } =
+            create_gl(event_loop, config, true)?;
+        if config.msaa > 0 {
+            log::info!("MSAA samples: {}", samples);
+        }
 
-        let window = window.unwrap();
-        let raw_window_handle = window.raw_window_handle();
+        // Initialize renderer
+        let mut renderer = Renderer::new(gl, config.point_size, config.fixed_scale)?;
+        renderer.set_tree_color_by_mass(config.tree_mass_color);
+        renderer.set_wireframe_width(config.wire_width);
+        renderer.set_wireframe_min_mass(config.wire_min_mass);
+        renderer.set_render_sample(config.render_sample);
+        if config.color_species {
+            renderer.set_color_mode(ColorMode::Species);
+        } else if config.color_body {
+            renderer.set_color_mode(ColorMode::PerBody);
+        }
+        renderer.set_show_velocities(config.velocity_arrows);
+        renderer.set_time_tint(config.time_tint);
+        renderer.set_bloom(config.bloom, config.bloom_strength)?;
+        if let Some(ref path) = config.sprite_image {
+            let (width, height, rgba) = fileio::read_png(path)?;
+            if width != height {
+                return Err(format!("Sprite image must be square, got {}x{}", width, height).into());
+            }
+            renderer.set_sprite(Some(&Sprite::new(width, rgba)?))?;
+        } else if config.sprites {
+            renderer.set_sprite(Some(&Sprite::glow(SPRITE_SIZE)))?;
+        }
+        self.renderer = Some(renderer);
+        self.gl_context = Some(gl_context);
+        self.gl_surface = Some(gl_surface);
 
-        let gl_display = gl_config.display();
-        let context_attributes = ContextAttributesBuilder::new().build(Some(raw_window_handle));
-        let gl_context = unsafe {
-            gl_display
-                .create_context(&gl_config, &context_attributes)
-                .map_err(|e| format!("Failed to create context: {}", e))?
-        };
+        Ok(window)
+    }
 
-        let attrs = window.build_surface_attributes(<_>::default());
-        let gl_surface = unsafe {
-            gl_display
-                .create_window_surface(&gl_config, &attrs)
-                .map_err(|e| format!("Failed to create surface: {}", e))?
-        };
+    fn update(&mut self, config: &Config) -> Result<(), NBodyError> {
+        if !self.paused || self.step_request != 0 {
+            if self.replay.is_some() {
+                self.advance_replay(config);
+            } else {
+                self.step_simulation(config)?;
+            }
+        }
 
-        let gl_context = gl_context
-            .make_current(&gl_surface)
-            .map_err(|e| format!("Failed to make context current: {}", e))?;
+        // Update FPS counter every second with the achieved frame rate
+        let elapsed = self.fps_update_timer.elapsed();
+        if elapsed >= Duration::from_secs(1) {
+            let fps = self.frames_rendered as f64 / elapsed.as_secs_f64();
+            if self.frames_rendered > 0 && config.title_stats {
+                let progress = match self.replay {
+                    Some(ref replay) => format!("frame {} of {}", replay.index() + 1, replay.len()),
+                    None => format!("t={:.4}", self.simulation.time()),
+                };
+                self.title = Some(format!(
+                    "{} bodies | {} | {:.1} FPS",
+                    self.simulation.bodies().len(),
+                    progress,
+                    fps
+                ));
+            }
+            if self.frames_rendered > 0 && !config.quiet {
+                match self.replay {
+                    Some(ref replay) => print!("\r{} {:>6} of {} | {:.1} FPS",
+                        console::style("Replay frame:").cyan(),
+                        replay.index() + 1,
+                        replay.len(),
+                        fps
+                    ),
+                    None => print!("\r{} {:<12.6} seconds | {:.1} FPS", 
+                        console::style("Simulation time:").cyan(),
+                        self.simulation.time(),
+                        fps
+                    ),
+                }
+            }
+            self.fps_update_timer = Instant::now();
+            self.frames_rendered = 0;
+        }
 
-        // Create glow context
-        let gl = unsafe {
-            let gl = glow::Context::from_loader_function(|s| {
-                gl_display.get_proc_address(&std::ffi::CString::new(s).unwrap()) as *const _
-            });
-            Arc::new(gl)
-        };
+        Ok(())
+    }
 
-        gl_surface
-            .set_swap_interval(&gl_context, SwapInterval::Wait(NonZeroU32::new(1).unwrap()))
-            .map_err(|e| format!("Failed to set swap interval: {}", e))?;
+    /// Advance the simulation one step, writing any dumps and outputs due
+    fn step_simulation(&mut self, config: &Config) -> Result<(), NBodyError> {
+        self.step_request = 0;
 
-        // Initialize renderer
-        self.renderer = Some(Renderer::new(gl, config.point_size, config.fixed_scale)?);
-        self.gl_context = Some(gl_context);
-        self.gl_surface = Some(gl_surface);
+        if self.simulation.step_count() == config.dump_step {
+            if let Some(ref dump_tree) = config.dump_tree {
+                fileio::write_dot(dump_tree, &self.simulation.get_tree())?;
+            }
 
-        Ok(window)
-    }
+            if let Some(ref potential_grid) = config.potential_grid {
+                let bounds = self.simulation.get_tree().get_bounds().clone();
+                let values = self.simulation.potential_grid(config.potential_resolution, bounds.clone());
+                fileio::write_grid(potential_grid, &values, config.potential_resolution, &bounds)?;
+            }
+
+            if let Some(ref speed_hist) = config.speed_hist {
+                let histogram = self.simulation.speed_histogram(config.speed_bins as usize);
+                fileio::write_speed_histogram(speed_hist, &histogram)?;
+            }
+        }
 
-    fn update(&mut self, config: &Config) -> Result<(), String> {
         self.simulation.step();
-        self.step_count += 1;
-        self.sim_time += config.timestep;
 
         // Save state if requested
-        if let Some(ref output_file) = config.output_file {
-            if self.step_count % config.write_interval == 0 {
-                fileio::write_bodies(
-                    output_file,
-                    self.simulation.bodies(),
-                    config.timestep,
-                    config.g,
-                    config.softening,
-                    config.tree_ratio,
-                )?;
-                self.last_save = self.step_count;
+        if self.simulation.step_count().is_multiple_of(config.write_interval) {
+            self.save(config)?;
+            if config.virial {
+                log::info!("Virial ratio at step {}: {:.4}", self.simulation.step_count(), self.simulation.virial_ratio());
             }
         }
 
-        // Update FPS counter every second
-        if self.fps_update_timer.elapsed() >= Duration::from_secs(1) {
-            if !self.frame_times.is_empty() {
-                let avg_frame_time = self.frame_times.iter().sum::<Duration>() / self.frame_times.len() as u32;
-                let fps = 1.0 / avg_frame_time.as_secs_f64();
-                print!("\r{} {:<12.6} seconds | {:.1} FPS", 
-                    console::style("Simulation time:").cyan(),
-                    self.sim_time,
-                    fps
-                );
+        Ok(())
+    }
+
+    /// Show the next replay frame (or the requested one while paused), one
+    /// per rendered frame. Frames that fail to load are skipped with a warning;
+    /// playback pauses on the last frame.
+    fn advance_replay(&mut self, config: &Config) {
+        if self.step_request == 0 && !self.should_render() {
+            return;
+        }
+        let delta = if self.step_request != 0 { self.step_request } else { 1 };
+        self.step_request = 0;
+
+        let Some(replay) = self.replay.as_mut() else {
+            return;
+        };
+        while replay.seek(delta.signum()) {
+            match replay.load() {
+                Ok(bodies) => {
+                    self.simulation = replay_simulation(bodies, config);
+                    return;
+                }
+                Err(e) => log::warn!("Skipping replay frame {}: {}", replay.path().display(), e),
             }
-            self.fps_update_timer = Instant::now();
-            self.frame_times.clear();
         }
 
+        if !self.paused && delta > 0 {
+            log::info!("Replay finished; press Left to step back");
+            self.paused = true;
+        }
+    }
+
+    /// Space pauses and resumes; while paused, Right takes one step (or shows
+    /// the next replay frame) and Left shows the previous replay frame
+    fn handle_key(&mut self, key: VirtualKeyCode, config: &Config) {
+        match key {
+            VirtualKeyCode::Space => self.paused = !self.paused,
+            VirtualKeyCode::Right if self.paused => self.step_request = 1,
+            VirtualKeyCode::Left if self.paused && self.replay.is_some() => self.step_request = -1,
+            VirtualKeyCode::LBracket | VirtualKeyCode::RBracket => {
+                if let Some(renderer) = self.renderer.as_mut() {
+                    let fraction = step_wire_threshold(renderer.wireframe_min_mass(), key == VirtualKeyCode::RBracket);
+                    renderer.set_wireframe_min_mass(fraction);
+                    log::info!("Drawing tree nodes with at least {} of the total mass", fraction);
+                }
+            }
+            VirtualKeyCode::W => {
+                if let Some(renderer) = self.renderer.as_mut() {
+                    renderer.set_wireframe(!renderer.wireframe());
+                }
+            }
+            VirtualKeyCode::K if self.replay.is_none() => self.kick(config),
+            VirtualKeyCode::C => {
+                if let Some(renderer) = self.renderer.as_mut() {
+                    let mode = next_color_mode(renderer.color_mode());
+                    renderer.set_color_mode(mode);
+                    log::info!("Coloring bodies by {:?}", mode);
+                }
+            }
+            VirtualKeyCode::Equals | VirtualKeyCode::Plus | VirtualKeyCode::NumpadAdd
+            | VirtualKeyCode::Minus | VirtualKeyCode::NumpadSubtract => {
+                if let Some(renderer) = self.renderer.as_mut() {
+                    let grow = !matches!(key, VirtualKeyCode::Minus | VirtualKeyCode::NumpadSubtract);
+                    let factor = if grow { POINT_SIZE_STEP } else { 1.0 / POINT_SIZE_STEP };
+                    renderer.set_point_size((renderer.point_size() * factor).max(MIN_POINT_SIZE));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Write the current state to every requested output
+    fn save(&mut self, config: &Config) -> Result<(), NBodyError> {
+        let step_count = self.simulation.step_count();
+
+        if let Some(ref output_file) = config.output_file {
+            fileio::write_bodies(
+                output_file,
+                self.simulation.bodies(),
+                self.simulation.timestep(),
+                self.simulation.g(),
+                self.simulation.softening(),
+                self.simulation.tree_ratio(),
+            )?;
+        }
+
+        if let Some(ref xyz_dir) = config.xyz_dir {
+            fileio::write_xyz(
+                xyz_dir.join(format!("snapshot_{:08}.xyz", step_count)),
+                self.simulation.bodies(),
+                &format!("step={} time={:.16e}", step_count, self.simulation.time()),
+            )?;
+        }
+
+        if let Some(ref vtk_dir) = config.vtk_dir {
+            fileio::write_vtk(
+                vtk_dir.join(format!("snapshot_{:08}.vtk", step_count)),
+                self.simulation.bodies(),
+            )?;
+        }
+
+        if let Some(ref tipsy_dir) = config.tipsy_dir {
+            fileio::write_tipsy(
+                tipsy_dir.join(format!("snapshot_{:08}.tipsy", step_count)),
+                self.simulation.bodies(),
+                self.simulation.time(),
+                self.simulation.softening(),
+            )?;
+        }
+
+        self.write_lagrangian_radii()?;
+
+        self.last_save = Some(step_count);
+        Ok(())
+    }
+
+    /// Add the current step to the --lagrangian table, if there is one
+    fn write_lagrangian_radii(&mut self) -> Result<(), NBodyError> {
+        if let Some(ref mut table) = self.lagrangian {
+            let mut row = vec![self.simulation.step_count() as Scalar, self.simulation.time()];
+            row.extend(self.simulation.lagrangian_radii(&LAGRANGIAN_FRACTIONS));
+            table.write_row(&row)?;
+        }
+        Ok(())
+    }
+
+    /// Why the run should stop, if --steps, --max-time or --sim-time was reached
+    fn limit_reached(&self, config: &Config) -> Option<String> {
+        if let Some(steps) = config.steps.filter(|&n| self.simulation.step_count() >= n) {
+            return Some(format!("reached {} steps", steps));
+        }
+        if let Some(seconds) = config.max_time.filter(|&t| self.started.elapsed().as_secs_f64() >= t) {
+            return Some(format!("reached the wall-clock limit of {} s", seconds));
+        }
+        if let Some(time) = config.sim_time.filter(|&t| self.simulation.time() >= t) {
+            return Some(format!("reached simulation time {}", time));
+        }
+        None
+    }
+
+    /// Write a final snapshot unless this step was just saved (or replaying),
+    /// even if no step was taken, and the --stats-out summary
+    fn finish(&mut self, config: &Config) -> Result<(), NBodyError> {
+        if self.replay.is_none() && self.last_save != Some(self.simulation.step_count()) {
+            self.save(config)?;
+        }
+        if let Some(ref stats_out) = config.stats_out {
+            fileio::write_stats(stats_out, &self.simulation, self.started.elapsed().as_secs_f64())?;
+        }
+        if let Some(depth) = config.max_tree_depth {
+            log::info!("Tree leaves holding several bodies at the depth cap of {}: {}",
+                depth,
+                self.simulation.capped_tree_leaves()
+            );
+        }
+        if config.report_opens {
+            log::info!("Nodes opened per force evaluation: {:.1} (tree ratio {}, {:?} opening)",
+                self.simulation.mean_nodes_opened(),
+                self.simulation.tree_ratio(),
+                self.simulation.opening_criterion()
+            );
+        }
+        Ok(())
+    }
+
+    /// Resize the GL surface and renderer to a new window size
+    fn resize(&mut self, width: u32, height: u32) -> Result<(), NBodyError> {
+        let (Some(w), Some(h)) = (NonZeroU32::new(width), NonZeroU32::new(height)) else {
+            return Ok(()); // Minimized
+        };
+
+        if let (Some(gl_surface), Some(gl_context)) = (self.gl_surface.as_ref(), self.gl_context.as_ref()) {
+            gl_surface.resize(gl_context, w, h);
+        }
+        if let Some(renderer) = self.renderer.as_mut() {
+            renderer.resize(width, height)?;
+        }
         Ok(())
     }
 
+    /// Record the cursor position given in window pixels
+    fn set_cursor(&mut self, x: f64, y: f64, width: u32, height: u32) {
+        self.cursor_ndc = [
+            (2.0 * x / width.max(1) as f64 - 1.0) as f32,
+            (1.0 - 2.0 * y / height.max(1) as f64) as f32,
+        ];
+    }
+
+    /// Left button pressed: start spawning a body with --spawn-mass,
+    /// otherwise select one
+    fn press(&mut self, config: &Config) {
+        if config.spawn_mass.is_some() {
+            self.spawn_start = self.cursor_world();
+        } else {
+            self.select_body();
+        }
+    }
+
+    /// Left button released: add the body being spawned, flung opposite to
+    /// the drag like a slingshot
+    fn release(&mut self, config: &Config) {
+        let (Some(start), Some(mass)) = (self.spawn_start.take(), config.spawn_mass) else {
+            return;
+        };
+        let Some(end) = self.cursor_world() else {
+            return;
+        };
+
+        let velocity = [
+            (start[0] - end[0]) * config.spawn_speed,
+            (start[1] - end[1]) * config.spawn_speed,
+        ];
+        self.simulation.add_body(Body::new(mass, start[0], start[1], velocity[0], velocity[1]));
+        println!("\n{} #{} | mass {:.6e} | position ({:.6e}, {:.6e}) | velocity ({:.6e}, {:.6e})",
+            console::style("Added body").cyan(),
+            self.simulation.bodies().len() - 1,
+            mass,
+            start[0], start[1],
+            velocity[0], velocity[1],
+        );
+    }
+
+    /// Add --kick-velocity to the bodies within --kick-radius of the cursor
+    fn kick(&mut self, config: &Config) {
+        let Some(center) = self.cursor_world() else {
+            return;
+        };
+        let kicked = self.simulation.kick_region(center, config.kick_radius, config.kick_velocity);
+        println!("\n{} {} bodies around ({:.6e}, {:.6e}) by ({:.6e}, {:.6e})",
+            console::style("Kicked").cyan(),
+            kicked,
+            center[0], center[1],
+            config.kick_velocity[0], config.kick_velocity[1],
+        );
+    }
+
+    /// The cursor position in world coordinates, through the view the last
+    /// frame was drawn with (which follows the bodies unless --fixed-scale)
+    fn cursor_world(&self) -> Option<[Scalar; 2]> {
+        let renderer = self.renderer.as_ref()?;
+        Some(renderer.screen_to_world(&self.simulation.get_tree(), self.cursor_ndc))
+    }
+
+    /// Select the body under the cursor and print its state
+    fn select_body(&mut self) {
+        let Some(renderer) = self.renderer.as_mut() else {
+            return;
+        };
+
+        let tree = self.simulation.get_tree();
+        let point = renderer.screen_to_world(&tree, self.cursor_ndc);
+        let bodies = self.simulation.bodies();
+
+        // The tree leaf under the cursor holds a copy of the body; fall back
+        // to the nearest body when the click lands in an empty cell
+        self.selected = tree
+            .locate(point)
+            .and_then(|found| bodies.iter().position(|b| b.position == found.position))
+            .or_else(|| nearest_body(bodies, point));
+        renderer.set_highlighted(self.selected);
+
+        if let Some(index) = self.selected {
+            let body = &bodies[index];
+            println!("\n{} #{} | mass {:.6e} | position ({:.6e}, {:.6e}) | velocity ({:.6e}, {:.6e}) | speed {:.6e}",
+                console::style("Selected body").cyan(),
+                index,
+                body.mass,
+                body.position[0], body.position[1],
+                body.velocity[0], body.velocity[1],
+                body.speed(),
+            );
+        }
+    }
+
     fn should_render(&self) -> bool {
-        self.last_render.elapsed() >= FRAME_TIME
+        self.last_render.elapsed() >= self.frame_interval
     }
 
-    fn render(&mut self) {
+    fn render(&mut self, config: &Config) -> Result<(), NBodyError> {
+        if let Some(renderer) = self.renderer.as_mut() {
+            renderer.set_time(self.simulation.time() as f32);
+        }
         if let (Some(renderer), Some(gl_surface), Some(gl_context)) = 
             (self.renderer.as_ref(), self.gl_surface.as_ref(), self.gl_context.as_ref()) {
-            let frame_start = Instant::now();
-            
-            let tree = self.simulation.get_tree();
+            // With the wireframe hidden the tree only sets the view, from its root bounds
+            let wire_depth = if renderer.wireframe() { config.wire_depth } else { Some(0) };
+            let tree = match wire_depth {
+                Some(depth) => self.simulation.display_tree(depth),
+                None => self.simulation.get_tree(),
+            };
             renderer.render(self.simulation.bodies(), &tree);
+
+            // Capture before swapping, while the back buffer holds this frame
+            if let Some(ref record_dir) = config.record_dir {
+                if self.total_frames.is_multiple_of(config.record_every as u64) {
+                    self.frames_recorded += 1;
+                    let (width, height, rgba) = renderer.read_pixels();
+                    fileio::write_png(
+                        record_dir.join(format!("frame_{:05}.png", self.frames_recorded)),
+                        width,
+                        height,
+                        &rgba,
+                    )?;
+                }
+            }
+
             gl_surface.swap_buffers(gl_context).unwrap();
             
-            // Track frame time
-            self.frame_times.push(frame_start.elapsed());
+            self.frames_rendered += 1;
+            self.total_frames += 1;
             self.last_render = Instant::now();
         }
+        Ok(())
     }
 }
 
+/// Index of the body closest to `point`
+fn nearest_body(bodies: &[Body], point: [Scalar; 2]) -> Option<usize> {
+    let distance_sq = |body: &Body| {
+        let dx = body.position[0] - point[0];
+        let dy = body.position[1] - point[1];
+        dx * dx + dy * dy
+    };
+
+    bodies
+        .iter()
+        .enumerate()
+        .min_by(|(_, a), (_, b)| distance_sq(a).total_cmp(&distance_sq(b)))
+        .map(|(i, _)| i)
+}
+
 fn random_bodies(config: &Config) -> Vec<Body> {
     let mut rng = rand::thread_rng();
     let mut bodies = Vec::with_capacity(config.n_bodies);
 
-    // Create central body first
-    bodies.push(Body::new(
-        config.mzero,
-        0.0, 0.0,  // position
-        0.0, 0.0   // velocity
-    ));
+    // Create central bodies first, as their own species only with
+    // --color-species, so that `-o` files otherwise keep five columns
+    let centrals = (config.central_bodies as usize).min(config.n_bodies.max(1));
+    let central_species = u8::from(config.color_species);
+    bodies.extend(
+        initial_conditions::central_ring(centrals, config.mzero, CENTRAL_RING_RADIUS, config.g)
+            .into_iter()
+            .map(|body| body.with_species(central_species)),
+    );
 
     // Create remaining bodies
-    for _ in 1..config.n_bodies {
-        let r = rng.gen::<f64>() * 2.0 - 1.0; // Range [-1, 1]
-        let theta = 2.0 * PI * rng.gen::<f64>();
-
-        let x = r * theta.cos();
-        let y = r * theta.sin();
+    let shape = config.shape.shape(config);
+    let mass_function = config.mass_function.mass_function(config);
+    for _ in centrals..config.n_bodies {
+        let [x, y] = shape.sample(&mut rng);
+        let r = x.hypot(y);
 
         let mut vx = 0.0;
         let mut vy = 0.0;
 
         if config.spin != 0.0 {
-            let spin_factor = config.spin * (1.0 + 0.1 * rng.gen::<f64>()) / (1.0 + r.abs());
+            let spin_factor = config.spin * (1.0 + 0.1 * rng.gen::<Scalar>()) / (1.0 + r);
             vx = -y * spin_factor; // Tangential velocity
             vy = x * spin_factor;
         }
 
-        bodies.push(Body::new(config.mass, x, y, vx, vy));
+        bodies.push(Body::new(mass_function.sample(&mut rng), x, y, vx, vy));
     }
 
     bodies
 }
 
-fn run_simulation(config: Config) -> Result<(), Box<dyn std::error::Error>> {
-    // Initialize bodies either from file or random distribution
+/// Bodies from a file, a preset or a random distribution
+fn initial_bodies(config: &Config) -> Result<Vec<Body>, NBodyError> {
     let bodies = if let Some(ref input_file) = config.input_file {
-        fileio::read_bodies(input_file)?
+        let mut bodies = fileio::read_bodies(input_file)?;
+        if let Some(n) = config.add_bodies {
+            let particles = initial_conditions::test_particles(&bodies, n, config.mass, config.g);
+            bodies.extend(particles);
+        }
+        bodies
+    } else if let Some(preset) = config.preset {
+        preset.bodies(config)
     } else {
-        random_bodies(&config)
+        match config.initial_condition {
+            InitialCondition::Disk => random_bodies(config),
+            InitialCondition::Collapse => initial_conditions::cold_collapse(
+                config.n_bodies,
+                1.0,
+                config.mass * config.n_bodies as Scalar,
+                config.rotation,
+            ),
+            InitialCondition::Plummer => initial_conditions::plummer(
+                config.n_bodies,
+                1.0,
+                config.mass * config.n_bodies as Scalar,
+                config.g,
+            ),
+            InitialCondition::Lattice => {
+                let nx = (config.n_bodies as f64).sqrt().ceil() as usize;
+                let ny = config.n_bodies.div_ceil(nx.max(1));
+                let mut bodies = initial_conditions::lattice(nx, ny, 2.0 / nx as Scalar, config.mass);
+                bodies.truncate(config.n_bodies);
+                bodies
+            }
+        }
     };
 
+    Ok(bodies)
+}
+
+/// A simulation holding one replayed snapshot, for the renderer to draw
+fn replay_simulation(bodies: Vec<Body>, config: &Config) -> Simulation {
+    Simulation::new(bodies, config.timestep, config.g, config.softening, config.tree_ratio)
+}
+
+/// Open a replay directory and load its first readable frame
+fn open_replay(dir: &std::path::Path) -> Result<(Replay, Vec<Body>), NBodyError> {
+    let mut replay = Replay::open(dir)?;
+    loop {
+        match replay.load() {
+            Ok(bodies) => return Ok((replay, bodies)),
+            Err(e) => log::warn!("Skipping replay frame {}: {}", replay.path().display(), e),
+        }
+        if !replay.seek(1) {
+            return Err(NBodyError::Parse("No readable snapshot files in replay directory".to_string()));
+        }
+    }
+}
+
+fn run_simulation(config: Config) -> Result<(), Box<dyn std::error::Error>> {
+    let replay = config.replay.as_deref().map(open_replay).transpose()?;
+
+    // Initialize bodies from a replay, a file, a preset or a random distribution
+    let (replay, bodies) = match replay {
+        Some((replay, bodies)) => (Some(replay), bodies),
+        None => (None, initial_bodies(&config)?),
+    };
+
+    let n_bodies = bodies.len();
+
     // Create simulation
-    let simulation = Simulation::new(
+    let mut simulation = Simulation::new(
         bodies,
         config.timestep,
         config.g,
         config.softening,
         config.tree_ratio
     );
+    simulation.set_incremental_tree(config.incremental_tree);
+    simulation.set_tree_rebuild_interval(config.tree_rebuild_interval);
+    simulation.set_max_tree_depth(config.max_tree_depth);
+    simulation.set_opening_criterion(config.opening.into());
+    simulation.set_integrator(config.integrator.integrator(&config));
+    simulation.set_compensated_summation(config.compensated_sum);
+    simulation.set_drag(config.drag);
+    simulation.set_self_gravity(!config.no_self_gravity);
+    simulation.set_adaptive_softening(config.adaptive_softening);
+    if config.nbody_units && !simulation.to_nbody_units() {
+        return Err("--nbody-units needs a bound system (negative total energy)".into());
+    }
 
     // Print initial configuration
-    println!("{}",
-        console::style("N-body Simulation Configuration")
-            .bold()
-            .bright()
-            .underlined()
-    );
-    println!("{}: {}", 
-        console::style("Number of bodies").cyan(),
-        console::style(config.n_bodies).yellow()
-    );
-    println!("{}: {}", 
-        console::style("Timestep").cyan(),
-        console::style(config.timestep).yellow()
-    );
-    println!("{}: {}", 
-        console::style("Graphics").cyan(),
-        console::style(!config.no_graphics).yellow()
-    );
+    if !config.quiet {
+        println!("{}",
+            console::style("N-body Simulation Configuration")
+                .bold()
+                .bright()
+                .underlined()
+        );
+        println!("{}: {}", 
+            console::style("Number of bodies").cyan(),
+            console::style(n_bodies).yellow()
+        );
+        println!("{}: {}", 
+            console::style("Timestep").cyan(),
+            console::style(simulation.timestep()).yellow()
+        );
+        println!("{}: {}",
+            console::style("Virial ratio").cyan(),
+            console::style(format!("{:.4}", simulation.virial_ratio())).yellow()
+        );
+        println!("{}: {}",
+            console::style("Bound fraction").cyan(),
+            console::style(format!("{:.4}", simulation.bound_fraction())).yellow()
+        );
+        println!("{}: {} (crossing time {})",
+            console::style("Relaxation time").cyan(),
+            console::style(format!("{:.4e}", simulation.relaxation_time())).yellow(),
+            console::style(format!("{:.4e}", simulation.crossing_time())).yellow()
+        );
+        println!("{}: {}", 
+            console::style("Graphics").cyan(),
+            console::style(!config.no_graphics).yellow()
+        );
+    }
+
+    if config.no_graphics && config.record_dir.is_some() {
+        log::warn!("--record has no effect with --no-graphics");
+    }
 
-    let mut state = SimulationState::new(simulation);
+    let mut state = SimulationState::new(simulation, config.fps);
+    state.replay = replay;
+    if let Some(ref path) = config.lagrangian {
+        let mut columns = vec!["step".to_string(), "time".to_string()];
+        columns.extend(LAGRANGIAN_FRACTIONS.iter().map(|f| format!("r{}", f * 100.0)));
+        state.lagrangian = Some(fileio::TableWriter::create(path, &columns)?);
+        state.write_lagrangian_radii()?;
+    }
 
     if !config.no_graphics {
         let event_loop = EventLoop::new();
@@ -352,17 +1259,80 @@ fn run_simulation(config: Config) -> Result<(), Box<dyn std::error::Error>> {
                     event: WindowEvent::CloseRequested,
                     ..
                 } => {
+                    stop(&mut state, &config, "window closed");
                     *control_flow = ControlFlow::Exit;
                 }
+                Event::WindowEvent {
+                    event: WindowEvent::Resized(size),
+                    ..
+                } => {
+                    if let Err(e) = state.resize(size.width, size.height) {
+                        log::error!("Error resizing renderer: {}", e);
+                        *control_flow = ControlFlow::Exit;
+                    }
+                }
+                Event::WindowEvent {
+                    event: WindowEvent::CursorMoved { position, .. },
+                    ..
+                } => {
+                    let size = window.inner_size();
+                    state.set_cursor(position.x, position.y, size.width, size.height);
+                }
+                Event::WindowEvent {
+                    event: WindowEvent::KeyboardInput {
+                        input: KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(key),
+                            ..
+                        },
+                        ..
+                    },
+                    ..
+                } => {
+                    state.handle_key(key, &config);
+                }
+                Event::WindowEvent {
+                    event: WindowEvent::MouseInput {
+                        state: ElementState::Pressed,
+                        button: MouseButton::Left,
+                        ..
+                    },
+                    ..
+                } => {
+                    state.press(&config);
+                }
+                Event::WindowEvent {
+                    event: WindowEvent::MouseInput {
+                        state: ElementState::Released,
+                        button: MouseButton::Left,
+                        ..
+                    },
+                    ..
+                } => {
+                    state.release(&config);
+                }
                 Event::MainEventsCleared => {
+                    if let Some(reason) = state.limit_reached(&config) {
+                        stop(&mut state, &config, &reason);
+                        *control_flow = ControlFlow::Exit;
+                        return;
+                    }
+
                     if let Err(e) = state.update(&config) {
-                        eprintln!("Error updating simulation: {}", e);
+                        log::error!("Error updating simulation: {}", e);
                         *control_flow = ControlFlow::Exit;
                         return;
                     }
 
+                    if let Some(title) = state.title.take() {
+                        window.set_title(&title);
+                    }
+
                     if state.should_render() {
-                        state.render();
+                        if let Err(e) = state.render(&config) {
+                            log::error!("Error recording frame: {}", e);
+                            *control_flow = ControlFlow::Exit;
+                        }
                     }
                 }
                 _ => (),
@@ -371,8 +1341,13 @@ fn run_simulation(config: Config) -> Result<(), Box<dyn std::error::Error>> {
     } else {
         // Non-graphical simulation loop
         loop {
+            if let Some(reason) = state.limit_reached(&config) {
+                stop(&mut state, &config, &reason);
+                break;
+            }
+
             if let Err(e) = state.update(&config) {
-                eprintln!("Error updating simulation: {}", e);
+                log::error!("Error updating simulation: {}", e);
                 break;
             }
         }
@@ -381,7 +1356,274 @@ fn run_simulation(config: Config) -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+/// The color mode the C key switches to from `mode`
+fn next_color_mode(mode: ColorMode) -> ColorMode {
+    match mode {
+        ColorMode::Uniform => ColorMode::Species,
+        ColorMode::Species => ColorMode::PerBody,
+        ColorMode::PerBody => ColorMode::Uniform,
+    }
+}
+
+/// The next wireframe mass threshold up or down: a factor of 10 at a time
+/// between 1e-6 and 1, and 0 (every node) below that
+fn step_wire_threshold(fraction: f32, up: bool) -> f32 {
+    const LOWEST: f32 = 1e-6;
+    match (up, fraction) {
+        (true, f) if f < LOWEST => LOWEST,
+        (true, f) => (f * 10.0).min(1.0),
+        (false, f) if f <= LOWEST * 1.5 => 0.0,
+        (false, f) => f / 10.0,
+    }
+}
+
+/// Write the final snapshot when a run limit is reached or the window is closed
+fn stop(state: &mut SimulationState, config: &Config, reason: &str) {
+    if !config.quiet {
+        println!();
+    }
+    log::info!("Stopping: {}", reason);
+    if let Err(e) = state.finish(config) {
+        log::error!("Error writing final snapshot: {}", e);
+    }
+}
+
+/// A window with a current OpenGL context on it
+struct OpenGl {
+    window: Window,
+    gl: Arc<glow::Context>,
+    context: PossiblyCurrentContext,
+    surface: Surface<WindowSurface>,
+    samples: u8,
+}
+
+/// Create the window and OpenGL context, picking a config for `--msaa`.
+/// The window is only shown if `visible`.
+fn create_gl(
+    event_loop: &EventLoop<()>,
+    config: &Config,
+    visible: bool,
+) -> Result<OpenGl, Box<dyn std::error::Error>> {
+    let window_builder = || {
+        WindowBuilder::new()
+            .with_title("N-body Simulation")
+            .with_inner_size(LogicalSize::new(
+                config.width as f64,
+                config.height as f64,
+            ))
+            .with_visible(visible)
+    };
+
+    let template = ConfigTemplateBuilder::new()
+        .with_alpha_size(8)
+        .with_transparency(true);
+
+    // With MSAA requested every offered config has at least that many
+    // samples, so take the smallest; otherwise take the most available
+    let msaa = config.msaa;
+    let pick_config = |configs: Box<dyn Iterator<Item = glutin::config::Config> + '_>| {
+        configs
+            .reduce(|accum, config| {
+                let transparency_check = config.supports_transparency().unwrap_or(false)
+                    & !accum.supports_transparency().unwrap_or(false);
+                let samples_check = if msaa > 0 {
+                    config.num_samples() < accum.num_samples()
+                } else {
+                    config.num_samples() > accum.num_samples()
+                };
+                if transparency_check || samples_check {
+                    config
+                } else {
+                    accum
+                }
+            })
+            .unwrap()
+    };
+
+    let display_builder = DisplayBuilder::new().with_window_builder(Some(window_builder()));
+    let built = if msaa > 0 {
+        display_builder.build(event_loop, template.clone().with_multisampling(msaa), pick_config)
+    } else {
+        display_builder.build(event_loop, template.clone(), pick_config)
+    };
+
+    // Fall back to any config if the requested sample count isn't available
+    let (window, gl_config) = match built {
+        Ok(built) => built,
+        Err(e) if msaa > 0 => {
+            log::warn!("{}x MSAA not available ({}), falling back", msaa, e);
+            DisplayBuilder::new()
+                .with_window_builder(Some(window_builder()))
+                .build(event_loop, template, pick_config)
+                .map_err(|e| format!("Failed to build window: {}", e))?
+        }
+        Err(e) => return Err(format!("Failed to build window: {}", e).into()),
+    };
+
+    let window = window.unwrap();
+    let raw_window_handle = window.raw_window_handle();
+
+    let gl_display = gl_config.display();
+    let context_attributes = ContextAttributesBuilder::new().build(Some(raw_window_handle));
+    let gl_context = unsafe {
+        gl_display
+            .create_context(&gl_config, &context_attributes)
+            .map_err(|e| format!("Failed to create context: {}", e))?
+    };
+
+    let attrs = window.build_surface_attributes(<_>::default());
+    let gl_surface = unsafe {
+        gl_display
+            .create_window_surface(&gl_config, &attrs)
+            .map_err(|e| format!("Failed to create surface: {}", e))?
+    };
+
+    let gl_context = gl_context
+        .make_current(&gl_surface)
+        .map_err(|e| format!("Failed to make context current: {}", e))?;
+
+    // Create glow context
+    let gl = unsafe {
+        let gl = glow::Context::from_loader_function(|s| {
+            gl_display.get_proc_address(&std::ffi::CString::new(s).unwrap()) as *const _
+        });
+        if msaa > 0 {
+            gl.enable(glow::MULTISAMPLE);
+        }
+        Arc::new(gl)
+    };
+
+    gl_surface
+        .set_swap_interval(&gl_context, SwapInterval::Wait(NonZeroU32::new(1).unwrap()))
+        .map_err(|e| format!("Failed to set swap interval: {}", e))?;
+
+    Ok(OpenGl {
+        window,
+        gl,
+        context: gl_context,
+        surface: gl_surface,
+        samples: gl_config.num_samples(),
+    })
+}
+
+/// Print the OpenGL driver's identification strings and the chosen config's
+/// sample count, for rendering bug reports. The window is never shown.
+fn print_gl_info(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let event_loop = EventLoop::new();
+    let OpenGl { gl, samples, .. } = create_gl(&event_loop, config, false)?;
+
+    let parameters = [
+        ("Vendor", glow::VENDOR),
+        ("Renderer", glow::RENDERER),
+        ("Version", glow::VERSION),
+        ("GLSL version", glow::SHADING_LANGUAGE_VERSION),
+    ];
+    for (name, parameter) in parameters {
+        println!("{}: {}", console::style(name).cyan(), unsafe { gl.get_parameter_string(parameter) });
+    }
+    println!("{}: {}", console::style("MSAA samples").cyan(), samples);
+
+    Ok(())
+}
+
+/// Print the memory a run with `config.n_bodies` bodies needs, from the sizes
+/// of the body and tree types alone
+fn print_memory_estimate(config: &Config) {
+    let n = config.n_bodies;
+    let bodies = n * std::mem::size_of::<Body>();
+    let tree = QuadTree::estimate_memory(n);
+    // Rendering builds its own tree each frame, which only coexists with the
+    // simulation's when --incremental-tree or --tree-rebuild-interval keeps
+    // that between steps
+    let keeps_tree = config.incremental_tree || config.tree_rebuild_interval > 1;
+    let trees = if keeps_tree && !config.no_graphics { 2 } else { 1 };
+    let megabytes = |bytes: usize| format!("{:.1} MB", bytes as f64 / 1e6);
+
+    println!("{}: {}", console::style("Bodies").cyan(), console::style(megabytes(bodies)).yellow());
+    println!("{}: {} each, {} at a time",
+        console::style("Barnes-Hut tree").cyan(),
+        console::style(megabytes(tree)).yellow(),
+        trees
+    );
+    println!("{}: {}", console::style("Total").cyan(), console::style(megabytes(bodies + trees * tree)).bold());
+}
+
+/// Print the force error and cost of the Barnes-Hut tree against direct
+/// summation, for systems of each of `SWEEP_BODIES` bodies generated as the
+/// run would be and each of `SWEEP_TREE_RATIOS`.
+///
+/// Forces are evaluated for an evenly spaced sample of bodies; the direct
+/// forces come from the same tree with a ratio of 0, which opens every node.
+/// Errors are relative to each body's direct force and times are per force
+/// evaluation, excluding the tree build.
+fn print_accuracy_sweep(mut config: Config) -> Result<(), NBodyError> {
+    println!("n,tree_ratio,mean_rel_error,max_rel_error,tree_us,direct_us");
+    for n in SWEEP_BODIES {
+        config.n_bodies = n;
+        let simulation = Simulation::new(initial_bodies(&config)?, config.timestep, config.g, config.softening, 0.0);
+        let tree = simulation.get_tree();
+        let sample = bodies_sample(simulation.bodies(), SWEEP_SAMPLE);
+        let params = |threshold| ForceParams {
+            opening: config.opening.into(),
+            ..ForceParams::new(config.g, config.softening, threshold)
+        };
+
+        let forces = |params: &ForceParams| {
+            let started = Instant::now();
+            let forces: Vec<[Scalar; 2]> = sample.iter().map(|body| tree.calculate_force_with(body, params)).collect();
+            (forces, started.elapsed().as_secs_f64() * 1e6 / sample.len() as f64)
+        };
+        let (direct, direct_us) = forces(&params(0.0));
+
+        for tree_ratio in SWEEP_TREE_RATIOS {
+            let (approximate, tree_us) = forces(&params(tree_ratio));
+            let errors: Vec<Scalar> = approximate
+                .iter()
+                .zip(&direct)
+                .map(|(a, d)| {
+                    let magnitude = d[0].hypot(d[1]);
+                    (a[0] - d[0]).hypot(a[1] - d[1]) / magnitude.max(Scalar::MIN_POSITIVE)
+                })
+                .collect();
+            let mean = errors.iter().sum::<Scalar>() / errors.len() as Scalar;
+            let max = errors.iter().cloned().fold(0.0, Scalar::max);
+            println!("{},{},{:.3e},{:.3e},{:.2},{:.2}", n, tree_ratio, mean, max, tree_us, direct_us);
+        }
+    }
+    Ok(())
+}
+
+/// At most `max` bodies, evenly spaced through `bodies`
+fn bodies_sample(bodies: &[Body], max: usize) -> Vec<&Body> {
+    let stride = bodies.len().div_ceil(max.max(1)).max(1);
+    bodies.iter().step_by(stride).collect()
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let config = Config::parse();
+    let mut config = Config::parse();
+    // Keep stdout clean for the snapshots
+    if config.output_file.as_deref().is_some_and(fileio::is_stdio) {
+        config.quiet = true;
+    }
+
+    // RUST_LOG overrides the default level, e.g. RUST_LOG=debug
+    let default_level = if config.quiet { "warn" } else { "info" };
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(default_level))
+        .format_timestamp(None)
+        .init();
+
+    if config.estimate_memory {
+        print_memory_estimate(&config);
+        return Ok(());
+    }
+
+    if config.gl_info {
+        return print_gl_info(&config);
+    }
+
+    if config.accuracy_sweep {
+        return Ok(print_accuracy_sweep(config)?);
+    }
+
     run_simulation(config)
 }
\ No newline at end of file
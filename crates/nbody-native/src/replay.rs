@@ -0,0 +1,122 @@
+//! Playback of a directory of saved snapshots, in place of stepping the
+//! simulation.
+
+use std::path::{Path, PathBuf};
+use nbody_core::{Body, NBodyError};
+use crate::fileio;
+
+/// An ordered list of snapshot files and the one currently shown
+pub struct Replay {
+    frames: Vec<PathBuf>,
+    index: usize,
+}
+
+impl Replay {
+    /// List the snapshot files in `dir`, ordered by the number in their
+    /// names (so `frame_2` comes before `frame_10` and gaps are fine), then
+    /// by name for files without one. Hidden files are ignored.
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self, NBodyError> {
+        let entries = std::fs::read_dir(dir)
+            .map_err(|e| NBodyError::io("Failed to read replay directory", e))?;
+
+        let mut frames = Vec::new();
+        for entry in entries {
+            let path = entry
+                .map_err(|e| NBodyError::io("Failed to read replay directory", e))?
+                .path();
+            let hidden = path.file_name().and_then(|n| n.to_str()).is_none_or(|n| n.starts_with('.'));
+            if path.is_file() && !hidden {
+                frames.push(path);
+            }
+        }
+
+        if frames.is_empty() {
+            return Err(NBodyError::Parse("No snapshot files in replay directory".to_string()));
+        }
+        frames.sort_by_cached_key(|path| (frame_number(path), path.clone()));
+
+        Ok(Replay { frames, index: 0 })
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Position of the current frame in the sequence
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Path of the current frame
+    pub fn path(&self) -> &Path {
+        &self.frames[self.index]
+    }
+
+    /// Move `delta` frames forward (or back), returning false and staying put
+    /// at either end of the sequence
+    pub fn seek(&mut self, delta: isize) -> bool {
+        match self.index.checked_add_signed(delta).filter(|&i| i < self.frames.len()) {
+            Some(index) => {
+                self.index = index;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Load the bodies of the current frame: XYZ files by their extension,
+    /// anything else as a `--output` state file
+    pub fn load(&self) -> Result<Vec<Body>, NBodyError> {
+        let path = self.path();
+        if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("xyz")) {
+            fileio::read_xyz(path)
+        } else {
+            fileio::read_bodies(path)
+        }
+    }
+}
+
+/// The last run of digits in a file's stem, e.g. 120 for `snapshot_00000120.xyz`
+fn frame_number(path: &Path) -> Option<u64> {
+    let stem = path.file_stem()?.to_str()?;
+    let end = stem.rfind(|c: char| c.is_ascii_digit())? + 1;
+    let start = stem[..end].rfind(|c: char| !c.is_ascii_digit()).map_or(0, |i| i + 1);
+    stem[start..end].parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_frames_in_numeric_order() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempdir()?;
+        let bodies = vec![Body::new(1.0, 0.5, 0.0, 0.0, 0.0)];
+        for step in [10, 2, 300] {
+            fileio::write_xyz(dir.path().join(format!("frame_{}.xyz", step)), &bodies, "")?;
+        }
+        fs::write(dir.path().join(".hidden"), "")?;
+
+        let mut replay = Replay::open(dir.path())?;
+        assert_eq!(replay.len(), 3);
+        let mut names = Vec::new();
+        loop {
+            names.push(replay.path().file_name().unwrap().to_string_lossy().into_owned());
+            assert_eq!(replay.load()?[0].position, [0.5, 0.0]);
+            if !replay.seek(1) {
+                break;
+            }
+        }
+        assert_eq!(names, ["frame_2.xyz", "frame_10.xyz", "frame_300.xyz"]);
+
+        assert!(replay.seek(-2));
+        assert_eq!(replay.index(), 0);
+        assert!(!replay.seek(-1));
+        assert_eq!(frame_number(Path::new("snapshot_00000120.xyz")), Some(120));
+        assert_eq!(frame_number(Path::new("state.dat")), None);
+
+        Ok(())
+    }
+}
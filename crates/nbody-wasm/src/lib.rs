@@ -1,23 +1,40 @@
+// Conversions and constants written for f64 become no-op casts or are rounded
+// when Scalar is f32
+#![cfg_attr(feature = "single-precision", allow(clippy::unnecessary_cast, clippy::excessive_precision))]
+
 use wasm_bindgen::prelude::*;
+#[cfg(feature = "webgl")]
 use web_sys::{WebGl2RenderingContext, HtmlCanvasElement};
-use nbody_core::{Simulation, Body, Renderer};
+use nbody_core::{Simulation, Body, Scalar};
+#[cfg(feature = "webgl")]
+use nbody_core::Renderer;
+#[cfg(feature = "webgl")]
 use std::sync::Arc;
-use std::f64::consts::PI;
 use rand::Rng;
+#[cfg(feature = "webgl")]
 use glow::Context as GlowContext;
 
+const PI: Scalar = std::f64::consts::PI as Scalar;
+
+/// Parameters of a simulation and its rendering.
+///
+/// `reconfigure` applies `g`, `timestep`, `softening`, `tree_ratio`,
+/// `point_size`, `fixed_scale` and `wireframe` to a running simulation;
+/// changing `n_bodies`, `mass`, `spin` or `mzero` generates new bodies.
 #[wasm_bindgen]
+#[derive(Clone)]
 pub struct SimConfig {
     pub n_bodies: usize,
-    pub mass: f64,
-    pub g: f64,
-    pub timestep: f64,
-    pub softening: f64,
-    pub spin: f64,
-    pub mzero: f64,
-    pub tree_ratio: f64,
+    pub mass: Scalar,
+    pub g: Scalar,
+    pub timestep: Scalar,
+    pub softening: Scalar,
+    pub spin: Scalar,
+    pub mzero: Scalar,
+    pub tree_ratio: Scalar,
     pub point_size: f32,
     pub fixed_scale: bool,
+    pub wireframe: bool,
 }
 
 #[wasm_bindgen]
@@ -35,16 +52,26 @@ impl SimConfig {
             tree_ratio: 3.0,
             point_size: 2.0,
             fixed_scale: false,
+            wireframe: true,
         }
     }
 }
 
+impl Default for SimConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "webgl")]
 #[wasm_bindgen]
 pub struct NBodySimulation {
     simulation: Simulation,
     renderer: Renderer,
+    config: SimConfig,
 }
 
+#[cfg(feature = "webgl")]
 #[wasm_bindgen]
 impl NBodySimulation {
     #[wasm_bindgen(constructor)]
@@ -62,27 +89,19 @@ impl NBodySimulation {
             .dyn_into::<WebGl2RenderingContext>()?;
 
         // Create glow context
-        let gl = unsafe {
-            let gl = GlowContext::from_webgl2_context(gl_context);
-            Arc::new(gl)
-        };
+        // WebGL contexts are single threaded, so the Arc is never shared across threads
+        #[allow(clippy::arc_with_non_send_sync)]
+        let gl = Arc::new(GlowContext::from_webgl2_context(gl_context));
 
         // Initialize renderer
-        let renderer = Renderer::new(gl, config.point_size, config.fixed_scale)
-            .map_err(|e| JsValue::from_str(&e))?;
-
-        // Initialize simulation with random bodies
-        let simulation = Simulation::new(
-            create_random_bodies(config),
-            config.timestep,
-            config.g,
-            config.softening,
-            config.tree_ratio,
-        );
+        let mut renderer = Renderer::new(gl, config.point_size, config.fixed_scale)
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+        renderer.set_wireframe(config.wireframe);
 
         Ok(NBodySimulation {
-            simulation,
+            simulation: create_simulation(config),
             renderer,
+            config: config.clone(),
         })
     }
 
@@ -90,6 +109,24 @@ impl NBodySimulation {
         self.simulation.step();
     }
 
+    /// Apply a new configuration to the running simulation and renderer,
+    /// keeping the bodies and the WebGL context. Bodies are only generated
+    /// again if `n_bodies`, `mass`, `spin` or `mzero` changed. Returns
+    /// whether they were.
+    pub fn reconfigure(&mut self, config: &SimConfig) -> bool {
+        let regenerated = reconfigure_simulation(&mut self.simulation, &self.config, config);
+        self.renderer.set_point_size(config.point_size);
+        self.renderer.set_fixed_scale(config.fixed_scale);
+        self.renderer.set_wireframe(config.wireframe);
+        self.config = config.clone();
+        regenerated
+    }
+
+    /// Set the width in pixels of the tree wireframe lines
+    pub fn set_wireframe_width(&mut self, width: f32) {
+        self.renderer.set_wireframe_width(width);
+    }
+
     pub fn render(&self) {
         let bodies = self.simulation.bodies();
         let tree = self.simulation.get_tree();
@@ -97,6 +134,99 @@ impl NBodySimulation {
     }
 }
 
+/// Summary of a simulation's progress, returned by `WasmSimulation::stats`
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy)]
+pub struct SimStats {
+    pub step: usize,
+    pub time: Scalar,
+    pub n_bodies: usize,
+    pub kinetic_energy: Scalar,
+}
+
+/// A simulation without a canvas or renderer, for running the physics in a
+/// Web Worker while the main thread draws the positions it posts back
+#[wasm_bindgen]
+pub struct WasmSimulation {
+    simulation: Simulation,
+    config: SimConfig,
+}
+
+#[wasm_bindgen]
+impl WasmSimulation {
+    #[wasm_bindgen(constructor)]
+    pub fn new(config: &SimConfig) -> WasmSimulation {
+        console_error_panic_hook::set_once();
+
+        WasmSimulation {
+            simulation: create_simulation(config),
+            config: config.clone(),
+        }
+    }
+
+    pub fn step(&mut self) {
+        self.simulation.step();
+    }
+
+    /// Apply a new configuration to the running simulation, as
+    /// `NBodySimulation::reconfigure` does. The rendering fields are ignored.
+    pub fn reconfigure(&mut self, config: &SimConfig) -> bool {
+        let regenerated = reconfigure_simulation(&mut self.simulation, &self.config, config);
+        self.config = config.clone();
+        regenerated
+    }
+
+    /// Interleaved x, y positions of every body, as a new `Float32Array`
+    /// whose buffer can be transferred to the main thread
+    pub fn body_positions(&self) -> Vec<f32> {
+        self.simulation
+            .bodies()
+            .iter()
+            .flat_map(|body| [body.position[0] as f32, body.position[1] as f32])
+            .collect()
+    }
+
+    pub fn stats(&self) -> SimStats {
+        SimStats {
+            step: self.simulation.step_count(),
+            time: self.simulation.time(),
+            n_bodies: self.simulation.bodies().len(),
+            kinetic_energy: self.simulation.kinetic_energy(),
+        }
+    }
+}
+
+/// Simulation of a random disk of bodies set up from `config`
+fn create_simulation(config: &SimConfig) -> Simulation {
+    Simulation::new(
+        create_random_bodies(config),
+        config.timestep,
+        config.g,
+        config.softening,
+        config.tree_ratio,
+    )
+}
+
+/// Bring a simulation set up from `current` in line with `config`: new bodies
+/// if the fields they are generated from differ, otherwise only the new
+/// parameters. Returns whether the bodies were generated again.
+fn reconfigure_simulation(simulation: &mut Simulation, current: &SimConfig, config: &SimConfig) -> bool {
+    let same_bodies = config.n_bodies == current.n_bodies
+        && config.mass == current.mass
+        && config.spin == current.spin
+        && config.mzero == current.mzero;
+    if !same_bodies {
+        *simulation = create_simulation(config);
+        return true;
+    }
+
+    simulation.set_timestep(config.timestep);
+    simulation.set_g(config.g);
+    simulation.set_softening(config.softening);
+    simulation.set_tree_ratio(config.tree_ratio);
+    false
+}
+
 fn create_random_bodies(config: &SimConfig) -> Vec<Body> {
     let mut rng = rand::thread_rng();
     let mut bodies = Vec::with_capacity(config.n_bodies);
@@ -110,14 +240,14 @@ fn create_random_bodies(config: &SimConfig) -> Vec<Body> {
 
     // Create remaining bodies
     for _ in 1..config.n_bodies {
-        let r = rng.gen::<f64>() * 2.0 - 1.0;
-        let theta = 2.0 * PI * rng.gen::<f64>();
+        let r = rng.gen::<Scalar>() * 2.0 - 1.0;
+        let theta = 2.0 * PI * rng.gen::<Scalar>();
 
         let x = r * theta.cos();
         let y = r * theta.sin();
 
         // Add some initial velocity for orbit
-        let spin_factor = config.spin * (1.0 + 0.1 * rng.gen::<f64>()) / (1.0 + r.abs());
+        let spin_factor = config.spin * (1.0 + 0.1 * rng.gen::<Scalar>()) / (1.0 + r.abs());
         let vx = -y * spin_factor;
         let vy = x * spin_factor;
 